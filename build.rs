@@ -1,8 +1,31 @@
+use std::{env, path::PathBuf};
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::configure()
-        .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
-        .compile_protos(
-            &["proto/user.proto", "proto/order.proto"],
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+
+    // prost::Name lets the validator find the descriptor of a message type
+    let mut config = prost_build::Config::new();
+    config.enable_type_names();
+
+    tonic_prost_build::configure()
+        // Services only, every request is checked against its (validate.rules) as it is decoded
+        .build_client(false)
+        .codec_path("crate::grpc::codec::ValidatingCodec")
+        .type_attribute(".user", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .type_attribute(".order", "#[derive(serde::Serialize, serde::Deserialize)]")
+        // Schemas of the OpenAPI document
+        .type_attribute(".user", "#[derive(utoipa::ToSchema)]")
+        .type_attribute(".order", "#[derive(utoipa::ToSchema)]")
+        // Served by the gRPC reflection service
+        .file_descriptor_set_path(out_dir.join("api_descriptor.bin"))
+        .compile_with_config(
+            config,
+            &[
+                "proto/user.proto",
+                "proto/order.proto",
+                // (auth.rule) options of transcoded routes
+                "proto/auth/auth.proto",
+            ],
             &["proto"],
         )?;
     Ok(())
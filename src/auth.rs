@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// Who a request acts for, decoded from a JWT or mapped from a verified client certificate
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: usize,
+
+    pub roles: Vec<String>,
+    pub policies: Vec<String>,
+}
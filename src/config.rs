@@ -3,11 +3,97 @@ use std::env;
 #[derive(Clone)]
 pub struct Config {
     pub host: String,
+    pub http_enabled: bool,
+    pub grpc_enabled: bool,
+    /// Serve gRPC and HTTP together on http_port, grpc_port is unused
+    pub single_port: bool,
     pub http_port: u16,
     pub grpc_port: u16,
     pub jwt_secret: String,
+    #[allow(dead_code)] // Read by token issuers, none exist yet
     pub jwt_ttl: u64,
     pub cors_origins: Vec<String>,
+    pub import_max_batch: usize,
+    /// Largest accepted bulk import upload
+    pub import_max_bytes: usize,
+    /// Bulk imports with more rows run as background jobs
+    pub import_inline_rows: usize,
+    /// Serve Swagger UI at /docs/
+    pub docs_enabled: bool,
+    /// Version of `/api` requests that name none
+    pub api_default_version: u32,
+    /// `v1:2026-09-01[:2027-03-01]` entries: version, deprecation date, optional sunset date
+    pub api_deprecations: Vec<String>,
+    /// Sent as `Link: <...>; rel="deprecation"` on responses of deprecated versions
+    pub api_deprecation_link: String,
+    /// `json` (default) or `logfmt`
+    pub log_format: String,
+    /// Proxies whose X-Forwarded-For is trusted for the client IP, addresses or CIDR ranges
+    pub trusted_proxies: Vec<String>,
+    /// Where spans go: `none` (default), `otlp` or `stdout`
+    pub otel_traces_exporter: String,
+    /// OTLP/gRPC collector receiving spans when otel_traces_exporter is `otlp`
+    pub otel_exporter_otlp_endpoint: String,
+    pub otel_service_name: String,
+    /// Listener serving `/metrics` apart from the API ports, e.g. `127.0.0.1:9090`,
+    /// empty serves no metrics
+    pub metrics_addr: String,
+    /// Seconds between service health checks
+    pub health_check_interval: u64,
+    /// Milliseconds each /health check may take before it counts as failed
+    pub health_check_timeout_ms: u64,
+    /// File system whose free space is checked, e.g. where imports are buffered
+    pub health_disk_path: String,
+    /// Readiness fails below this many free megabytes, 0 disables the check
+    pub health_disk_min_free_mb: u64,
+    /// Dependencies that must accept TCP connections, as `name=host:port`
+    pub health_downstreams: Vec<(String, String)>,
+    /// Seconds between a shutdown signal and closing the listeners, while readiness fails
+    pub shutdown_delay: u64,
+    /// Seconds in-flight requests, streams and background jobs get to finish
+    pub shutdown_timeout: u64,
+    pub rate_limit_enabled: bool,
+    /// `token_bucket` (default) or `sliding_window`
+    pub rate_limit_algorithm: String,
+    /// `memory` (default, per replica) or a `redis://` URL shared by every replica
+    pub rate_limit_store: String,
+    /// Quota of callers without a role quota, e.g. `100/1m`
+    pub rate_limit_default: String,
+    /// `role=quota` entries replacing the default for callers with that role
+    pub rate_limit_roles: Vec<(String, String)>,
+    /// `path prefix=quota` entries, counted on top of the caller's quota. Prefixes match
+    /// whole path segments.
+    pub rate_limit_routes: Vec<(String, String)>,
+    /// `name=key` entries identifying callers that send X-Api-Key
+    pub rate_limit_api_keys: Vec<(String, String)>,
+    /// Seconds service results stay cached, 0 disables the caches
+    pub cache_ttl: u64,
+    /// Entries per service cache, the least recently used are evicted first
+    pub cache_capacity: usize,
+    /// `max-age` of cacheable HTTP responses, 0 makes clients revalidate every time
+    pub http_cache_max_age: u64,
+    /// PEM certificate chain of both listeners, empty serves plaintext
+    pub tls_cert: String,
+    /// PEM private key of `tls_cert`
+    pub tls_key: String,
+    /// PEM CA bundle client certificates on the gRPC listener are verified against, empty for no mTLS
+    pub tls_client_ca: String,
+    /// `required` or `optional`, whether gRPC clients must present a certificate
+    pub tls_client_auth: String,
+    /// `subject=role` entries granting roles to client certificates, one role per entry
+    pub tls_client_roles: Vec<(String, String)>,
+    /// Seconds between checks of the certificate files for changes, 0 never reloads them
+    pub tls_reload_interval: u64,
+}
+
+/// `name=value` entries separated by commas
+fn pairs(var: &str) -> Vec<(String, String)> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|d| d.trim().split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect()
 }
 
 impl Config {
@@ -16,6 +102,21 @@ impl Config {
 
         let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".into());
 
+        let http_enabled = env::var("HTTP_ENABLED")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(true);
+
+        let grpc_enabled = env::var("GRPC_ENABLED")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(true);
+
+        let single_port = env::var("SINGLE_PORT")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(false);
+
         let http_port = env::var("HTTP_PORT")
             .ok()
             .and_then(|p| p.parse::<u16>().ok())
@@ -41,13 +142,184 @@ impl Config {
             .filter(|o| !o.is_empty())
             .collect::<Vec<_>>();
 
+        let import_max_batch = env::var("IMPORT_MAX_BATCH")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(500);
+
+        let import_max_bytes = env::var("IMPORT_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(10 * 1024 * 1024);
+
+        let import_inline_rows = env::var("IMPORT_INLINE_ROWS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(1000);
+
+        let docs_enabled = env::var("DOCS_ENABLED")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(false);
+
+        let api_default_version = env::var("API_DEFAULT_VERSION")
+            .ok()
+            .and_then(|v| v.trim_start_matches('v').parse::<u32>().ok())
+            .unwrap_or(1);
+
+        let api_deprecations = env::var("API_DEPRECATIONS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty())
+            .collect::<Vec<_>>();
+
+        let api_deprecation_link = env::var("API_DEPRECATION_LINK")
+            .unwrap_or_else(|_| "/api/openapi.json".into());
+
+        let log_format = env::var("LOG_FORMAT")
+            .map(|v| v.to_ascii_lowercase())
+            .unwrap_or_else(|_| "json".into());
+
+        let trusted_proxies = env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<_>>();
+
+        let otel_traces_exporter = env::var("OTEL_TRACES_EXPORTER")
+            .map(|v| v.to_ascii_lowercase())
+            .unwrap_or_else(|_| "none".into());
+
+        let otel_exporter_otlp_endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .unwrap_or_else(|_| "http://localhost:4317".into());
+
+        let otel_service_name = env::var("OTEL_SERVICE_NAME")
+            .unwrap_or_else(|_| env!("CARGO_PKG_NAME").into());
+
+        let metrics_addr = env::var("METRICS_ADDR").unwrap_or_else(|_| "127.0.0.1:9090".into());
+
+        let health_check_interval = env::var("HEALTH_CHECK_INTERVAL")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(5);
+
+        let health_check_timeout_ms = env::var("HEALTH_CHECK_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(2000);
+
+        let health_disk_path = env::var("HEALTH_DISK_PATH").unwrap_or_else(|_| ".".into());
+
+        let health_disk_min_free_mb = env::var("HEALTH_DISK_MIN_FREE_MB")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(100);
+
+        let health_downstreams = pairs("HEALTH_DOWNSTREAMS");
+
+        let shutdown_delay = env::var("SHUTDOWN_DELAY")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0);
+
+        let shutdown_timeout = env::var("SHUTDOWN_TIMEOUT")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(30);
+
+        let rate_limit_enabled = env::var("RATE_LIMIT_ENABLED")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(false);
+
+        let rate_limit_algorithm = env::var("RATE_LIMIT_ALGORITHM")
+            .map(|v| v.to_ascii_lowercase())
+            .unwrap_or_else(|_| "token_bucket".into());
+
+        let rate_limit_store = env::var("RATE_LIMIT_STORE").unwrap_or_else(|_| "memory".into());
+
+        let rate_limit_default = env::var("RATE_LIMIT_DEFAULT").unwrap_or_else(|_| "100/1m".into());
+
+        let rate_limit_roles = pairs("RATE_LIMIT_ROLES");
+
+        let rate_limit_routes = pairs("RATE_LIMIT_ROUTES");
+
+        let rate_limit_api_keys = pairs("RATE_LIMIT_API_KEYS");
+
+        let cache_ttl = env::var("CACHE_TTL")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(30);
+
+        let cache_capacity = env::var("CACHE_CAPACITY")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(1000);
+
+        let http_cache_max_age = env::var("HTTP_CACHE_MAX_AGE")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0);
+
+        let tls_cert = env::var("TLS_CERT").unwrap_or_default();
+        let tls_key = env::var("TLS_KEY").unwrap_or_default();
+        let tls_client_ca = env::var("TLS_CLIENT_CA").unwrap_or_default();
+        let tls_client_auth = env::var("TLS_CLIENT_AUTH").unwrap_or_else(|_| "required".into());
+        let tls_client_roles = pairs("TLS_CLIENT_ROLES");
+
+        let tls_reload_interval = env::var("TLS_RELOAD_INTERVAL")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(30);
+
         Self {
             host,
+            http_enabled,
+            grpc_enabled,
+            single_port,
             http_port,
             grpc_port,
             jwt_secret,
             jwt_ttl,
             cors_origins,
+            import_max_batch,
+            import_max_bytes,
+            import_inline_rows,
+            docs_enabled,
+            api_default_version,
+            api_deprecations,
+            api_deprecation_link,
+            log_format,
+            trusted_proxies,
+            otel_traces_exporter,
+            otel_exporter_otlp_endpoint,
+            otel_service_name,
+            metrics_addr,
+            health_check_interval,
+            health_check_timeout_ms,
+            health_disk_path,
+            health_disk_min_free_mb,
+            health_downstreams,
+            shutdown_delay,
+            shutdown_timeout,
+            rate_limit_enabled,
+            rate_limit_algorithm,
+            rate_limit_store,
+            rate_limit_default,
+            rate_limit_roles,
+            rate_limit_routes,
+            rate_limit_api_keys,
+            cache_ttl,
+            cache_capacity,
+            http_cache_max_age,
+            tls_cert,
+            tls_key,
+            tls_client_ca,
+            tls_client_auth,
+            tls_client_roles,
+            tls_reload_interval,
         }
     }
 }
@@ -0,0 +1,221 @@
+use crate::services::{AppError, FieldViolation};
+use actix_web::http::StatusCode;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tonic_types::{ErrorDetails, StatusExt};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// ErrorInfo domain of every error raised by this server
+pub const ERROR_DOMAIN: &str = env!("CARGO_PKG_NAME");
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ProblemField {
+    pub field: String,
+    pub description: String,
+}
+
+impl From<&FieldViolation> for ProblemField {
+    fn from(v: &FieldViolation) -> Self {
+        Self {
+            field: v.field.clone(),
+            description: v.description.clone(),
+        }
+    }
+}
+
+/// RFC 7807 problem details, the body of every HTTP error response.
+/// `instance` and `request_id` are filled in by the ProblemDetails middleware.
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// AppError reason, e.g. VALIDATION_FAILED
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ProblemField>,
+    /// Sent as the Retry-After header
+    #[serde(skip)]
+    pub retry_after: Option<Duration>,
+}
+
+impl Problem {
+    /// Problem with no more semantics than its status code (`type: about:blank`)
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: None,
+            instance: None,
+            code: None,
+            request_id: None,
+            errors: Vec::new(),
+            retry_after: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    fn with_code(mut self, code: &str) -> Self {
+        self.problem_type = format!("/problems/{}", code.to_lowercase().replace('_', "-"));
+        self.code = Some(code.to_string());
+        self
+    }
+
+    pub fn from_error(error: &AppError) -> Self {
+        let mut problem = Self::new(error.status_code())
+            .with_detail(error.public_message())
+            .with_code(error.reason());
+        match error {
+            AppError::Validation(violations) => {
+                problem.errors = violations.iter().map(ProblemField::from).collect();
+            }
+            AppError::RateLimited { retry_after } => problem.retry_after = Some(*retry_after),
+            _ => {}
+        }
+        problem
+    }
+
+    /// Same problem as `from_error` would give, rebuilt from the rich details of a gRPC status
+    pub fn from_grpc(status: StatusCode, grpc: &tonic::Status) -> Self {
+        let mut problem = Self::new(status).with_detail(grpc.message());
+        let details = grpc.get_error_details();
+        if let Some(info) = details.error_info()
+            && info.domain == ERROR_DOMAIN
+        {
+            problem = problem.with_code(&info.reason);
+        }
+        if let Some(bad_request) = details.bad_request() {
+            problem.errors = bad_request
+                .field_violations
+                .iter()
+                .map(|v| ProblemField {
+                    field: v.field.clone(),
+                    description: v.description.clone(),
+                })
+                .collect();
+        }
+        problem.retry_after = details.retry_info().and_then(|info| info.retry_delay);
+        problem
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut res = HttpResponse::build(status);
+        // Whole seconds, rounded up so a client never retries too early
+        if let Some(retry_after) = self.retry_after {
+            res.insert_header((
+                RETRY_AFTER,
+                retry_after.as_secs_f64().ceil().max(1.0) as u64,
+            ));
+        }
+        res.content_type(PROBLEM_JSON)
+            .body(serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+fn log_internal(error: &AppError) {
+    if let AppError::Internal(message) = error {
+        log::error!("internal error: {message}");
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound { .. } => StatusCode::NOT_FOUND,
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        log_internal(self);
+        Problem::from_error(self).to_http()
+    }
+}
+
+/// google.rpc error details for the `grpc-status-details-bin` trailer
+fn error_details(error: &AppError) -> ErrorDetails {
+    let mut details = ErrorDetails::with_error_info(error.reason(), ERROR_DOMAIN, HashMap::new());
+    match error {
+        AppError::Validation(violations) => {
+            for v in violations {
+                details.add_bad_request_violation(v.field.clone(), v.description.clone());
+            }
+        }
+        AppError::RateLimited { retry_after } => {
+            details.set_retry_info(Some(*retry_after));
+        }
+        AppError::NotFound { resource, id } => {
+            details.set_resource_info(resource.to_string(), id.clone(), "", error.public_message());
+        }
+        _ => {}
+    }
+    details
+}
+
+impl From<AppError> for tonic::Status {
+    fn from(error: AppError) -> Self {
+        use tonic::Code;
+        log_internal(&error);
+        let code = match error {
+            AppError::NotFound { .. } => Code::NotFound,
+            AppError::Validation(_) => Code::InvalidArgument,
+            AppError::Conflict(_) => Code::AlreadyExists,
+            AppError::Unauthorized(_) => Code::Unauthenticated,
+            AppError::Forbidden(_) => Code::PermissionDenied,
+            AppError::RateLimited { .. } => Code::ResourceExhausted,
+            AppError::Unavailable(_) => Code::Unavailable,
+            AppError::Internal(_) => Code::Internal,
+        };
+        tonic::Status::with_error_details(code, error.public_message(), error_details(&error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_grpc_keeps_code_violations_and_retry_info() {
+        let status = tonic::Status::from(AppError::RateLimited {
+            retry_after: Duration::from_millis(1500),
+        });
+        let problem = Problem::from_grpc(StatusCode::TOO_MANY_REQUESTS, &status);
+        assert_eq!(problem.code.as_deref(), Some("RATE_LIMITED"));
+        assert_eq!(problem.retry_after, Some(Duration::from_millis(1500)));
+        let res = problem.to_http();
+        assert_eq!(res.headers().get(RETRY_AFTER).unwrap(), "2");
+
+        let status = tonic::Status::from(AppError::Validation(vec![FieldViolation {
+            field: "quantity".to_string(),
+            description: "must be at least 1".to_string(),
+        }]));
+        let problem = Problem::from_grpc(StatusCode::BAD_REQUEST, &status);
+        assert_eq!(problem.errors.len(), 1);
+        assert_eq!(problem.errors[0].field, "quantity");
+        assert!(problem.retry_after.is_none());
+    }
+}
@@ -0,0 +1,367 @@
+use crate::services::{AppError, FieldViolation, Order, OrderFilter};
+use actix_web::HttpResponse;
+use actix_web::http::header::{
+    CONTENT_DISPOSITION, ContentDisposition, DispositionParam, DispositionType,
+};
+use actix_web::web::Bytes;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use serde::Deserialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+#[derive(Clone, Copy)]
+pub enum ExportFormat {
+    Ndjson,
+    Csv,
+}
+
+impl ExportFormat {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ndjson" => Some(Self::Ndjson),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    fn media_type(self) -> &'static str {
+        match self {
+            Self::Ndjson => "application/x-ndjson",
+            Self::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Ndjson => "ndjson",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ExportColumn {
+    Id,
+    UserId,
+    Product,
+    Quantity,
+    CreatedAt,
+}
+
+impl ExportColumn {
+    const ALL: [Self; 5] = [
+        Self::Id,
+        Self::UserId,
+        Self::Product,
+        Self::Quantity,
+        Self::CreatedAt,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::UserId => "user_id",
+            Self::Product => "product",
+            Self::Quantity => "quantity",
+            Self::CreatedAt => "created_at",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    fn value(self, order: &Order) -> Field<'_> {
+        match self {
+            Self::Id => Field::Text(&order.id),
+            Self::UserId => Field::Text(&order.user_id),
+            Self::Product => Field::Text(&order.product),
+            Self::Quantity => Field::Number(i64::from(order.quantity)),
+            Self::CreatedAt => Field::Timestamp(order.created_at),
+        }
+    }
+}
+
+enum Field<'a> {
+    Text(&'a str),
+    Number(i64),
+    Timestamp(i64),
+}
+
+/// RFC 3339 in UTC, e.g. `2026-10-01T12:30:00.250Z`
+fn format_timestamp(millis: i64) -> String {
+    OffsetDateTime::from_unix_timestamp_nanos(i128::from(millis) * 1_000_000)
+        .ok()
+        .and_then(|t| t.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+fn parse_timestamp(field: &str, value: &str) -> Result<i64, FieldViolation> {
+    OffsetDateTime::parse(value, &Rfc3339)
+        .map(|t| (t.unix_timestamp_nanos() / 1_000_000) as i64)
+        .map_err(|_| FieldViolation::new(field, "must be an RFC 3339 timestamp"))
+}
+
+/// RFC 4180 quoting. Text starting like a formula is prefixed with `'`
+/// so spreadsheets don't evaluate it.
+fn csv_text(value: &str, out: &mut String) {
+    let formula = value.starts_with(['=', '+', '-', '@', '\t', '\r']);
+    if formula || value.contains([',', '"', '\n', '\r']) {
+        out.push('"');
+        if formula {
+            out.push('\'');
+        }
+        out.push_str(&value.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(value);
+    }
+}
+
+/// Query string of `GET /api/v1/orders/export`
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// `ndjson` (default) or `csv`
+    format: Option<String>,
+    /// Comma-separated column names, every column by default
+    columns: Option<String>,
+    /// Exact match
+    user_id: Option<String>,
+    /// Exact match
+    product: Option<String>,
+    /// Inclusive
+    min_quantity: Option<i32>,
+    /// Inclusive
+    max_quantity: Option<i32>,
+    /// Creation time, RFC 3339, inclusive
+    from: Option<String>,
+    /// Creation time, RFC 3339, exclusive
+    to: Option<String>,
+}
+
+/// Encodes an export as CSV or NDJSON, one chunk per page of orders
+pub struct OrderExportController {
+    format: ExportFormat,
+    columns: Vec<ExportColumn>,
+}
+
+impl OrderExportController {
+    /// Reports every invalid parameter at once
+    pub fn from_query(query: ExportQuery) -> Result<(Self, OrderFilter), AppError> {
+        let mut violations = Vec::new();
+
+        let format = match query.format.as_deref() {
+            None => ExportFormat::Ndjson,
+            Some(value) => ExportFormat::parse(value).unwrap_or_else(|| {
+                violations.push(FieldViolation::new("format", "must be one of ndjson, csv"));
+                ExportFormat::Ndjson
+            }),
+        };
+
+        let columns = match query.columns.as_deref() {
+            None => ExportColumn::ALL.to_vec(),
+            Some(list) => list
+                .split(',')
+                .map(str::trim)
+                .filter_map(|name| {
+                    let column = ExportColumn::parse(name);
+                    if column.is_none() {
+                        violations.push(FieldViolation::new(
+                            "columns",
+                            &format!("unknown column {name:?}"),
+                        ));
+                    }
+                    column
+                })
+                .collect(),
+        };
+        if columns.is_empty() && violations.is_empty() {
+            violations.push(FieldViolation::new("columns", "must not be empty"));
+        }
+
+        let mut timestamp = |field: &str, value: Option<String>| {
+            value.and_then(|v| {
+                parse_timestamp(field, &v)
+                    .map_err(|violation| violations.push(violation))
+                    .ok()
+            })
+        };
+        let filter = OrderFilter {
+            created_from: timestamp("from", query.from),
+            created_to: timestamp("to", query.to),
+            user_id: query.user_id,
+            product: query.product,
+            min_quantity: query.min_quantity,
+            max_quantity: query.max_quantity,
+        };
+
+        if violations.is_empty() {
+            Ok((Self { format, columns }, filter))
+        } else {
+            Err(AppError::Validation(violations))
+        }
+    }
+
+    fn header(&self) -> Option<Bytes> {
+        match self.format {
+            ExportFormat::Ndjson => None,
+            ExportFormat::Csv => {
+                let names: Vec<&str> = self.columns.iter().map(|c| c.name()).collect();
+                Some(Bytes::from(names.join(",") + "\r\n"))
+            }
+        }
+    }
+
+    fn encode(&self, orders: &[Order]) -> Bytes {
+        let mut out = String::new();
+        for order in orders {
+            for (i, column) in self.columns.iter().enumerate() {
+                match self.format {
+                    ExportFormat::Ndjson => {
+                        out.push(if i == 0 { '{' } else { ',' });
+                        out.push_str(&format!("\"{}\":", column.name()));
+                        match column.value(order) {
+                            Field::Text(s) => out.push_str(&serde_json::Value::from(s).to_string()),
+                            Field::Number(n) => out.push_str(&n.to_string()),
+                            Field::Timestamp(ms) => out.push_str(
+                                &serde_json::Value::from(format_timestamp(ms)).to_string(),
+                            ),
+                        }
+                    }
+                    ExportFormat::Csv => {
+                        if i > 0 {
+                            out.push(',');
+                        }
+                        match column.value(order) {
+                            Field::Text(s) => csv_text(s, &mut out),
+                            Field::Number(n) => out.push_str(&n.to_string()),
+                            Field::Timestamp(ms) => out.push_str(&format_timestamp(ms)),
+                        }
+                    }
+                }
+            }
+            out.push_str(match self.format {
+                ExportFormat::Ndjson => "}\n",
+                ExportFormat::Csv => "\r\n",
+            });
+        }
+        Bytes::from(out)
+    }
+
+    /// Chunked response; pages are encoded as the client reads them, so memory use
+    /// does not grow with the size of the export
+    pub fn into_http(
+        self,
+        pages: BoxStream<'static, Result<Vec<Order>, AppError>>,
+    ) -> HttpResponse {
+        let format = self.format;
+        let header = stream::iter(self.header().map(Ok::<_, AppError>));
+        let rows = pages.map(move |page| page.map(|orders| self.encode(&orders)));
+        HttpResponse::Ok()
+            .content_type(format.media_type())
+            .insert_header((
+                CONTENT_DISPOSITION,
+                ContentDisposition {
+                    disposition: DispositionType::Attachment,
+                    parameters: vec![DispositionParam::Filename(format!(
+                        "orders.{}",
+                        format.extension()
+                    ))],
+                },
+            ))
+            .streaming(header.chain(rows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Query;
+
+    fn csv(value: &str) -> String {
+        let mut out = String::new();
+        csv_text(value, &mut out);
+        out
+    }
+
+    fn controller(query: &str) -> Result<(OrderExportController, OrderFilter), AppError> {
+        OrderExportController::from_query(Query::<ExportQuery>::from_query(query).unwrap().0)
+    }
+
+    fn order(product: &str) -> Order {
+        Order {
+            id: "7".to_string(),
+            user_id: "u1".to_string(),
+            product: product.to_string(),
+            quantity: 2,
+            created_at: 1_791_117_000_250,
+        }
+    }
+
+    #[test]
+    fn leaves_plain_text_unquoted() {
+        assert_eq!(csv("Book"), "Book");
+        assert_eq!(csv("a=b"), "a=b");
+        assert_eq!(csv(""), "");
+    }
+
+    #[test]
+    fn quotes_separators_and_doubles_quotes() {
+        assert_eq!(csv("a,b"), "\"a,b\"");
+        assert_eq!(csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn escapes_formulas() {
+        for value in ["=1+1", "+1", "-1", "@SUM(A1)", "\tx", "\rx"] {
+            assert_eq!(csv(value), format!("\"'{value}\""), "{value:?}");
+        }
+        assert_eq!(
+            csv("=HYPERLINK(\"x\",\"y\")"),
+            "\"'=HYPERLINK(\"\"x\"\",\"\"y\"\")\""
+        );
+    }
+
+    #[test]
+    fn encodes_selected_columns() {
+        let (csv, _) = controller("format=csv&columns=product,created_at").unwrap();
+        assert_eq!(csv.header().unwrap(), "product,created_at\r\n");
+        assert_eq!(
+            csv.encode(&[order("=cmd"), order("Pen")]),
+            "\"'=cmd\",2026-10-04T12:30:00.25Z\r\nPen,2026-10-04T12:30:00.25Z\r\n"
+        );
+
+        let (ndjson, _) = controller("columns=id,%20quantity").unwrap();
+        assert!(ndjson.header().is_none());
+        assert_eq!(
+            ndjson.encode(&[order("Pen")]),
+            "{\"id\":\"7\",\"quantity\":2}\n"
+        );
+    }
+
+    #[test]
+    fn reports_every_invalid_parameter() {
+        let Err(AppError::Validation(violations)) =
+            controller("format=xml&columns=id,price&from=yesterday")
+        else {
+            panic!("expected a validation error");
+        };
+        let fields: Vec<&str> = violations.iter().map(|v| v.field.as_str()).collect();
+        assert_eq!(fields, ["format", "columns", "from"]);
+
+        let Err(AppError::Validation(violations)) = controller("columns=") else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(violations[0].description, "unknown column \"\"");
+    }
+
+    #[test]
+    fn parses_time_range() {
+        let (_, filter) =
+            controller("from=2026-10-01T00:00:00Z&to=2026-10-01T00:00:01.5Z").unwrap();
+        assert_eq!(filter.created_from, Some(1_790_812_800_000));
+        assert_eq!(filter.created_to, Some(1_790_812_801_500));
+    }
+}
@@ -0,0 +1,71 @@
+use crate::services::{CheckResult, HealthReport};
+use actix_web::HttpResponse;
+use actix_web::http::header::{CacheControl, CacheDirective};
+use serde::Serialize;
+
+/// Media type of health reports (draft-inadarei-api-health-check)
+pub const HEALTH_JSON: &str = "application/health+json";
+
+/// Outcome of one check
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct HealthCheckBody {
+    pub name: String,
+    /// `pass` or `fail`
+    pub status: &'static str,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Aggregated health, 200 when `pass` and 503 when `fail`
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct HealthBody {
+    /// `pass` or `fail`
+    pub status: &'static str,
+    /// Set while the server drains its requests before stopping
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub shutting_down: bool,
+    pub checks: Vec<HealthCheckBody>,
+}
+
+fn status(passed: bool) -> &'static str {
+    if passed { "pass" } else { "fail" }
+}
+
+impl From<CheckResult> for HealthCheckBody {
+    fn from(check: CheckResult) -> Self {
+        Self {
+            name: check.name,
+            status: status(check.error.is_none()),
+            latency_ms: (check.latency.as_secs_f64() * 1_000_000.0).round() / 1000.0,
+            error: check.error,
+        }
+    }
+}
+
+pub struct HealthController(pub HealthReport);
+
+impl HealthController {
+    pub fn into_http(self) -> HttpResponse {
+        let healthy = self.0.healthy();
+        let body = HealthBody {
+            status: status(healthy),
+            shutting_down: self.0.shutting_down,
+            checks: self
+                .0
+                .checks
+                .into_iter()
+                .map(HealthCheckBody::from)
+                .collect(),
+        };
+        let mut res = if healthy {
+            HttpResponse::Ok()
+        } else {
+            HttpResponse::ServiceUnavailable()
+        };
+        // Probes must never see a cached answer
+        res.insert_header(CacheControl(vec![CacheDirective::NoStore]))
+            .content_type(HEALTH_JSON)
+            .json(body)
+    }
+}
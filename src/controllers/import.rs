@@ -0,0 +1,309 @@
+use super::error::ProblemField;
+use crate::proto::CreateOrderRequest;
+use crate::services::{
+    AppError, FieldViolation, ImportJob, ImportJobStatus, ImportRows, NewOrder, NewUser,
+};
+use actix_multipart::Multipart;
+use actix_web::error::{ErrorBadRequest, ErrorPayloadTooLarge, ErrorUnsupportedMediaType};
+use actix_web::http::header::LOCATION;
+use actix_web::{Error, HttpResponse};
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Upload size and the row count above which imports run in the background
+pub struct ImportLimits {
+    pub max_bytes: usize,
+    pub inline_rows: usize,
+}
+
+/// Query string of `POST /api/v1/imports/{kind}`
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    /// Validate every row without writing anything
+    #[serde(default)]
+    pub dry_run: bool,
+    /// `csv` or `jsonl`, detected from the uploaded file when absent
+    pub format: Option<String>,
+}
+
+/// Multipart body of `POST /api/v1/imports/{kind}`, read field by field by ImportUploadController
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)] // Describes the upload in the OpenAPI document only
+pub struct ImportUpload {
+    /// CSV with a header row, or JSON Lines
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+enum UploadFormat {
+    Csv,
+    JsonLines,
+}
+
+impl UploadFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(Self::Csv),
+            "jsonl" | "ndjson" => Some(Self::JsonLines),
+            _ => None,
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "text/csv" => Some(Self::Csv),
+            "application/jsonl" | "application/x-ndjson" | "application/json-lines" => {
+                Some(Self::JsonLines)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Row of a users upload
+#[derive(Deserialize)]
+struct UserRow {
+    name: String,
+    email: String,
+}
+
+impl From<UserRow> for NewUser {
+    fn from(r: UserRow) -> Self {
+        Self {
+            name: r.name,
+            email: r.email,
+        }
+    }
+}
+
+type Rows<R> = Vec<(usize, Result<R, Vec<FieldViolation>>)>;
+
+fn parse_csv<R: DeserializeOwned>(data: &[u8], columns: &[&str]) -> Result<Rows<R>, AppError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data);
+    let headers = reader
+        .headers()
+        .map_err(|e| AppError::Validation(vec![FieldViolation::new("file", &e.to_string())]))?
+        .clone();
+    let missing: Vec<FieldViolation> = columns
+        .iter()
+        .filter(|c| !headers.iter().any(|h| h == **c))
+        .map(|c| FieldViolation::new("file", &format!("missing column {c:?}")))
+        .collect();
+    if !missing.is_empty() {
+        return Err(AppError::Validation(missing));
+    }
+
+    let mut rows = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        // The header is line 1
+        let mut line = i + 2;
+        let row = record
+            .and_then(|record| {
+                line = record.position().map_or(line, |p| p.line() as usize);
+                record.deserialize::<R>(Some(&headers))
+            })
+            .map_err(|e| {
+                let violation = match e.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => {
+                        let field = err
+                            .field()
+                            .and_then(|f| headers.get(f as usize))
+                            .unwrap_or("row");
+                        FieldViolation::new(field, &err.kind().to_string())
+                    }
+                    other => FieldViolation::new("row", &format!("{other:?}")),
+                };
+                vec![violation]
+            });
+        rows.push((line, row));
+    }
+    Ok(rows)
+}
+
+fn parse_json_lines<R: DeserializeOwned>(data: &[u8]) -> Rows<R> {
+    data.split(|b| *b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim_ascii().is_empty())
+        .map(|(i, line)| {
+            let row = serde_json::from_slice(line)
+                .map_err(|e| vec![FieldViolation::new("row", &e.to_string())]);
+            (i + 1, row)
+        })
+        .collect()
+}
+
+/// The `file` part of a multipart import upload
+pub struct ImportUploadController {
+    format: UploadFormat,
+    data: Vec<u8>,
+}
+
+impl ImportUploadController {
+    /// Reads the upload into memory, refusing more than max_bytes
+    pub async fn read(
+        mut multipart: Multipart,
+        format: Option<&str>,
+        max_bytes: usize,
+    ) -> Result<Self, Error> {
+        let requested = match format {
+            None => None,
+            Some(name) => Some(UploadFormat::parse(name).ok_or_else(|| {
+                AppError::Validation(vec![FieldViolation::new(
+                    "format",
+                    "must be one of csv, jsonl",
+                )])
+            })?),
+        };
+
+        while let Some(field) = multipart.next().await {
+            let mut field = field?;
+            if field.name() != Some("file") {
+                continue;
+            }
+            let filename = field
+                .content_disposition()
+                .and_then(|d| d.get_filename())
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            let format = requested
+                .or_else(|| {
+                    field
+                        .content_type()
+                        .and_then(|m| UploadFormat::from_media_type(m.essence_str()))
+                })
+                .or_else(|| {
+                    let extension = filename.rsplit_once('.').map(|(_, e)| e)?;
+                    UploadFormat::parse(extension)
+                })
+                .ok_or_else(|| {
+                    ErrorUnsupportedMediaType(
+                        "upload text/csv or application/jsonl, or pass ?format=csv|jsonl",
+                    )
+                })?;
+
+            let mut data = Vec::new();
+            while let Some(chunk) = field.next().await {
+                let chunk = chunk?;
+                if data.len() + chunk.len() > max_bytes {
+                    return Err(ErrorPayloadTooLarge(format!(
+                        "uploads are limited to {max_bytes} bytes"
+                    )));
+                }
+                data.extend_from_slice(&chunk);
+            }
+            return Ok(Self { format, data });
+        }
+        Err(ErrorBadRequest("multipart body has no \"file\" part"))
+    }
+
+    /// One entry per data row
+    pub fn parse(&self, kind: &str) -> Result<ImportRows, AppError> {
+        match kind {
+            "orders" => {
+                let rows: Rows<CreateOrderRequest> = match self.format {
+                    UploadFormat::Csv => {
+                        parse_csv(&self.data, &["user_id", "product", "quantity"])?
+                    }
+                    UploadFormat::JsonLines => parse_json_lines(&self.data),
+                };
+                // Rules are checked as rows are imported, see NewOrder::validate
+                let rows = rows
+                    .into_iter()
+                    .map(|(line, row)| (line, row.map(NewOrder::from)))
+                    .collect();
+                Ok(ImportRows::Orders(rows))
+            }
+            "users" => {
+                let rows: Rows<UserRow> = match self.format {
+                    UploadFormat::Csv => parse_csv(&self.data, &["name", "email"])?,
+                    UploadFormat::JsonLines => parse_json_lines(&self.data),
+                };
+                let rows = rows
+                    .into_iter()
+                    .map(|(line, row)| (line, row.map(NewUser::from)))
+                    .collect();
+                Ok(ImportRows::Users(rows))
+            }
+            other => Err(AppError::NotFound {
+                resource: "import kind",
+                id: other.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct RowErrorBody {
+    /// Line in the uploaded file
+    pub line: usize,
+    pub errors: Vec<ProblemField>,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct ImportJobBody {
+    pub id: String,
+    pub kind: &'static str,
+    pub dry_run: bool,
+    /// `running`, `succeeded` or `failed`
+    pub status: &'static str,
+    pub total: usize,
+    pub processed: usize,
+    pub imported: usize,
+    pub failed: usize,
+    pub errors: Vec<RowErrorBody>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Unix timestamps in milliseconds
+    pub started_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+}
+
+pub struct ImportJobController(pub ImportJobBody);
+
+impl ImportJobController {
+    pub fn from_job(job: ImportJob) -> Self {
+        let status = match job.status {
+            ImportJobStatus::Running => "running",
+            ImportJobStatus::Succeeded => "succeeded",
+            ImportJobStatus::Failed => "failed",
+        };
+        Self(ImportJobBody {
+            id: job.id,
+            kind: job.kind,
+            dry_run: job.dry_run,
+            status,
+            total: job.total,
+            processed: job.processed,
+            imported: job.imported,
+            failed: job.failed,
+            errors: job
+                .errors
+                .iter()
+                .map(|e| RowErrorBody {
+                    line: e.line,
+                    errors: e.violations.iter().map(ProblemField::from).collect(),
+                })
+                .collect(),
+            error: job.error,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+        })
+    }
+
+    pub fn into_http(self) -> HttpResponse {
+        HttpResponse::Ok().json(self.0)
+    }
+
+    /// For jobs still running in the background, Location points at the status endpoint
+    pub fn into_accepted(self) -> HttpResponse {
+        HttpResponse::Accepted()
+            .insert_header((LOCATION, format!("/api/v1/imports/jobs/{}", self.0.id)))
+            .json(self.0)
+    }
+}
@@ -1,3 +1,8 @@
+pub mod error;
+pub mod export;
+pub mod health;
+pub mod import;
 pub mod login;
+pub mod negotiation;
 pub mod order;
 pub mod user;
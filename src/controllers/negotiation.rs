@@ -0,0 +1,167 @@
+use crate::services::AppError;
+use actix_web::body::BoxBody;
+use actix_web::error::ErrorNotAcceptable;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE, VARY};
+use actix_web::{HttpRequest, HttpResponse, Responder, ResponseError};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Wire formats of the HTTP API, picked from Accept (responses) and Content-Type (requests)
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Protobuf,
+    MsgPack,
+    Cbor,
+}
+
+pub const SUPPORTED_MEDIA_TYPES: &str =
+    "application/json, application/x-protobuf, application/msgpack, application/cbor";
+
+const VENDOR_PREFIX: &str = "application/vnd.app.v";
+
+/// API version and format of a vendor media type, e.g. `application/vnd.app.v2+json`.
+/// The suffix picks the format and defaults to JSON.
+pub fn vendor_media_type(media_type: &str) -> Option<(u32, Format)> {
+    let essence = media_type.split(';').next()?.trim().to_ascii_lowercase();
+    let rest = essence.strip_prefix(VENDOR_PREFIX)?;
+    let (version, suffix) = rest.split_once('+').unwrap_or((rest, "json"));
+    let format = match suffix {
+        "json" => Format::Json,
+        "protobuf" | "x-protobuf" => Format::Protobuf,
+        "msgpack" => Format::MsgPack,
+        "cbor" => Format::Cbor,
+        _ => return None,
+    };
+    Some((version.parse().ok()?, format))
+}
+
+impl Format {
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        let essence = media_type.split(';').next()?.trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "application/json" => Some(Self::Json),
+            "application/x-protobuf" | "application/protobuf" => Some(Self::Protobuf),
+            "application/msgpack" | "application/x-msgpack" => Some(Self::MsgPack),
+            "application/cbor" => Some(Self::Cbor),
+            other => vendor_media_type(other).map(|(_, format)| format),
+        }
+    }
+
+    pub fn media_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Protobuf => "application/x-protobuf",
+            Self::MsgPack => "application/msgpack",
+            Self::Cbor => "application/cbor",
+        }
+    }
+
+    /// Preferred supported format of the Accept header, JSON when it is absent or a wildcard.
+    /// None when the client accepts none of the supported formats.
+    pub fn from_accept(req: &HttpRequest) -> Option<Self> {
+        let Some(accept) = req.headers().get(ACCEPT).and_then(|v| v.to_str().ok()) else {
+            return Some(Self::Json);
+        };
+        if accept.trim().is_empty() {
+            return Some(Self::Json);
+        }
+
+        let mut ranges: Vec<(&str, f32)> = accept
+            .split(',')
+            .map(|range| {
+                let mut params = range.split(';');
+                let media_type = params.next().unwrap_or_default().trim();
+                let q = params
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .find_map(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                (media_type, q)
+            })
+            .filter(|(_, q)| *q > 0.0)
+            .collect();
+        // Stable sort keeps the client's order between equal weights
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        ranges
+            .into_iter()
+            .find_map(|(media_type, _)| match media_type {
+                "*/*" | "application/*" => Some(Self::Json),
+                other => Self::from_media_type(other),
+            })
+    }
+
+    /// Format of the request body, JSON when Content-Type is absent.
+    /// None for an unsupported Content-Type.
+    pub fn from_content_type(req: &HttpRequest) -> Option<Self> {
+        match req.headers().get(CONTENT_TYPE) {
+            None => Some(Self::Json),
+            Some(value) => value.to_str().ok().and_then(Self::from_media_type),
+        }
+    }
+
+    pub fn encode<T: Serialize + prost::Message>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Self::Protobuf => Ok(value.encode_to_vec()),
+            // Maps rather than arrays, so fields are named like in JSON
+            Self::MsgPack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Self::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map(|()| bytes)
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned + prost::Message + Default>(
+        self,
+        bytes: &[u8],
+    ) -> Result<T, String> {
+        match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Self::Protobuf => T::decode(bytes).map_err(|e| e.to_string()),
+            Self::MsgPack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            Self::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+pub fn not_acceptable() -> HttpResponse {
+    ErrorNotAcceptable(format!("supported media types: {SUPPORTED_MEDIA_TYPES}")).error_response()
+}
+
+/// Response body encoded in the format the client asked for in Accept
+pub struct Negotiated<T> {
+    status: StatusCode,
+    body: T,
+}
+
+impl<T> Negotiated<T> {
+    pub fn new(status: StatusCode, body: T) -> Self {
+        Self { status, body }
+    }
+
+    pub fn ok(body: T) -> Self {
+        Self::new(StatusCode::OK, body)
+    }
+}
+
+impl<T: Serialize + prost::Message> Responder for Negotiated<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse {
+        let Some(format) = Format::from_accept(req) else {
+            return not_acceptable();
+        };
+        match format.encode(&self.body) {
+            Ok(bytes) => HttpResponse::build(self.status)
+                .content_type(format.media_type())
+                .insert_header((VARY, "accept"))
+                .body(bytes),
+            Err(e) => AppError::Internal(e).error_response(),
+        }
+    }
+}
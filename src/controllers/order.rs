@@ -1,29 +1,144 @@
-use crate::proto::{GetOrdersResponse, Order as ProtoOrder};
-use crate::services::Order;
-use actix_web::HttpResponse;
+use crate::proto::{
+    CreateOrderRequest, FieldViolation as ProtoFieldViolation, GetOrdersResponse, ImportAck,
+    ImportError, ImportSummary, Order as ProtoOrder, OrderEvent as ProtoOrderEvent,
+    OrderEventType,
+};
+use crate::services::{FieldViolation, ImportOutcome, NewOrder, Order, OrderEvent, OrderEventKind};
+use super::negotiation::Negotiated;
+use actix_web::http::StatusCode;
 use tonic::Response;
 
+impl From<CreateOrderRequest> for NewOrder {
+    fn from(r: CreateOrderRequest) -> Self {
+        Self {
+            user_id: r.user_id,
+            product: r.product,
+            quantity: r.quantity,
+        }
+    }
+}
+
+impl From<FieldViolation> for ProtoFieldViolation {
+    fn from(v: FieldViolation) -> Self {
+        Self {
+            field: v.field,
+            description: v.description,
+        }
+    }
+}
+
+impl From<Order> for ProtoOrder {
+    fn from(o: Order) -> Self {
+        Self {
+            id: o.id,
+            user_id: o.user_id,
+            product: o.product,
+            quantity: o.quantity,
+        }
+    }
+}
+
 pub struct OrderController(pub GetOrdersResponse);
 
 impl OrderController {
     pub fn from_orders(orders: Vec<Order>) -> Self {
-        let proto_orders: Vec<ProtoOrder> = orders
-            .into_iter()
-            .map(|o| ProtoOrder {
-                id: o.id,
-                user_id: o.user_id,
-                product: o.product,
-                quantity: o.quantity,
-            })
-            .collect();
+        let proto_orders: Vec<ProtoOrder> = orders.into_iter().map(ProtoOrder::from).collect();
         Self(GetOrdersResponse { orders: proto_orders })
     }
 
-    pub fn to_http(&self) -> HttpResponse {
-        HttpResponse::Ok().json(&self.0)
+    pub fn into_http(self) -> Negotiated<GetOrdersResponse> {
+        Negotiated::ok(self.0)
+    }
+
+    pub fn into_grpc(self) -> Response<GetOrdersResponse> {
+        Response::new(self.0)
+    }
+}
+
+pub struct CreatedOrderController(pub ProtoOrder);
+
+impl CreatedOrderController {
+    pub fn from_order(order: Order) -> Self {
+        Self(order.into())
+    }
+
+    pub fn into_http(self) -> Negotiated<ProtoOrder> {
+        Negotiated::new(StatusCode::CREATED, self.0)
+    }
+}
+
+pub struct SingleOrderController(pub ProtoOrder);
+
+impl SingleOrderController {
+    pub fn from_order(order: Order) -> Self {
+        Self(order.into())
+    }
+
+    pub fn into_grpc(self) -> Response<ProtoOrder> {
+        Response::new(self.0)
+    }
+}
+
+/// Stream item for WatchOrders
+pub struct OrderEventController(pub ProtoOrderEvent);
+
+impl OrderEventController {
+    pub fn from_event(event: OrderEvent) -> Self {
+        let r#type = match event.kind {
+            OrderEventKind::Created => OrderEventType::Created,
+        };
+        Self(ProtoOrderEvent {
+            r#type: r#type.into(),
+            order: Some(event.order.into()),
+            occurred_at: event.occurred_at,
+        })
+    }
+}
+
+/// Stream item for ImportOrdersWithAcks
+pub struct ImportAckController(pub ImportAck);
+
+impl ImportAckController {
+    pub fn from_outcome(outcome: ImportOutcome) -> Self {
+        let index = outcome.index as u32;
+        Self(match outcome.result {
+            Ok(order) => ImportAck {
+                index,
+                order: Some(order.into()),
+                violations: Vec::new(),
+            },
+            Err(violations) => ImportAck {
+                index,
+                order: None,
+                violations: violations.into_iter().map(Into::into).collect(),
+            },
+        })
+    }
+}
+
+/// Accumulates outcomes of a client-streaming ImportOrders call
+#[derive(Default)]
+pub struct ImportSummaryController(pub ImportSummary);
+
+impl ImportSummaryController {
+    pub fn add(&mut self, outcomes: Vec<ImportOutcome>) {
+        for outcome in outcomes {
+            self.0.received += 1;
+            match outcome.result {
+                Ok(_) => self.0.imported += 1,
+                Err(violations) => {
+                    self.0.failed += 1;
+                    self.0.errors.push(ImportError {
+                        index: outcome.index as u32,
+                        violations: violations.into_iter().map(Into::into).collect(),
+                    });
+                }
+            }
+        }
     }
 
-    pub fn to_grpc(self) -> Result<Response<GetOrdersResponse>, tonic::Status> {
-        Ok(Response::new(self.0))
+    pub fn into_grpc(mut self) -> Response<ImportSummary> {
+        self.0.errors.sort_by_key(|e| e.index);
+        Response::new(self.0)
     }
 }
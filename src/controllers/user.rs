@@ -1,7 +1,18 @@
-use crate::proto::GetUsersResponse;
-use actix_web::HttpResponse;
+use crate::proto::{GetUsersResponse, GetUsersV2Response, UserV2};
+use crate::services::User;
+use super::negotiation::Negotiated;
 use tonic::Response;
 
+impl From<User> for UserV2 {
+    fn from(u: User) -> Self {
+        Self {
+            id: u.id,
+            name: u.name,
+            email: u.email,
+        }
+    }
+}
+
 pub struct UserController(pub GetUsersResponse);
 
 impl UserController {
@@ -9,11 +20,29 @@ impl UserController {
         Self(GetUsersResponse { users })
     }
 
-    pub fn to_http(&self) -> HttpResponse {
-        HttpResponse::Ok().json(&self.0)
+    pub fn into_http(self) -> Negotiated<GetUsersResponse> {
+        Negotiated::ok(self.0)
+    }
+
+    pub fn into_grpc(self) -> Response<GetUsersResponse> {
+        Response::new(self.0)
+    }
+}
+
+pub struct UserV2Controller(pub GetUsersV2Response);
+
+impl UserV2Controller {
+    pub fn from_users(users: Vec<User>) -> Self {
+        Self(GetUsersV2Response {
+            users: users.into_iter().map(UserV2::from).collect(),
+        })
+    }
+
+    pub fn into_http(self) -> Negotiated<GetUsersV2Response> {
+        Negotiated::ok(self.0)
     }
 
-    pub fn to_grpc(self) -> Result<Response<GetUsersResponse>, tonic::Status> {
-        Ok(Response::new(self.0))
+    pub fn into_grpc(self) -> Response<GetUsersV2Response> {
+        Response::new(self.0)
     }
 }
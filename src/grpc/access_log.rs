@@ -0,0 +1,167 @@
+use super::single_port::is_grpc;
+use super::tls::remote_addr;
+use crate::auth::Claims;
+use crate::logging::{self, AccessLog, TrustedProxies};
+use crate::telemetry;
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use http::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use http::{Request, Response};
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::Status;
+use tonic::body::Body;
+use tower::{Layer, Service};
+
+const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+const FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+fn grpc_status(headers: &HeaderMap) -> Option<i32> {
+    headers.get("grpc-status")?.to_str().ok()?.parse().ok()
+}
+
+/// Counts response bytes and picks grpc-status from the trailers,
+/// then writes the access log once the call is over
+struct LoggedBody {
+    body: Body,
+    entry: AccessLog,
+    status: u16,
+    grpc_status: Option<i32>,
+    bytes: u64,
+    user: Option<String>,
+}
+
+impl HttpBody for LoggedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.body).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll {
+            if let Some(data) = frame.data_ref() {
+                self.bytes += data.len() as u64;
+            }
+            if let Some(status) = frame.trailers_ref().and_then(grpc_status) {
+                self.grpc_status = Some(status);
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        self.entry
+            .finish(self.status, self.grpc_status, self.bytes, self.user.as_deref());
+    }
+}
+
+/// Structured access log of gRPC calls, with an X-Request-Id that is propagated
+/// to the handler's metadata or generated, and echoed in the response headers
+#[derive(Clone)]
+pub struct AccessLogLayer {
+    proxies: Arc<TrustedProxies>,
+}
+
+impl AccessLogLayer {
+    pub fn new(proxies: TrustedProxies) -> Self {
+        Self {
+            proxies: Arc::new(proxies),
+        }
+    }
+}
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLogService {
+            inner,
+            proxies: self.proxies.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AccessLogService<S> {
+    inner: S,
+    proxies: Arc<TrustedProxies>,
+}
+
+impl<S> Service<Request<Body>> for AccessLogService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        // In single port mode REST requests pass through here too, actix logs those
+        if !is_grpc(&req) {
+            return Box::pin(self.inner.call(req));
+        }
+
+        let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+        let request_id = logging::request_id(header(REQUEST_ID));
+        let peer = remote_addr(&req).map(|addr| addr.ip());
+        // Set by ClientCertLayer for callers with a client certificate
+        let user = req.extensions().get::<Claims>().map(|claims| claims.sub.clone());
+        let entry = AccessLog {
+            protocol: "grpc",
+            request_id: request_id.clone(),
+            method: req.method().to_string(),
+            path: req.uri().path().to_string(),
+            client_ip: self.proxies.client_ip(peer, header(FORWARDED_FOR)),
+            user_agent: header(USER_AGENT).map(str::to_string),
+            trace_id: telemetry::current_trace_id(),
+            started: Instant::now(),
+        };
+        let request_id = HeaderValue::from_str(&request_id).ok();
+        if let Some(value) = &request_id {
+            req.headers_mut().insert(REQUEST_ID, value.clone());
+        }
+
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if let Some(value) = request_id {
+                res.headers_mut().insert(REQUEST_ID, value);
+            }
+            // Trailers-only responses (errors) carry grpc-status in the headers
+            let grpc_status = grpc_status(res.headers());
+            let status = res.status().as_u16();
+            Ok(res.map(|body| {
+                Body::new(LoggedBody {
+                    body,
+                    entry,
+                    status,
+                    grpc_status,
+                    bytes: 0,
+                    user,
+                })
+            }))
+        })
+    }
+}
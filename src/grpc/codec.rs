@@ -0,0 +1,138 @@
+use crate::validation::Validator;
+use prost::{Message, Name};
+use tonic::Status;
+use tonic::codec::{BufferSettings, Codec, DecodeBuf, Decoder};
+use tonic_prost::{ProstCodec, ProstDecoder, ProstEncoder};
+
+/// `ProstCodec` that checks each request against its `(validate.rules)` once it is decoded,
+/// before the handler runs. build.rs makes it the codec of every generated service, so a new
+/// RPC is validated without asking. Violations fail the call with INVALID_ARGUMENT.
+pub struct ValidatingCodec<T, U>(ProstCodec<T, U>);
+
+impl<T, U> Default for ValidatingCodec<T, U> {
+    fn default() -> Self {
+        Self(ProstCodec::default())
+    }
+}
+
+impl<T, U> Codec for ValidatingCodec<T, U>
+where
+    T: Message + Send + 'static,
+    U: Message + Name + Default + Send + 'static,
+{
+    type Encode = T;
+    type Decode = U;
+
+    type Encoder = ProstEncoder<T>;
+    type Decoder = ValidatingDecoder<U>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        self.0.encoder()
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        ValidatingDecoder(self.0.decoder())
+    }
+}
+
+pub struct ValidatingDecoder<U>(ProstDecoder<U>);
+
+impl<U: Message + Name + Default> Decoder for ValidatingDecoder<U> {
+    type Item = U;
+    type Error = Status;
+
+    fn decode(&mut self, buf: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        let item = self.0.decode(buf)?;
+        if let Some(item) = &item {
+            Validator::shared().validate_request(item)?;
+        }
+        Ok(item)
+    }
+
+    fn buffer_settings(&self) -> BufferSettings {
+        self.0.buffer_settings()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grpc::endpoints::order::OrderEndpoint;
+    use crate::proto::order_service_server::OrderServiceServer;
+    use crate::proto::{CreateOrderRequest, GetOrderRequest, GetOrdersRequest, WatchOrdersRequest};
+    use crate::services::OrderServiceFactoryImpl;
+    use http_body_util::Full;
+    use prost::Message;
+    use std::sync::Arc;
+    use tonic::body::Body;
+    use tonic::{Code, Status};
+    use tower::ServiceExt;
+
+    /// Status of a unary or server streaming call that fails before its first message
+    async fn call(path: &str, message: impl Message) -> Status {
+        let payload = message.encode_to_vec();
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&payload);
+        let req = http::Request::builder()
+            .method(http::Method::POST)
+            .uri(path)
+            .header(http::header::CONTENT_TYPE, "application/grpc")
+            .body(Body::new(Full::new(bytes::Bytes::from(frame))))
+            .unwrap();
+        let endpoint = OrderEndpoint::new(Arc::new(OrderServiceFactoryImpl), 100, "secret");
+        let res = OrderServiceServer::new(endpoint)
+            .oneshot(req)
+            .await
+            .unwrap();
+        Status::from_header_map(res.headers()).expect("a trailers-only response")
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_get_orders() {
+        let status = call(
+            "/order.OrderService/GetOrders",
+            GetOrdersRequest {
+                user_id: "not valid!".to_string(),
+            },
+        )
+        .await;
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_get_order() {
+        let status = call(
+            "/order.OrderService/GetOrder",
+            GetOrderRequest {
+                user_id: "u1".to_string(),
+                order_id: "seven".to_string(),
+            },
+        )
+        .await;
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_watch_orders() {
+        let status = call(
+            "/order.OrderService/WatchOrders",
+            WatchOrdersRequest {
+                user_id: String::new(),
+            },
+        )
+        .await;
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn leaves_client_stream_items_to_their_handler() {
+        let validator = crate::validation::Validator::shared();
+        let item = CreateOrderRequest {
+            user_id: String::new(),
+            product: String::new(),
+            quantity: 0,
+        };
+        assert!(validator.validate_request(&item).is_ok());
+        assert!(validator.validate(&item).is_err());
+    }
+}
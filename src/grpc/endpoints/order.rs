@@ -1,18 +1,86 @@
-use crate::controllers::order::OrderController;
+use crate::auth::Claims;
+use crate::controllers::order::{
+    ImportAckController, ImportSummaryController, OrderController, OrderEventController,
+    SingleOrderController,
+};
+use crate::metrics::{AuthRejection, Metrics};
 use crate::proto::order_service_server::OrderService as GrpcOrderService;
-use crate::proto::{GetOrdersRequest, GetOrdersResponse};
-use crate::services::OrderServiceFactory;
+use crate::proto::{
+    CreateOrderRequest, GetOrderRequest, GetOrdersRequest, GetOrdersResponse, ImportAck,
+    ImportSummary, Order, OrderEvent, WatchOrdersRequest,
+};
+use crate::services::{AppError, OrderImporter, OrderServiceFactory};
+use futures_util::Stream;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode};
+use std::pin::Pin;
 use std::sync::Arc;
-use tonic::{Request, Response, Status};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
+use tonic::{Request, Response, Status, Streaming};
+
+/// Role that may read and watch every user's orders
+const ADMIN_ROLE: &str = "admin";
 
 /// OrderEndpoint holds a factory for Scoped lifetime
 pub struct OrderEndpoint<F: OrderServiceFactory> {
     order_service_factory: Arc<F>,
+    import_max_batch: usize,
+    decoding_key: DecodingKey,
 }
 
 impl<F: OrderServiceFactory> OrderEndpoint<F> {
-    pub fn new(order_service_factory: Arc<F>) -> Self {
-        Self { order_service_factory }
+    pub fn new(order_service_factory: Arc<F>, import_max_batch: usize, jwt_secret: &str) -> Self {
+        Self {
+            order_service_factory,
+            import_max_batch,
+            decoding_key: DecodingKey::from_secret(jwt_secret.as_bytes()),
+        }
+    }
+
+    /// Claims of the bearer token, or without one those of a verified client certificate,
+    /// added by ClientCertLayer or the HTTP gateway. Only the owner or an admin gets through.
+    fn authorize<T>(&self, request: &Request<T>, user_id: &str) -> Result<(), AppError> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let claims = match token {
+            Some(token) => {
+                match decode::<Claims>(
+                    token,
+                    &self.decoding_key,
+                    &Validation::new(Algorithm::HS256),
+                ) {
+                    Ok(d) => d.claims,
+                    Err(_) => {
+                        Metrics::shared().auth_rejected(AuthRejection::Invalid);
+                        return Err(AppError::Unauthorized(
+                            "invalid or expired token".to_string(),
+                        ));
+                    }
+                }
+            }
+            None => match request.extensions().get::<Claims>() {
+                Some(certificate) => certificate.clone(),
+                None => {
+                    Metrics::shared().auth_rejected(AuthRejection::Missing);
+                    return Err(AppError::Unauthorized(
+                        "missing bearer token or client certificate".to_string(),
+                    ));
+                }
+            },
+        };
+
+        if claims.sub != user_id && !claims.roles.iter().any(|role| role == ADMIN_ROLE) {
+            Metrics::shared().auth_rejected(AuthRejection::ForbiddenRole);
+            return Err(AppError::Forbidden(
+                "orders of another user need the admin role".to_string(),
+            ));
+        }
+        Ok(())
     }
 }
 
@@ -24,7 +92,331 @@ impl<F: OrderServiceFactory + 'static> GrpcOrderService for OrderEndpoint<F> {
     ) -> Result<Response<GetOrdersResponse>, Status> {
         let service = self.order_service_factory.create();
         let user_id = &request.into_inner().user_id;
-        let orders = service.get_orders(user_id).await;
-        OrderController::from_orders(orders).to_grpc()
+        let orders = service.get_orders(user_id).await?;
+        Ok(OrderController::from_orders(orders).into_grpc())
+    }
+
+    async fn get_order(&self, request: Request<GetOrderRequest>) -> Result<Response<Order>, Status> {
+        self.authorize(&request, &request.get_ref().user_id)?;
+        let service = self.order_service_factory.create();
+        let req = request.into_inner();
+        let order = service.get_order(&req.user_id, &req.order_id).await?;
+        Ok(SingleOrderController::from_order(order).into_grpc())
+    }
+
+    type WatchOrdersStream = Pin<Box<dyn Stream<Item = Result<OrderEvent, Status>> + Send>>;
+
+    /// Streams order changes as they happen, to the owner or an admin; a subscriber that falls too far behind
+    /// gets DATA_LOSS and should re-read with GetOrders before watching again
+    async fn watch_orders(
+        &self,
+        request: Request<WatchOrdersRequest>,
+    ) -> Result<Response<Self::WatchOrdersStream>, Status> {
+        self.authorize(&request, &request.get_ref().user_id)?;
+        let service = self.order_service_factory.create();
+        let user_id = request.into_inner().user_id;
+        let events = BroadcastStream::new(service.watch_orders()).filter_map(move |event| {
+            match event {
+                Ok(event) if event.order.user_id == user_id => {
+                    Some(Ok(OrderEventController::from_event(event).0))
+                }
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(Err(Status::data_loss(
+                    format!("watcher lagged behind, {skipped} events dropped"),
+                ))),
+            }
+        });
+        // Events only stop when the server shuts down: tell the client to watch elsewhere
+        let events = events.chain(tokio_stream::once(Err(Status::unavailable(
+            "server is shutting down, watch again",
+        ))));
+        Ok(Response::new(Box::pin(events)))
+    }
+
+    /// Client-streaming import: valid items are committed in batches of
+    /// IMPORT_MAX_BATCH, invalid ones are reported in the summary
+    async fn import_orders(
+        &self,
+        request: Request<Streaming<CreateOrderRequest>>,
+    ) -> Result<Response<ImportSummary>, Status> {
+        let mut importer =
+            OrderImporter::new(self.order_service_factory.create(), self.import_max_batch);
+        let mut summary = ImportSummaryController::default();
+        let mut stream = request.into_inner();
+
+        while let Some(item) = stream.message().await? {
+            // The importer checks each item's rules and reports a rejected one in the summary
+            summary.add(importer.push(item.into()).await?);
+        }
+        summary.add(importer.finish().await?);
+        Ok(summary.into_grpc())
+    }
+
+    type ImportOrdersWithAcksStream =
+        Pin<Box<dyn Stream<Item = Result<ImportAck, Status>> + Send>>;
+
+    /// Bidirectional import: rejected items are acked immediately,
+    /// accepted ones once the batch holding them is committed
+    async fn import_orders_with_acks(
+        &self,
+        request: Request<Streaming<CreateOrderRequest>>,
+    ) -> Result<Response<Self::ImportOrdersWithAcksStream>, Status> {
+        let mut importer =
+            OrderImporter::new(self.order_service_factory.create(), self.import_max_batch);
+        let mut stream = request.into_inner();
+        let (tx, rx) = mpsc::channel(self.import_max_batch.max(1));
+
+        tokio::spawn(async move {
+            loop {
+                let outcomes = match stream.message().await {
+                    Ok(Some(item)) => importer.push(item.into()).await.map_err(Status::from),
+                    Ok(None) => break,
+                    Err(status) => Err(status),
+                };
+                let outcomes = match outcomes {
+                    Ok(outcomes) => outcomes,
+                    Err(status) => {
+                        let _ = tx.send(Err(status)).await;
+                        return;
+                    }
+                };
+                for outcome in outcomes {
+                    if tx.send(Ok(ImportAckController::from_outcome(outcome).0)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            match importer.finish().await {
+                Ok(outcomes) => {
+                    for outcome in outcomes {
+                        let _ = tx.send(Ok(ImportAckController::from_outcome(outcome).0)).await;
+                    }
+                }
+                Err(error) => {
+                    let _ = tx.send(Err(error.into())).await;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::order_service_server::OrderServiceServer;
+    use crate::services::OrderServiceFactoryImpl;
+    use bytes::{Buf, Bytes, BytesMut};
+    use http_body::Frame;
+    use http_body_util::{BodyExt, StreamBody};
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use prost::Message;
+    use std::convert::Infallible;
+    use std::time::Duration;
+    use tonic::Code;
+    use tonic::body::Body;
+    use tower::ServiceExt;
+
+    const SECRET: &str = "secret";
+
+    fn endpoint() -> OrderEndpoint<OrderServiceFactoryImpl> {
+        OrderEndpoint::new(Arc::new(OrderServiceFactoryImpl), 100, SECRET)
+    }
+
+    fn claims(sub: &str, roles: &[&str]) -> Claims {
+        Claims {
+            sub: sub.to_string(),
+            exp: 4_000_000_000,
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            policies: Vec::new(),
+        }
+    }
+
+    fn with_token<T>(message: T, sub: &str, roles: &[&str]) -> Request<T> {
+        let token = encode(
+            &Header::default(),
+            &claims(sub, roles),
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        )
+        .unwrap();
+        let mut request = Request::new(message);
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {token}").parse().unwrap());
+        request
+    }
+
+    fn get_order(user_id: &str) -> GetOrderRequest {
+        GetOrderRequest {
+            user_id: user_id.to_string(),
+            order_id: "999999".to_string(),
+        }
+    }
+
+    fn watch_orders(user_id: &str) -> WatchOrdersRequest {
+        WatchOrdersRequest {
+            user_id: user_id.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_order_needs_the_owner_or_an_admin() {
+        let endpoint = endpoint();
+        let code = |result: Result<Response<Order>, Status>| result.unwrap_err().code();
+
+        let anonymous = endpoint.get_order(Request::new(get_order("u1"))).await;
+        assert_eq!(code(anonymous), Code::Unauthenticated);
+        let mut forged = Request::new(get_order("u1"));
+        forged
+            .metadata_mut()
+            .insert("authorization", "Bearer forged".parse().unwrap());
+        assert_eq!(
+            code(endpoint.get_order(forged).await),
+            Code::Unauthenticated
+        );
+
+        let other = endpoint
+            .get_order(with_token(get_order("u1"), "u2", &[]))
+            .await;
+        assert_eq!(code(other), Code::PermissionDenied);
+
+        // Past the check, the order does not exist
+        let owner = endpoint
+            .get_order(with_token(get_order("u1"), "u1", &[]))
+            .await;
+        assert_eq!(code(owner), Code::NotFound);
+        let admin = endpoint
+            .get_order(with_token(get_order("u1"), "u2", &[ADMIN_ROLE]))
+            .await;
+        assert_eq!(code(admin), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn watch_orders_needs_the_owner_or_an_admin() {
+        let endpoint = endpoint();
+        let code = |result: Result<Response<_>, Status>| result.err().map(|status| status.code());
+
+        let anonymous = endpoint
+            .watch_orders(Request::new(watch_orders("u1")))
+            .await;
+        assert_eq!(code(anonymous), Some(Code::Unauthenticated));
+        let other = endpoint
+            .watch_orders(with_token(watch_orders("u1"), "u2", &[]))
+            .await;
+        assert_eq!(code(other), Some(Code::PermissionDenied));
+
+        let owner = endpoint
+            .watch_orders(with_token(watch_orders("u1"), "u1", &[]))
+            .await;
+        assert_eq!(code(owner), None);
+    }
+
+    #[tokio::test]
+    async fn client_certificate_claims_stand_in_for_a_token() {
+        let endpoint = endpoint();
+        let request = |sub: &str, roles: &[&str]| {
+            let mut request = Request::new(watch_orders("u1"));
+            request.extensions_mut().insert(claims(sub, roles));
+            request
+        };
+
+        let billing = endpoint.watch_orders(request("billing", &[])).await;
+        assert_eq!(billing.err().unwrap().code(), Code::PermissionDenied);
+        assert!(
+            endpoint
+                .watch_orders(request("billing", &[ADMIN_ROLE]))
+                .await
+                .is_ok()
+        );
+    }
+
+    /// gRPC length-prefixed message: uncompressed flag + big endian length
+    fn frame(message: impl Message) -> Frame<Bytes> {
+        let payload = message.encode_to_vec();
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&payload);
+        Frame::data(Bytes::from(frame))
+    }
+
+    /// Decodes acks from a response body as they arrive
+    struct Acks {
+        body: Body,
+        buffer: BytesMut,
+    }
+
+    impl Acks {
+        /// None once the call has ended
+        async fn next(&mut self) -> Option<ImportAck> {
+            loop {
+                if self.buffer.len() >= 5 {
+                    let len = u32::from_be_bytes(self.buffer[1..5].try_into().unwrap()) as usize;
+                    if self.buffer.len() >= 5 + len {
+                        self.buffer.advance(5);
+                        return Some(ImportAck::decode(self.buffer.split_to(len)).unwrap());
+                    }
+                }
+                let frame = self.body.frame().await?.unwrap();
+                self.buffer
+                    .extend_from_slice(frame.into_data().ok()?.as_ref());
+            }
+        }
+
+        async fn pending(&mut self) -> bool {
+            tokio::time::timeout(Duration::from_millis(100), self.next())
+                .await
+                .is_err()
+        }
+    }
+
+    #[tokio::test]
+    async fn import_with_acks_acks_rejected_items_at_once_and_accepted_ones_per_batch() {
+        let (items, rx) = mpsc::channel::<Result<Frame<Bytes>, Infallible>>(8);
+        let req = http::Request::builder()
+            .method(http::Method::POST)
+            .uri("/order.OrderService/ImportOrdersWithAcks")
+            .header(http::header::CONTENT_TYPE, "application/grpc")
+            .body(Body::new(StreamBody::new(ReceiverStream::new(rx))))
+            .unwrap();
+        let endpoint = OrderEndpoint::new(Arc::new(OrderServiceFactoryImpl), 2, SECRET);
+        let res = OrderServiceServer::new(endpoint)
+            .oneshot(req)
+            .await
+            .unwrap();
+        let mut acks = Acks {
+            body: res.into_body(),
+            buffer: BytesMut::new(),
+        };
+        let item = |quantity| {
+            Ok(frame(CreateOrderRequest {
+                user_id: "importer".to_string(),
+                product: "Pen".to_string(),
+                quantity,
+            }))
+        };
+
+        items.send(item(1)).await.unwrap();
+        assert!(acks.pending().await);
+
+        // Rejected while the accepted item before it waits for its batch
+        items.send(item(0)).await.unwrap();
+        let ack = acks.next().await.unwrap();
+        assert_eq!((ack.index, ack.order.is_none()), (1, true));
+        assert_eq!(ack.violations[0].field, "quantity");
+        assert!(acks.pending().await);
+
+        items.send(item(2)).await.unwrap();
+        let (first, second) = (acks.next().await.unwrap(), acks.next().await.unwrap());
+        assert_eq!((first.index, second.index), (0, 2));
+        assert!(first.order.is_some() && second.order.is_some());
+
+        // The partial batch is committed once the client is done
+        items.send(item(3)).await.unwrap();
+        assert!(acks.pending().await);
+        drop(items);
+        let ack = acks.next().await.unwrap();
+        assert_eq!((ack.index, ack.order.is_some()), (3, true));
+        assert!(acks.next().await.is_none());
     }
 }
@@ -1,6 +1,6 @@
-use crate::controllers::user::UserController;
+use crate::controllers::user::{UserController, UserV2Controller};
 use crate::proto::user_service_server::UserService as GrpcUserService;
-use crate::proto::{GetUsersRequest, GetUsersResponse};
+use crate::proto::{GetUsersRequest, GetUsersResponse, GetUsersV2Request, GetUsersV2Response};
 use crate::services::UserService;
 use std::sync::Arc;
 use tonic::{Request, Response, Status};
@@ -21,7 +21,15 @@ impl<S: UserService + 'static> GrpcUserService for UserEndpoint<S> {
         &self,
         _request: Request<GetUsersRequest>,
     ) -> Result<Response<GetUsersResponse>, Status> {
-        let users = self.user_service.get_users().await;
-        UserController::from_users(users).to_grpc()
+        let users = self.user_service.get_users().await?;
+        Ok(UserController::from_users(users).into_grpc())
+    }
+
+    async fn get_users_v2(
+        &self,
+        _request: Request<GetUsersV2Request>,
+    ) -> Result<Response<GetUsersV2Response>, Status> {
+        let users = self.user_service.list_users().await?;
+        Ok(UserV2Controller::from_users(users).into_grpc())
     }
 }
@@ -0,0 +1,59 @@
+use crate::proto::order_service_server::OrderServiceServer;
+use crate::proto::user_service_server::UserServiceServer;
+use crate::services::{OrderServiceFactory, UserService};
+use super::endpoints::order::OrderEndpoint;
+use super::endpoints::user::UserEndpoint;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tonic::server::NamedService;
+use tonic_health::ServingStatus;
+use tonic_health::server::HealthReporter;
+
+/// Keep grpc.health.v1 statuses in sync with the services behind each gRPC service.
+/// The overall ("") status is SERVING only while every service is.
+/// Everything flips to NOT_SERVING once shutdown starts.
+pub async fn report<U, F>(
+    reporter: HealthReporter,
+    user_service: Arc<U>,
+    order_service_factory: Arc<F>,
+    interval: Duration,
+    shutdown: CancellationToken,
+) where
+    U: UserService + 'static,
+    F: OrderServiceFactory + 'static,
+{
+    let user = <UserServiceServer<UserEndpoint<U>> as NamedService>::NAME;
+    let order = <OrderServiceServer<OrderEndpoint<F>> as NamedService>::NAME;
+
+    loop {
+        let checks = [
+            (user, user_service.check().await),
+            (order, order_service_factory.create().check().await),
+        ];
+
+        let mut all_serving = true;
+        for (name, result) in checks {
+            let status = match result {
+                Ok(()) => ServingStatus::Serving,
+                Err(reason) => {
+                    log::warn!("gRPC service {name} is not serving: {reason}");
+                    all_serving = false;
+                    ServingStatus::NotServing
+                }
+            };
+            reporter.set_service_status(name, status).await;
+        }
+        let overall = if all_serving { ServingStatus::Serving } else { ServingStatus::NotServing };
+        reporter.set_service_status("", overall).await;
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.cancelled() => break,
+        }
+    }
+
+    for name in ["", user, order] {
+        reporter.set_service_status(name, ServingStatus::NotServing).await;
+    }
+}
@@ -0,0 +1,120 @@
+use super::single_port::is_grpc;
+use crate::metrics::{InFlight, Metrics};
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use http::header::HeaderMap;
+use http::{Request, Response};
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::Status;
+use tonic::body::Body;
+use tower::{Layer, Service};
+
+fn grpc_status(headers: &HeaderMap) -> Option<i32> {
+    headers.get("grpc-status")?.to_str().ok()?.parse().ok()
+}
+
+/// Picks grpc-status from the trailers and records the call once the stream is over
+struct MeteredBody {
+    body: Body,
+    path: String,
+    grpc_status: Option<i32>,
+    started: Instant,
+    _in_flight: InFlight,
+}
+
+impl HttpBody for MeteredBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.body).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(status) = frame.trailers_ref().and_then(grpc_status)
+        {
+            self.grpc_status = Some(status);
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl Drop for MeteredBody {
+    fn drop(&mut self) {
+        // A stream dropped before its trailers was cancelled by the client
+        let code = self.grpc_status.unwrap_or(tonic::Code::Cancelled as i32);
+        Metrics::shared().grpc_call(&self.path, code, self.started.elapsed());
+    }
+}
+
+/// Call count, latency and in-flight metrics per gRPC method, see `crate::metrics`
+#[derive(Clone, Default)]
+pub struct MetricsLayer;
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for MetricsService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // In single port mode REST requests pass through here too, actix records those
+        if !is_grpc(&req) {
+            return Box::pin(self.inner.call(req));
+        }
+
+        let in_flight = Metrics::shared().in_flight("grpc");
+        let path = req.uri().path().to_string();
+        let started = Instant::now();
+
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let res = fut.await?;
+            // Trailers-only responses (errors) carry grpc-status in the headers
+            let grpc_status = grpc_status(res.headers());
+            Ok(res.map(|body| {
+                Body::new(MeteredBody {
+                    body,
+                    path,
+                    grpc_status,
+                    started,
+                    _in_flight: in_flight,
+                })
+            }))
+        })
+    }
+}
@@ -1,41 +1,152 @@
+mod access_log;
+pub(crate) mod codec;
 mod endpoints;
+mod health;
+mod metrics;
+mod rate_limit;
+mod single_port;
+mod tls;
+mod trace;
+mod web;
 
 use crate::config::Config;
+use crate::logging::{ProxySecret, TrustedProxies};
 use crate::proto;
+use crate::rate_limit::RateLimiter;
 use crate::services::{OrderServiceFactory, UserService};
+use crate::tls::Tls;
 use endpoints::order::OrderEndpoint;
 use endpoints::user::UserEndpoint;
+use futures_util::future::BoxFuture;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tonic::service::Routes;
 use tonic::transport::Server;
 
+/// Application gRPC services, also called in-process by the HTTP transcoding gateway
+pub fn routes<U, F>(user_service: Arc<U>, order_service_factory: Arc<F>) -> Routes
+where
+    U: UserService + 'static,
+    F: OrderServiceFactory + 'static,
+{
+    let cfg = Config::from_env();
+
+    let user_endpoint = UserEndpoint::new(user_service);
+    let order_endpoint = OrderEndpoint::new(
+        order_service_factory,
+        cfg.import_max_batch,
+        &cfg.jwt_secret,
+    );
+
+    Routes::new(proto::user_service_server::UserServiceServer::new(
+        user_endpoint,
+    ))
+    .add_service(proto::order_service_server::OrderServiceServer::new(
+        order_endpoint,
+    ))
+}
+
 /// Start gRPC server
 /// - user_service: Singleton (shared Arc across all requests)
 /// - order_service_factory: Scoped (factory creates new instance per request)
+/// - rate_limiter: Singleton, shared with the HTTP server
+/// - tls: serve TLS, verifying client certificates if a client CA is configured
+/// - http_upstream: single port mode, serve on HTTP_PORT and forward non-gRPC requests here,
+///   with the secret that lets actix believe the client address passed along
+/// - shutdown: stops the server gracefully once cancelled
 pub async fn start<U, F>(
     user_service: Arc<U>,
     order_service_factory: Arc<F>,
+    rate_limiter: Arc<RateLimiter>,
+    tls: Option<Arc<Tls>>,
+    http_upstream: Option<(SocketAddr, ProxySecret)>,
+    shutdown: CancellationToken,
 ) -> Result<(), Box<dyn std::error::Error>>
 where
     U: UserService + 'static,
     F: OrderServiceFactory + 'static,
 {
     let cfg = Config::from_env();
-    let addr = format!("{}:{}", cfg.host, cfg.grpc_port).parse()?;
 
-    println!("Starting gRPC server on grpc://{}", addr);
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(health::report(
+        health_reporter,
+        user_service.clone(),
+        order_service_factory.clone(),
+        Duration::from_secs(cfg.health_check_interval),
+        shutdown.clone(),
+    ));
 
-    let user_endpoint = UserEndpoint::new(user_service);
-    let order_endpoint = OrderEndpoint::new(order_service_factory);
+    let reflection_service = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build_v1()?;
+    // Older grpcurl and IDE plugins still only speak v1alpha
+    let reflection_service_v1alpha = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build_v1alpha()?;
 
-    Server::builder()
-        .add_service(proto::user_service_server::UserServiceServer::new(
-            user_endpoint,
-        ))
-        .add_service(proto::order_service_server::OrderServiceServer::new(
-            order_endpoint,
+    let routes = routes(user_service, order_service_factory)
+        .add_service(health_service)
+        .add_service(reflection_service)
+        .add_service(reflection_service_v1alpha);
+    // Around the gRPC routes only: in single port mode REST requests, preflights included,
+    // are forwarded to actix, which applies its own CORS
+    let routes = Routes::from(routes.into_axum_router().layer(web::cors(&cfg.cors_origins)?));
+
+    let (addr, routes) = match http_upstream {
+        Some((upstream, secret)) => {
+            let addr: SocketAddr = format!("{}:{}", cfg.host, cfg.http_port).parse()?;
+            let scheme = if tls.is_some() { "https" } else { "http" };
+            log::info!("Starting HTTP + gRPC server on {scheme}://{}", addr);
+            (addr, single_port::routes(routes, upstream, secret))
+        }
+        None => {
+            let addr: SocketAddr = format!("{}:{}", cfg.host, cfg.grpc_port).parse()?;
+            let scheme = if tls.is_some() { "grpcs" } else { "grpc" };
+            log::info!("Starting gRPC server on {scheme}://{}", addr);
+            (addr, routes)
+        }
+    };
+
+    let shutdown_delay = Duration::from_secs(cfg.shutdown_delay);
+    let shutdown_timeout = Duration::from_secs(cfg.shutdown_timeout);
+    let router = Server::builder()
+        // Browsers (gRPC-Web) and, in single port mode, REST clients speak HTTP/1.1
+        .accept_http1(true)
+        .layer(web::GrpcWebLayer)
+        // Inside gRPC-Web translation, where grpc-status is still a trailer
+        .layer(trace::TraceLayer)
+        .layer(metrics::MetricsLayer)
+        // Outside the access log, so it records who the certificate names
+        .layer(tls::ClientCertLayer::new(&cfg.tls_client_roles))
+        .layer(access_log::AccessLogLayer::new(TrustedProxies::parse(&cfg.trusted_proxies)?))
+        // Inside the access log and metrics, so rejected calls are recorded
+        .layer(rate_limit::RateLimitLayer::new(
+            rate_limiter,
+            TrustedProxies::parse(&cfg.trusted_proxies)?,
         ))
-        .serve(addr)
-        .await?;
+        .add_routes(routes);
+
+    let stop = crate::shutdown::stop_accepting(shutdown.clone(), shutdown_delay);
+    let server: BoxFuture<'_, Result<(), tonic::transport::Error>> = match tls {
+        Some(tls) => {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            Box::pin(router.serve_with_incoming_shutdown(tls::incoming(listener, tls), stop))
+        }
+        None => Box::pin(router.serve_with_shutdown(addr, stop)),
+    };
+
+    // Tonic waits for every open call, a stuck one must not hold up the exit
+    tokio::select! {
+        result = server => result?,
+        _ = crate::shutdown::drain_expired(shutdown, shutdown_delay, shutdown_timeout) => {
+            log::warn!("gRPC calls still running after SHUTDOWN_TIMEOUT were cut");
+        }
+    }
 
     Ok(())
 }
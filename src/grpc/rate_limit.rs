@@ -0,0 +1,112 @@
+use super::single_port::is_grpc;
+use super::tls::remote_addr;
+use crate::auth::Claims;
+use crate::logging::TrustedProxies;
+use crate::rate_limit::{self, Decision, RateLimiter};
+use futures_util::future::BoxFuture;
+use http::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
+use http::{Request, Response};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::Status;
+use tonic::body::Body;
+use tower::{Layer, Service};
+
+const FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const API_KEY: HeaderName = HeaderName::from_static(rate_limit::API_KEY);
+
+fn insert_headers(headers: &mut HeaderMap, decision: &Decision) {
+    for (name, value) in decision.headers() {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+/// Spends one request of the caller's budget per gRPC call, see `crate::rate_limit`.
+/// Over budget calls fail with RESOURCE_EXHAUSTED and a RetryInfo detail,
+/// RateLimit-* headers are sent as response metadata.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: Arc<RateLimiter>,
+    proxies: Arc<TrustedProxies>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limiter: Arc<RateLimiter>, proxies: TrustedProxies) -> Self {
+        Self {
+            limiter,
+            proxies: Arc::new(proxies),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limiter: self.limiter.clone(),
+            proxies: self.proxies.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    limiter: Arc<RateLimiter>,
+    proxies: Arc<TrustedProxies>,
+}
+
+impl<S> Service<Request<Body>> for RateLimitService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // In single port mode REST requests pass through here too, actix limits those
+        if !self.limiter.enabled() || !is_grpc(&req) {
+            return Box::pin(self.inner.call(req));
+        }
+
+        let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+        let peer = remote_addr(&req).map(|addr| addr.ip());
+        let caller = self.limiter.caller(
+            header(AUTHORIZATION).and_then(|v| v.strip_prefix("Bearer ")),
+            // Added by ClientCertLayer, further out
+            req.extensions().get::<Claims>(),
+            header(API_KEY),
+            self.proxies.client_ip(peer, header(FORWARDED_FOR)),
+        );
+        let limiter = self.limiter.clone();
+
+        // Take the ready service and leave a fresh clone behind, as poll_ready was called on self
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let Some(decision) = limiter.check(req.uri().path(), &caller).await else {
+                return inner.call(req).await;
+            };
+
+            let mut res = if decision.allowed {
+                inner.call(req).await?
+            } else {
+                Status::from(decision.error()).into_http()
+            };
+            insert_headers(res.headers_mut(), &decision);
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,151 @@
+use super::tls::remote_addr;
+use crate::auth::Claims;
+use crate::logging::{
+    PROXIED_CLAIMS_HEADER, PROXIED_PEER_HEADER, PROXY_SECRET_HEADER, ProxySecret,
+};
+use crate::telemetry::{self, HeaderExtractor};
+use axum::body::Body;
+use futures_util::future::BoxFuture;
+use http::header::{HOST, HeaderValue};
+use http::{Request, Response, StatusCode, Version};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::task::{Context, Poll};
+use tonic::service::Routes;
+use tower::{Service, ServiceExt};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Wrap gRPC routes so requests that are not gRPC are forwarded to the actix server
+/// listening on `http_upstream`
+pub fn routes(grpc: Routes, http_upstream: SocketAddr, secret: ProxySecret) -> Routes {
+    let steer = GrpcOrHttp {
+        grpc: grpc.into_axum_router(),
+        http: HttpProxy::new(http_upstream, secret),
+    };
+    Routes::from(axum::Router::new().fallback_service(steer))
+}
+
+pub fn is_grpc<B>(req: &Request<B>) -> bool {
+    req.headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/grpc"))
+}
+
+#[derive(Clone)]
+struct GrpcOrHttp {
+    grpc: axum::Router,
+    http: HttpProxy,
+}
+
+impl Service<Request<Body>> for GrpcOrHttp {
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if is_grpc(&req) {
+            let grpc = self.grpc.clone();
+            Box::pin(grpc.oneshot(req))
+        } else {
+            let http = self.http.clone();
+            Box::pin(async move { Ok(http.forward(req).await) })
+        }
+    }
+}
+
+/// Minimal reverse proxy to the in-process actix server over loopback HTTP/1.1
+#[derive(Clone)]
+struct HttpProxy {
+    client: Client<HttpConnector, Body>,
+    upstream: SocketAddr,
+    secret: ProxySecret,
+}
+
+impl HttpProxy {
+    fn new(upstream: SocketAddr, secret: ProxySecret) -> Self {
+        Self {
+            client: Client::builder(TokioExecutor::new()).build_http(),
+            upstream,
+            secret,
+        }
+    }
+
+    async fn forward(&self, mut req: Request<Body>) -> Response<Body> {
+        // HTTP/2 clients send :authority instead of Host, keep it for actix
+        if !req.headers().contains_key(HOST)
+            && let Some(authority) = req.uri().authority()
+            && let Ok(host) = HeaderValue::from_str(authority.as_str())
+        {
+            req.headers_mut().insert(HOST, host);
+        }
+
+        // actix sees loopback as the peer, pass the real client address and the claims of
+        // its certificate along with the secret that proves they come from here.
+        // Client-sent copies are dropped first.
+        let headers = req.headers_mut();
+        headers.remove(PROXIED_PEER_HEADER);
+        headers.remove(PROXIED_CLAIMS_HEADER);
+        headers.remove(PROXY_SECRET_HEADER);
+        let peer = remote_addr(&req).and_then(|peer| HeaderValue::from_str(&peer.to_string()).ok());
+        let claims = req
+            .extensions()
+            .get::<Claims>()
+            .and_then(|claims| serde_json::to_vec(claims).ok())
+            .and_then(|json| HeaderValue::from_bytes(&json).ok());
+        if let Ok(secret) = HeaderValue::from_str(self.secret.as_str()) {
+            let headers = req.headers_mut();
+            if let Some(peer) = peer {
+                headers.insert(PROXIED_PEER_HEADER, peer);
+            }
+            if let Some(claims) = claims {
+                headers.insert(PROXIED_CLAIMS_HEADER, claims);
+            }
+            headers.insert(PROXY_SECRET_HEADER, secret);
+        }
+
+        let path_and_query = req
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or("/");
+        let uri = format!("http://{}{}", self.upstream, path_and_query);
+        match uri.parse() {
+            Ok(uri) => *req.uri_mut() = uri,
+            Err(_) => return status_response(StatusCode::BAD_REQUEST),
+        }
+        *req.version_mut() = Version::HTTP_11;
+
+        // Continue the caller's trace through the hop to actix
+        let span = tracing::info_span!(
+            "single port proxy",
+            otel.kind = "client",
+            http.request.method = req.method().as_str(),
+            url.path = req.uri().path(),
+        );
+        span.set_parent(telemetry::extract(&HeaderExtractor(req.headers())));
+        telemetry::inject(&span, req.headers_mut());
+
+        match self.client.request(req).instrument(span).await {
+            Ok(res) => res.map(Body::new),
+            Err(e) => {
+                log::error!("single port proxy to {} failed: {e}", self.upstream);
+                status_response(StatusCode::BAD_GATEWAY)
+            }
+        }
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = status;
+    res
+}
@@ -0,0 +1,143 @@
+use crate::tls::{Tls, client_claims, client_roles};
+use futures_util::Stream;
+use http::Request;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tower::{Layer, Service};
+
+/// A client that has not finished its handshake by then is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Pause after a failed accept, doubled up to ACCEPT_BACKOFF_MAX while failures repeat.
+/// Errors like EMFILE last until connections close, retrying at once would spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// Connections handed to tonic once their handshake succeeded. Handshakes run on their
+/// own tasks, so a slow client never holds up accepting the next one.
+pub fn incoming(
+    listener: TcpListener,
+    tls: Arc<Tls>,
+) -> impl Stream<Item = std::io::Result<TlsStream<TcpStream>>> {
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        let mut backoff = ACCEPT_BACKOFF;
+        loop {
+            let (stream, peer) = tokio::select! {
+                // The server stopped and dropped the stream
+                _ = tx.closed() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        log::warn!("gRPC accept failed, retrying in {backoff:?}: {e}");
+                        tokio::select! {
+                            _ = tx.closed() => break,
+                            _ = tokio::time::sleep(backoff) => {}
+                        }
+                        backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                        continue;
+                    }
+                },
+            };
+            backoff = ACCEPT_BACKOFF;
+            let Some(acceptor) = tls.grpc_acceptor() else {
+                continue;
+            };
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Ok(Err(e)) => log::debug!("TLS handshake with {peer} failed: {e}"),
+                    Err(_) => log::debug!("TLS handshake with {peer} timed out"),
+                }
+            });
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+/// Address of the connection a call came in on, over plaintext or TLS
+pub fn remote_addr<B>(req: &Request<B>) -> Option<SocketAddr> {
+    let extensions = req.extensions();
+    extensions
+        .get::<TcpConnectInfo>()
+        .or_else(|| {
+            extensions
+                .get::<TlsConnectInfo<TcpConnectInfo>>()
+                .map(|info| info.get_ref())
+        })
+        .and_then(|info| info.remote_addr())
+}
+
+/// Adds the `Claims` of a verified client certificate to the request extensions, where
+/// the rate limiter and the access log find them. In single port mode they are passed on
+/// to actix with REST requests, whose routes accept them in place of a token.
+#[derive(Clone)]
+pub struct ClientCertLayer {
+    /// Roles granted per subject, from TLS_CLIENT_ROLES
+    roles: Arc<HashMap<String, Vec<String>>>,
+}
+
+impl ClientCertLayer {
+    pub fn new(roles: &[(String, String)]) -> Self {
+        Self {
+            roles: Arc::new(client_roles(roles)),
+        }
+    }
+}
+
+impl<S> Layer<S> for ClientCertLayer {
+    type Service = ClientCertService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClientCertService {
+            inner,
+            roles: self.roles.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ClientCertService<S> {
+    inner: S,
+    roles: Arc<HashMap<String, Vec<String>>>,
+}
+
+impl<S, B> Service<Request<B>> for ClientCertService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        // rustls only hands over certificates that passed verification against TLS_CLIENT_CA
+        let claims = req
+            .extensions()
+            .get::<TlsConnectInfo<TcpConnectInfo>>()
+            .and_then(|info| info.peer_certs())
+            .and_then(|certs| {
+                certs
+                    .first()
+                    .and_then(|cert| client_claims(cert, &self.roles))
+            });
+        if let Some(claims) = claims {
+            req.extensions_mut().insert(claims);
+        }
+        self.inner.call(req)
+    }
+}
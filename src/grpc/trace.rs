@@ -0,0 +1,132 @@
+use super::single_port::is_grpc;
+use crate::telemetry::{self, HeaderExtractor};
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use http::header::HeaderMap;
+use http::{Request, Response};
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tonic::Status;
+use tonic::body::Body;
+use tower::{Layer, Service};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+fn record_status(span: &tracing::Span, headers: &HeaderMap) {
+    let Some(code) = headers
+        .get("grpc-status")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<i32>().ok())
+    else {
+        return;
+    };
+    span.record("rpc.grpc.status_code", code);
+    if code != 0 {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+/// Keeps the call's span open until the last message and the trailers are sent
+struct TracedBody {
+    body: Body,
+    span: tracing::Span,
+}
+
+impl HttpBody for TracedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let span = self.span.clone();
+        let _entered = span.enter();
+        let poll = Pin::new(&mut self.body).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(trailers) = frame.trailers_ref()
+        {
+            record_status(&span, trailers);
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// Opens a server span for every gRPC call, continuing the caller's W3C `traceparent`
+#[derive(Clone, Default)]
+pub struct TraceLayer;
+
+impl<S> Layer<S> for TraceLayer {
+    type Service = TraceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct TraceService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for TraceService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if !is_grpc(&req) {
+            return Box::pin(self.inner.call(req));
+        }
+
+        // /package.Service/Method
+        let path = req.uri().path();
+        let (service, method) = path
+            .trim_start_matches('/')
+            .split_once('/')
+            .unwrap_or((path, ""));
+        let span = tracing::info_span!(
+            "gRPC call",
+            otel.name = path.trim_start_matches('/'),
+            otel.kind = "server",
+            otel.status_code = tracing::field::Empty,
+            rpc.system = "grpc",
+            rpc.service = service,
+            rpc.method = method,
+            rpc.grpc.status_code = tracing::field::Empty,
+        );
+        span.set_parent(telemetry::extract(&HeaderExtractor(req.headers())));
+
+        // Inner layers (the access log) see the span as current
+        let fut = span.in_scope(|| self.inner.call(req));
+
+        Box::pin(
+            async move {
+                let res = fut.await?;
+                let span = tracing::Span::current();
+                // Trailers-only responses (errors) carry grpc-status in the headers
+                record_status(&span, res.headers());
+                Ok(res.map(|body| Body::new(TracedBody { body, span })))
+            }
+            .instrument(span),
+        )
+    }
+}
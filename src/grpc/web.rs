@@ -0,0 +1,98 @@
+use futures_util::future::BoxFuture;
+use http::header::{CONTENT_TYPE, HeaderName, HeaderValue, InvalidHeaderValue};
+use http::{Method, Request, Response};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tonic::body::Body;
+use tower::{Layer, Service, ServiceExt};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer, ExposeHeaders};
+
+const ALLOW_HEADERS: [&str; 7] = [
+    "x-grpc-web",
+    "x-user-agent",
+    "grpc-timeout",
+    "content-type",
+    "authorization",
+    "x-api-key",
+    "x-request-id",
+];
+const EXPOSE_HEADERS: [&str; 8] = [
+    "grpc-status",
+    "grpc-message",
+    "grpc-status-details-bin",
+    "x-request-id",
+    "ratelimit-policy",
+    "ratelimit-limit",
+    "ratelimit-remaining",
+    "ratelimit-reset",
+];
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(86400);
+
+/// CORS for gRPC-Web calls, allowing the same origins as the actix server
+pub fn cors(origins: &[String]) -> Result<CorsLayer, InvalidHeaderValue> {
+    let origins = origins
+        .iter()
+        .map(|origin| HeaderValue::from_str(origin))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_credentials(true)
+        .allow_methods(AllowMethods::list([Method::POST]))
+        .allow_headers(AllowHeaders::list(ALLOW_HEADERS.map(HeaderName::from_static)))
+        .expose_headers(ExposeHeaders::list(EXPOSE_HEADERS.map(HeaderName::from_static)))
+        .max_age(PREFLIGHT_MAX_AGE))
+}
+
+fn is_grpc_web<B>(req: &Request<B>) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/grpc-web"))
+}
+
+/// Translates gRPC-Web calls (binary and text) into plain gRPC with tonic-web.
+/// tonic-web answers every other HTTP/1.1 request with 400, so those, CORS preflights
+/// and REST in single port mode included, pass through untouched instead.
+#[derive(Clone)]
+pub struct GrpcWebLayer;
+
+impl<S: Clone> Layer<S> for GrpcWebLayer {
+    type Service = GrpcWebService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcWebService {
+            web: tonic_web::GrpcWebLayer::new().layer(inner.clone()),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GrpcWebService<S> {
+    web: tonic_web::GrpcWebService<S>,
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for GrpcWebService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Each call drives a clone of the branch it takes to readiness
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if is_grpc_web(&req) {
+            Box::pin(self.web.clone().oneshot(req))
+        } else {
+            Box::pin(self.inner.clone().oneshot(req))
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::controllers::health::{HealthBody, HealthController};
+use crate::services::{HealthChecks, HealthReport};
+use actix_web::{HttpResponse, web};
+
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses(
+        (status = 200, description = "The process is up and serving HTTP", body = HealthBody, content_type = "application/health+json"),
+    )
+)]
+/// Liveness: runs no checks, so a failing dependency never gets the pod restarted
+pub async fn live() -> HttpResponse {
+    HealthController(HealthReport::default()).into_http()
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every check passed", body = HealthBody, content_type = "application/health+json"),
+        (status = 503, description = "A check failed or shutdown has started", body = HealthBody, content_type = "application/health+json"),
+    )
+)]
+/// Readiness: runs every check, and fails as soon as shutdown starts
+pub async fn ready(checks: web::Data<HealthChecks>) -> HttpResponse {
+    HealthController(checks.ready().await).into_http()
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/startup",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every check has passed once", body = HealthBody, content_type = "application/health+json"),
+        (status = 503, description = "Still starting: a check has not passed yet", body = HealthBody, content_type = "application/health+json"),
+    )
+)]
+/// Startup: passes for good once every check has passed
+pub async fn startup(checks: web::Data<HealthChecks>) -> HttpResponse {
+    HealthController(checks.startup().await).into_http()
+}
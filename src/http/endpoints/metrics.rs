@@ -0,0 +1,13 @@
+use crate::metrics::{self, Metrics};
+use crate::services::AppError;
+use actix_web::{HttpResponse, ResponseError};
+
+/// Prometheus scrape target
+pub async fn get_metrics() -> HttpResponse {
+    match Metrics::shared().render() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(metrics::CONTENT_TYPE)
+            .body(body),
+        Err(e) => AppError::Internal(e).error_response(),
+    }
+}
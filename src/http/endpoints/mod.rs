@@ -1,2 +1,5 @@
+pub mod health;
+pub mod metrics;
 pub mod v1;
-pub mod v2;
\ No newline at end of file
+pub mod v2;
+pub mod versions;
\ No newline at end of file
@@ -0,0 +1,81 @@
+use crate::controllers::error::Problem;
+use crate::controllers::import::{
+    ImportJobBody, ImportJobController, ImportLimits, ImportQuery, ImportUpload,
+    ImportUploadController,
+};
+use crate::services::{AppError, ImportJobs, OrderServiceFactory, UserService};
+use actix_multipart::Multipart;
+use actix_web::{Error, HttpResponse, web};
+use std::sync::Arc;
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/imports/{kind}",
+    tag = "imports",
+    params(("kind" = String, Path, description = "`orders` or `users`"), ImportQuery),
+    request_body(content = ImportUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Import finished", body = ImportJobBody),
+        (status = 202, description = "Import continues in the background, poll Location", body = ImportJobBody),
+        (status = 400, description = "Invalid upload", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Requires the admin role", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "Upload larger than IMPORT_MAX_BYTES", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "Unknown file format", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+/// Admin only: imports a CSV or JSON Lines upload of orders or users.
+/// Small uploads are imported before responding, larger ones continue in the background.
+pub async fn import(
+    kind: web::Path<String>,
+    query: web::Query<ImportQuery>,
+    multipart: Multipart,
+    limits: web::Data<ImportLimits>,
+    jobs: web::Data<ImportJobs>,
+    factory: web::Data<Arc<dyn OrderServiceFactory>>,
+    user_service: web::Data<Arc<dyn UserService>>,
+) -> Result<HttpResponse, Error> {
+    let upload =
+        ImportUploadController::read(multipart, query.format.as_deref(), limits.max_bytes).await?;
+    // Parsing is CPU bound, keep it off the worker's event loop
+    let kind = kind.into_inner();
+    let rows = web::block(move || upload.parse(&kind)).await??;
+    let dry_run = query.dry_run;
+    let job = jobs.create(&rows, dry_run)?;
+    let inline = rows.len() <= limits.inline_rows;
+    let orders = factory.create();
+    let users = user_service.get_ref().clone();
+
+    let jobs = jobs.into_inner();
+    let id = job.id.clone();
+    let done = jobs.clone().start(id, rows, dry_run, orders, users);
+    if !inline {
+        return Ok(ImportJobController::from_job(job).into_accepted());
+    }
+    let job = match done.await {
+        Ok(result) => result?,
+        // Stopped before sending its result, the job records how far it got
+        Err(_) => jobs.get(&job.id)?,
+    };
+    Ok(ImportJobController::from_job(job).into_http())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/imports/jobs/{id}",
+    tag = "imports",
+    params(("id" = String, Path, description = "Job id returned by the upload")),
+    responses(
+        (status = 200, description = "Progress and row errors", body = ImportJobBody),
+        (status = 403, description = "Requires the admin role", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown or expired job", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+pub async fn get_job(
+    id: web::Path<String>,
+    jobs: web::Data<ImportJobs>,
+) -> Result<HttpResponse, AppError> {
+    let job = jobs.get(&id)?;
+    Ok(ImportJobController::from_job(job).into_http())
+}
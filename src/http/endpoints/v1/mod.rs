@@ -1,19 +1,55 @@
+pub mod import;
 pub mod order;
 pub mod user;
 
 use actix_web::web;
 
+use crate::http::middlewares::cache::HttpCache;
 use crate::http::middlewares::jwt_authorize::JwtAuth;
 
+// Auth wraps resources, not scopes: a scope's middleware also runs for paths none of its
+// resources match, which fall through to the gateway and get its 404 or the rule's auth
 pub fn routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/users")
-            .wrap(JwtAuth::new())
-            .route("", web::get().to(user::get_users))
+            .service(
+                web::resource("")
+                    .wrap(HttpCache::new())
+                    .wrap(JwtAuth::new())
+                    .route(web::get().to(user::get_users))
+            )
     );
     cfg.service(
         web::scope("/orders")
-            .wrap(JwtAuth::new())
-            .route("/{user_id}", web::get().to(order::get_orders))
+            .service(
+                web::resource("")
+                    .wrap(JwtAuth::new())
+                    .route(web::post().to(order::create_order))
+            )
+            // Before /{user_id}, which would otherwise read `export` as a user id
+            .service(
+                web::resource("/export")
+                    .wrap(JwtAuth::with_roles(vec!["admin"]))
+                    .route(web::get().to(order::export_orders))
+            )
+            .service(
+                web::resource("/{user_id}")
+                    .wrap(HttpCache::new())
+                    .wrap(JwtAuth::new())
+                    .route(web::get().to(order::get_orders))
+            )
     );
-}
\ No newline at end of file
+    cfg.service(
+        web::scope("/imports")
+            .service(
+                web::resource("/jobs/{id}")
+                    .wrap(JwtAuth::with_roles(vec!["admin"]))
+                    .route(web::get().to(import::get_job))
+            )
+            .service(
+                web::resource("/{kind}")
+                    .wrap(JwtAuth::with_roles(vec!["admin"]))
+                    .route(web::post().to(import::import))
+            )
+    );
+}
@@ -1,15 +1,84 @@
-use crate::controllers::order::OrderController;
-use crate::services::OrderServiceFactory;
+use crate::controllers::error::Problem;
+use crate::controllers::export::{ExportQuery, OrderExportController};
+use crate::controllers::order::{CreatedOrderController, OrderController};
+use crate::http::extractors::{Body, Valid};
+use crate::proto::{CreateOrderRequest, GetOrdersRequest, GetOrdersResponse, Order};
+use crate::services::{AppError, OrderServiceFactory};
 use actix_web::{Responder, web};
 use std::sync::Arc;
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/orders/{user_id}",
+    tag = "orders",
+    params(("user_id" = String, Path, description = "Owner of the orders")),
+    responses(
+        (status = 200, description = "Orders of the user", body = GetOrdersResponse),
+        (status = 400, description = "Invalid user_id", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 /// Scoped: factory.create() is called per request, creating a new OrderService instance
 pub async fn get_orders(
     factory: web::Data<Arc<dyn OrderServiceFactory>>,
-    path: web::Path<String>,
-) -> impl Responder {
+    path: Valid<web::Path<GetOrdersRequest>>,
+) -> Result<impl Responder, AppError> {
     let service = factory.create();
-    let user_id = path.into_inner();
-    let orders = service.get_orders(&user_id).await;
-    OrderController::from_orders(orders).to_http()
+    let user_id = path.into_inner().user_id;
+    let orders = service.get_orders(&user_id).await?;
+    Ok(OrderController::from_orders(orders).into_http())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/orders",
+    tag = "orders",
+    request_body(
+        content = CreateOrderRequest,
+        description = "JSON, or Protobuf, MessagePack or CBOR with the matching Content-Type"
+    ),
+    responses(
+        (status = 201, description = "The created order", body = Order),
+        (status = 400, description = "Invalid order", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "Unsupported Content-Type", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+/// Publishes an order created event, visible to gRPC WatchOrders subscribers
+pub async fn create_order(
+    factory: web::Data<Arc<dyn OrderServiceFactory>>,
+    body: Valid<Body<CreateOrderRequest>>,
+) -> Result<impl Responder, AppError> {
+    let service = factory.create();
+    let order = service.create_order(body.into_inner().into()).await?;
+    Ok(CreatedOrderController::from_order(order).into_http())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/orders/export",
+    tag = "orders",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "Matching orders, one per line", content(
+            (String = "application/x-ndjson"),
+            (String = "text/csv"),
+        )),
+        (status = 400, description = "Invalid parameters", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Requires the admin role", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+/// Admin only: streams every matching order as NDJSON or CSV from a snapshot taken
+/// when the request starts, so orders created meanwhile are not included
+pub async fn export_orders(
+    factory: web::Data<Arc<dyn OrderServiceFactory>>,
+    query: web::Query<ExportQuery>,
+) -> Result<impl Responder, AppError> {
+    let (controller, filter) = OrderExportController::from_query(query.into_inner())?;
+    let pages = factory.create().export_orders(filter)?;
+    Ok(controller.into_http(pages))
 }
@@ -1,9 +1,23 @@
+use crate::controllers::error::Problem;
 use crate::controllers::user::UserController;
-use crate::services::UserService;
+use crate::proto::GetUsersResponse;
+use crate::services::{AppError, UserService};
 use actix_web::{Responder, web};
 use std::sync::Arc;
 
-pub async fn get_users(service: web::Data<Arc<dyn UserService>>) -> impl Responder {
-    let users = service.get_users().await;
-    UserController::from_users(users).to_http()
+#[utoipa::path(
+    get,
+    path = "/api/v1/users",
+    tag = "users",
+    responses(
+        (status = 200, description = "Names of every user", body = GetUsersResponse),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+pub async fn get_users(
+    service: web::Data<Arc<dyn UserService>>,
+) -> Result<impl Responder, AppError> {
+    let users = service.get_users().await?;
+    Ok(UserController::from_users(users).into_http())
 }
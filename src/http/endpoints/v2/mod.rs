@@ -3,13 +3,27 @@ pub mod user;
 
 use actix_web::web;
 
+use crate::http::middlewares::cache::HttpCache;
+use crate::http::middlewares::jwt_authorize::JwtAuth;
+
+// Auth wraps resources, see v1
 pub fn routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/users")
-            .route("", web::get().to(user::get_users_v2))
+            .service(
+                web::resource("")
+                    .wrap(HttpCache::new())
+                    .wrap(JwtAuth::new())
+                    .route(web::get().to(user::get_users_v2))
+            )
     );
     cfg.service(
         web::scope("/orders")
-            .route("/{user_id}", web::get().to(order::get_orders))
+            .service(
+                web::resource("/{user_id}")
+                    .wrap(HttpCache::new())
+                    .wrap(JwtAuth::new())
+                    .route(web::get().to(order::get_orders))
+            )
     );
 }
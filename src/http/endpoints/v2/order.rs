@@ -1,21 +1,37 @@
+use crate::controllers::error::Problem;
 use crate::controllers::order::OrderController;
-use crate::services::OrderServiceTransient;
+use crate::http::extractors::Valid;
+use crate::proto::{GetOrdersRequest, GetOrdersResponse};
+use crate::services::{AppError, OrderServiceTransient};
 use actix_web::{Responder, web};
 
+#[utoipa::path(
+    get,
+    path = "/api/v2/orders/{user_id}",
+    operation_id = "get_orders_v2",
+    tag = "orders",
+    params(("user_id" = String, Path, description = "Owner of the orders")),
+    responses(
+        (status = 200, description = "Orders of the user", body = GetOrdersResponse),
+        (status = 400, description = "Invalid user_id", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
 /// Transient: create_fn() is called every time we need a service instance
 /// Multiple calls within the same request = multiple instances
 pub async fn get_orders(
     create_fn: web::Data<OrderServiceTransient>,
-    path: web::Path<String>,
-) -> impl Responder {
-    let user_id = path.into_inner();
+    path: Valid<web::Path<GetOrdersRequest>>,
+) -> Result<impl Responder, AppError> {
+    let user_id = path.into_inner().user_id;
 
     // Transient: new instance for first operation
     let service1 = create_fn();
-    let orders = service1.get_orders(&user_id).await;
+    let orders = service1.get_orders(&user_id).await?;
 
     // Transient: could create another instance for different operation
     // let service2 = create_fn();  // Different instance than service1
 
-    OrderController::from_orders(orders).to_http()
+    Ok(OrderController::from_orders(orders).into_http())
 }
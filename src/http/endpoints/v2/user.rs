@@ -1,19 +1,23 @@
-use actix_web::{HttpResponse, Responder, web};
-use serde::Serialize;
+use crate::controllers::error::Problem;
+use crate::controllers::user::UserV2Controller;
+use crate::proto::GetUsersV2Response;
+use crate::services::{AppError, UserService};
+use actix_web::{Responder, web};
+use std::sync::Arc;
 
-#[derive(Serialize)]
-pub struct UserV2 {
-    pub id: u32,
-    pub name: String,
-    pub email: String,
+#[utoipa::path(
+    get,
+    path = "/api/v2/users",
+    tag = "users",
+    responses(
+        (status = 200, description = "Every user", body = GetUsersV2Response),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+pub async fn get_users_v2(
+    service: web::Data<Arc<dyn UserService>>,
+) -> Result<impl Responder, AppError> {
+    let users = service.list_users().await?;
+    Ok(UserV2Controller::from_users(users).into_http())
 }
-
-pub async fn get_users_v2() -> impl Responder {
-    HttpResponse::Ok().json(vec![
-        UserV2 {
-            id: 1,
-            name: "Alice V2".into(),
-            email: "alice@example.com".into(),
-        }
-    ])
-}
\ No newline at end of file
@@ -0,0 +1,18 @@
+use crate::controllers::error::Problem;
+use crate::http::middlewares::api_version::{ApiVersionBody, ApiVersions};
+use actix_web::{HttpResponse, web};
+
+#[utoipa::path(
+    get,
+    path = "/api/versions",
+    tag = "versions",
+    responses(
+        (status = 200, description = "Every API version with its deprecation and usage since startup", body = Vec<ApiVersionBody>),
+        (status = 403, description = "Requires the admin role", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+/// Admin only: shows which versions are still called, and how they pick their version
+pub async fn get_versions(versions: web::Data<ApiVersions>) -> HttpResponse {
+    HttpResponse::Ok().json(versions.report())
+}
@@ -0,0 +1,123 @@
+use crate::controllers::negotiation::{Format, SUPPORTED_MEDIA_TYPES};
+use crate::services::{AppError, FieldViolation};
+use crate::validation::Validator;
+use actix_web::dev::Payload;
+use actix_web::error::ErrorUnsupportedMediaType;
+use actix_web::{Error, FromRequest, HttpRequest, web};
+use futures_util::future::LocalBoxFuture;
+use std::ops::Deref;
+
+/// Extractors whose payload is a proto message
+pub trait Extracted: FromRequest + Deref {
+    fn into_message(self) -> Self::Target
+    where
+        Self::Target: Sized;
+}
+
+impl<M> Extracted for web::Json<M>
+where
+    M: serde::de::DeserializeOwned,
+{
+    fn into_message(self) -> M {
+        self.into_inner()
+    }
+}
+
+impl<M> Extracted for web::Path<M>
+where
+    M: serde::de::DeserializeOwned,
+{
+    fn into_message(self) -> M {
+        self.into_inner()
+    }
+}
+
+impl<M> Extracted for web::Query<M>
+where
+    M: serde::de::DeserializeOwned,
+{
+    fn into_message(self) -> M {
+        self.into_inner()
+    }
+}
+
+impl<M> Extracted for Body<M>
+where
+    M: serde::de::DeserializeOwned + prost::Message + Default + 'static,
+{
+    fn into_message(self) -> M {
+        self.0
+    }
+}
+
+/// Proto message request body in any negotiable format, chosen by Content-Type
+/// (JSON when absent). Unsupported types are rejected with 415.
+pub struct Body<M>(pub M);
+
+impl<M> Deref for Body<M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.0
+    }
+}
+
+impl<M> FromRequest for Body<M>
+where
+    M: serde::de::DeserializeOwned + prost::Message + Default + 'static,
+{
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let format = Format::from_content_type(req);
+        // Goes through web::Bytes so the PayloadConfig size limit still applies
+        let bytes = web::Bytes::from_request(req, payload);
+        Box::pin(async move {
+            let Some(format) = format else {
+                return Err(ErrorUnsupportedMediaType(format!(
+                    "supported media types: {SUPPORTED_MEDIA_TYPES}"
+                )));
+            };
+            let bytes = bytes.await?;
+            format
+                .decode(&bytes)
+                .map(Body)
+                .map_err(|e| AppError::Validation(vec![FieldViolation::new("body", &e)]).into())
+        })
+    }
+}
+
+/// Runs the `(validate.rules)` of the extracted proto message before the handler,
+/// e.g. `Valid<Body<CreateOrderRequest>>`. Fails with every violation at once.
+pub struct Valid<E: Extracted>(E)
+where
+    E::Target: Sized;
+
+impl<E: Extracted> Valid<E>
+where
+    E::Target: Sized,
+{
+    pub fn into_inner(self) -> E::Target {
+        self.0.into_message()
+    }
+}
+
+impl<E> FromRequest for Valid<E>
+where
+    E: Extracted + 'static,
+    E::Target: prost::Message + prost::Name + Sized,
+    E::Error: Into<Error>,
+{
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let extract = E::from_request(req, payload);
+        Box::pin(async move {
+            let extracted = extract.await.map_err(Into::into)?;
+            Validator::shared().validate(&*extracted)?;
+            Ok(Valid(extracted))
+        })
+    }
+}
@@ -0,0 +1,721 @@
+use crate::auth::Claims;
+use crate::controllers::error::Problem;
+use crate::controllers::negotiation::{Format, SUPPORTED_MEDIA_TYPES, not_acceptable};
+use super::middlewares::jwt_authorize::{authorize, extract_token};
+use super::middlewares::metrics::RouteTemplate;
+use actix_web::error::ErrorUnsupportedMediaType;
+use actix_web::http::header::VARY;
+use actix_web::http::{Method, StatusCode};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, ResponseError, web};
+use http_body_util::{BodyExt, Full};
+use percent_encoding::percent_decode_str;
+use prost_reflect::prost::Message;
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, ExtensionDescriptor, FieldDescriptor, Kind, MessageDescriptor,
+    MethodDescriptor, SerializeOptions, Value,
+};
+use serde_json::{Map, Value as Json};
+use tonic::body::Body;
+use tonic::service::Routes;
+use tower::ServiceExt;
+
+const HTTP_RULE_EXTENSION: &str = "google.api.http";
+const AUTH_RULE_EXTENSION: &str = "auth.rule";
+
+enum Segment {
+    Literal(String),
+    /// Dotted path to a request field, e.g. `user_id` or `filter.status`
+    Field(String),
+}
+
+enum BodyMapping {
+    None,
+    /// `body: "*"`: every field not bound by the path comes from the body
+    All,
+    /// `body: "field"`: the body is the value of one top-level field
+    Field(String),
+}
+
+/// Who may call a route, from the `(auth.rule)` option of its RPC
+#[derive(Clone)]
+enum Access {
+    Public,
+    /// A valid token with any of `roles` and all of `policies`, when given
+    Token {
+        roles: Option<Vec<String>>,
+        policies: Option<Vec<String>>,
+    },
+}
+
+impl Access {
+    fn parse(rpc: &MethodDescriptor, extension: Option<&ExtensionDescriptor>) -> Self {
+        let rule = extension
+            .filter(|extension| rpc.options().has_extension(extension))
+            .map(|extension| rpc.options().get_extension(extension).into_owned());
+        let Some(Value::Message(rule)) = rule else {
+            return Self::Token {
+                roles: None,
+                policies: None,
+            };
+        };
+        if rule
+            .get_field_by_name("public")
+            .is_some_and(|public| public.as_bool() == Some(true))
+        {
+            return Self::Public;
+        }
+        let strings = |name: &str| {
+            let values: Vec<String> = rule
+                .get_field_by_name(name)
+                .and_then(|list| {
+                    list.as_list().map(|items| {
+                        items
+                            .iter()
+                            .filter_map(|v| v.as_str().map(String::from))
+                            .collect()
+                    })
+                })
+                .unwrap_or_default();
+            Some(values).filter(|values| !values.is_empty())
+        };
+        Self::Token {
+            roles: strings("roles"),
+            policies: strings("policies"),
+        }
+    }
+}
+
+struct Rule {
+    method: Method,
+    segments: Vec<Segment>,
+    body: BodyMapping,
+    response_body: Option<String>,
+    rpc: MethodDescriptor,
+    /// gRPC request path, e.g. `/order.OrderService/GetOrders`
+    grpc_path: String,
+    access: Access,
+}
+
+/// A transcoded route as described in the OpenAPI document
+pub struct TranscodedRoute<'a> {
+    pub method: &'a Method,
+    /// Template with `{field}` placeholders, e.g. `/api/v1/orders/{user_id}`
+    pub path: String,
+    pub path_fields: Vec<&'a str>,
+    pub has_body: bool,
+    /// Set when the response is a single field of the RPC output
+    pub response_body: Option<&'a str>,
+    pub rpc: &'a MethodDescriptor,
+    /// Callable without a token
+    pub public: bool,
+}
+
+/// REST surface for every RPC annotated with `google.api.http` in `proto/*.proto`.
+/// Matched requests are turned into protobuf and handed to the same gRPC services
+/// the tonic server runs, without going over the network.
+pub struct Gateway {
+    rules: Vec<Rule>,
+    grpc: Routes,
+}
+
+impl Gateway {
+    pub fn new(file_descriptor_set: &[u8], grpc: Routes) -> Result<Self, String> {
+        let pool = DescriptorPool::decode(file_descriptor_set).map_err(|e| e.to_string())?;
+        let extension = pool
+            .get_extension_by_name(HTTP_RULE_EXTENSION)
+            .ok_or("google/api/annotations.proto is not part of the descriptor set")?;
+        let auth_extension = pool.get_extension_by_name(AUTH_RULE_EXTENSION);
+
+        let mut rules = Vec::new();
+        for service in pool.services() {
+            for rpc in service.methods() {
+                let options = rpc.options();
+                if !options.has_extension(&extension) {
+                    continue;
+                }
+                if rpc.is_client_streaming() || rpc.is_server_streaming() {
+                    log::warn!("{} is streaming and cannot be transcoded", rpc.full_name());
+                    continue;
+                }
+                let Value::Message(http_rule) = options.get_extension(&extension).into_owned()
+                else {
+                    continue;
+                };
+                let grpc_path = format!("/{}/{}", service.full_name(), rpc.name());
+                let access = Access::parse(&rpc, auth_extension.as_ref());
+                let mut bindings = vec![http_rule.clone()];
+                if let Some(additional) = http_rule.get_field_by_name("additional_bindings")
+                    && let Value::List(additional) = additional.as_ref()
+                {
+                    bindings.extend(additional.iter().filter_map(|b| b.as_message().cloned()));
+                }
+                for binding in bindings {
+                    rules.push(Rule::parse(&binding, &rpc, &grpc_path, &access)?);
+                }
+            }
+        }
+
+        Ok(Self { rules, grpc })
+    }
+
+    pub fn routes(&self) -> impl Iterator<Item = TranscodedRoute<'_>> {
+        self.rules.iter().map(|rule| TranscodedRoute {
+            method: &rule.method,
+            path: rule.path(),
+            path_fields: rule
+                .segments
+                .iter()
+                .filter_map(|segment| match segment {
+                    Segment::Field(field) => Some(field.as_str()),
+                    Segment::Literal(_) => None,
+                })
+                .collect(),
+            has_body: !matches!(rule.body, BodyMapping::None),
+            response_body: rule.response_body.as_deref(),
+            rpc: &rule.rpc,
+            public: matches!(rule.access, Access::Public),
+        })
+    }
+
+    fn find(&self, method: &Method, path: &str) -> Option<(&Rule, Vec<(&str, String)>)> {
+        let parts: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        self.rules
+            .iter()
+            .filter(|rule| rule.method == *method && rule.segments.len() == parts.len())
+            .find_map(|rule| {
+                let mut captures = Vec::new();
+                for (segment, part) in rule.segments.iter().zip(&parts) {
+                    match segment {
+                        Segment::Literal(literal) if literal == part => {}
+                        Segment::Literal(_) => return None,
+                        Segment::Field(field) => captures.push((
+                            field.as_str(),
+                            percent_decode_str(part).decode_utf8_lossy().into_owned(),
+                        )),
+                    }
+                }
+                Some((rule, captures))
+            })
+    }
+}
+
+fn string_field(message: &DynamicMessage, name: &str) -> String {
+    message
+        .get_field_by_name(name)
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+impl Rule {
+    /// Template with `{field}` placeholders, e.g. `/api/v1/orders/{user_id}`
+    fn path(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => format!("/{literal}"),
+                Segment::Field(field) => format!("/{{{field}}}"),
+            })
+            .collect()
+    }
+
+    fn parse(
+        binding: &DynamicMessage,
+        rpc: &MethodDescriptor,
+        grpc_path: &str,
+        access: &Access,
+    ) -> Result<Self, String> {
+        let (method, template) = ["get", "put", "post", "delete", "patch"]
+            .into_iter()
+            .find_map(|verb| {
+                let template = string_field(binding, verb);
+                (!template.is_empty()).then(|| (verb.to_uppercase(), template))
+            })
+            .or_else(|| {
+                let custom = binding.get_field_by_name("custom")?;
+                let custom = custom.as_message()?;
+                Some((string_field(custom, "kind"), string_field(custom, "path")))
+                    .filter(|(kind, _)| !kind.is_empty())
+            })
+            .ok_or_else(|| format!("{}: google.api.http has no pattern", rpc.full_name()))?;
+
+        let method = Method::from_bytes(method.as_bytes())
+            .map_err(|e| format!("{}: {e}", rpc.full_name()))?;
+
+        let segments = template
+            .trim_start_matches('/')
+            .split('/')
+            .map(
+                |segment| match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                    // Only single segment captures are supported: {field} or {field=*}
+                    Some(var) => match var.split_once('=') {
+                        None | Some((_, "*")) => Ok(Segment::Field(
+                            var.split('=').next().unwrap_or(var).to_string(),
+                        )),
+                        Some(_) => Err(format!(
+                            "{}: path template {template} is not supported",
+                            rpc.full_name()
+                        )),
+                    },
+                    None if segment.contains('{') => Err(format!(
+                        "{}: path template {template} is not supported",
+                        rpc.full_name()
+                    )),
+                    None => Ok(Segment::Literal(segment.to_string())),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let body = match string_field(binding, "body").as_str() {
+            "" => BodyMapping::None,
+            "*" => BodyMapping::All,
+            field => BodyMapping::Field(field.to_string()),
+        };
+        let response_body = Some(string_field(binding, "response_body")).filter(|f| !f.is_empty());
+
+        Ok(Self {
+            method,
+            segments,
+            body,
+            response_body,
+            rpc: rpc.clone(),
+            grpc_path: grpc_path.to_string(),
+            access: access.clone(),
+        })
+    }
+}
+
+/// Look up the descriptor of a dotted field path, e.g. `filter.status`
+fn field_descriptor(message: &MessageDescriptor, path: &str) -> Option<FieldDescriptor> {
+    let mut message = message.clone();
+    let mut names = path.split('.').peekable();
+    while let Some(name) = names.next() {
+        let field = message.get_field_by_name(name)?;
+        if names.peek().is_none() {
+            return Some(field);
+        }
+        message = field.kind().as_message()?.clone();
+    }
+    None
+}
+
+/// Bind a path or query parameter into the JSON form of the request message
+fn bind(
+    fields: &mut Map<String, Json>,
+    message: &MessageDescriptor,
+    path: &str,
+    raw: String,
+) -> Result<(), String> {
+    let field = field_descriptor(message, path).ok_or_else(|| format!("unknown field {path}"))?;
+    let value = match field.kind() {
+        Kind::Bool => Json::Bool(
+            raw.parse()
+                .map_err(|_| format!("{path}: expected true or false"))?,
+        ),
+        Kind::Message(_) => return Err(format!("{path}: message fields cannot be bound")),
+        // Proto3 JSON accepts numbers and enum names as strings
+        _ => Json::String(raw),
+    };
+
+    let mut target = fields;
+    let names: Vec<&str> = path.split('.').collect();
+    for name in &names[..names.len() - 1] {
+        target = target
+            .entry(name.to_string())
+            .or_insert_with(|| Json::Object(Map::new()))
+            .as_object_mut()
+            .ok_or_else(|| format!("{path}: conflicts with another parameter"))?;
+    }
+    let leaf = names[names.len() - 1].to_string();
+    if field.is_list() {
+        match target
+            .entry(leaf)
+            .or_insert_with(|| Json::Array(Vec::new()))
+        {
+            Json::Array(items) => items.push(value),
+            _ => return Err(format!("{path}: conflicts with another parameter")),
+        }
+    } else {
+        target.insert(leaf, value);
+    }
+    Ok(())
+}
+
+fn error_response(status: StatusCode, message: impl Into<String>) -> HttpResponse {
+    Problem::new(status).with_detail(message).to_http()
+}
+
+/// Same mapping as grpc-gateway
+fn http_status(code: tonic::Code) -> StatusCode {
+    use tonic::Code;
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::Cancelled => StatusCode::from_u16(499).unwrap_or(StatusCode::BAD_REQUEST),
+        Code::InvalidArgument | Code::OutOfRange => StatusCode::BAD_REQUEST,
+        Code::FailedPrecondition => StatusCode::BAD_REQUEST,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// JSON form of a request body in any supported format. Protobuf bodies need the message
+/// they encode, which is the request itself or the message type of the body field.
+fn body_json(
+    format: Format,
+    body: &[u8],
+    message: Option<MessageDescriptor>,
+) -> Result<Json, String> {
+    if body.is_empty() {
+        return Ok(Json::Object(Map::new()));
+    }
+    match format {
+        Format::Json => serde_json::from_slice(body).map_err(|e| format!("invalid JSON body: {e}")),
+        Format::MsgPack => {
+            rmp_serde::from_slice(body).map_err(|e| format!("invalid MessagePack body: {e}"))
+        }
+        Format::Cbor => ciborium::from_reader(body).map_err(|e| format!("invalid CBOR body: {e}")),
+        Format::Protobuf => {
+            let message = message.ok_or("the body field of this route is not a message")?;
+            let message = DynamicMessage::decode(message, body)
+                .map_err(|e| format!("invalid protobuf body: {e}"))?;
+            message
+                .serialize_with_options(serde_json::value::Serializer, &json_options())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Same field names and integer encoding as the serde-derived proto types
+fn json_options() -> SerializeOptions {
+    SerializeOptions::new()
+        .use_proto_field_name(true)
+        .stringify_64_bit_integers(false)
+        .skip_default_fields(false)
+}
+
+fn request_message(
+    rule: &Rule,
+    req: &HttpRequest,
+    format: Format,
+    body: &[u8],
+    captures: Vec<(&str, String)>,
+) -> Result<DynamicMessage, String> {
+    let input = rule.rpc.input();
+
+    let mut fields = Map::new();
+    match &rule.body {
+        BodyMapping::None => {}
+        BodyMapping::All => match body_json(format, body, Some(input.clone()))? {
+            Json::Object(object) => fields = object,
+            _ => return Err("request body must be an object".to_string()),
+        },
+        BodyMapping::Field(name) => {
+            let message = input
+                .get_field_by_name(name)
+                .and_then(|field| match field.kind() {
+                    Kind::Message(message) => Some(message),
+                    _ => None,
+                });
+            fields.insert(name.clone(), body_json(format, body, message)?);
+        }
+    }
+
+    for (path, value) in captures {
+        bind(&mut fields, &input, path, value)?;
+    }
+
+    if !matches!(rule.body, BodyMapping::All) {
+        let query = web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+            .map_err(|e| format!("invalid query string: {e}"))?;
+        for (path, value) in query.into_inner() {
+            bind(&mut fields, &input, &path, value)?;
+        }
+    }
+
+    DynamicMessage::deserialize(input, Json::Object(fields)).map_err(|e| e.to_string())
+}
+
+/// Default service of the actix app: hand-written routes always take precedence.
+/// Requests no rule matches get 404 before any auth, matched ones need what their rule asks.
+pub async fn dispatch(
+    gateway: web::Data<Gateway>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> HttpResponse {
+    let Some((rule, captures)) = gateway.find(req.method(), req.path()) else {
+        return error_response(StatusCode::NOT_FOUND, "no route matches this request");
+    };
+    req.extensions_mut().insert(RouteTemplate(rule.path()));
+    if let Access::Token { roles, policies } = &rule.access {
+        match authorize(&req, roles.as_deref(), policies.as_deref()) {
+            Ok(claims) => {
+                req.extensions_mut().insert(claims);
+            }
+            Err(e) => return e.error_response(),
+        }
+    }
+    let Some(format) = Format::from_accept(&req) else {
+        return not_acceptable();
+    };
+    let body_format = match Format::from_content_type(&req) {
+        Some(body_format) => body_format,
+        // Routes without a body ignore its Content-Type
+        None if body.is_empty() => Format::Json,
+        None => {
+            return ErrorUnsupportedMediaType(format!(
+                "supported media types: {SUPPORTED_MEDIA_TYPES}"
+            ))
+            .error_response();
+        }
+    };
+
+    let input = match request_message(rule, &req, body_format, &body, captures) {
+        Ok(input) => input,
+        Err(message) => {
+            return error_response(StatusCode::BAD_REQUEST, message);
+        }
+    };
+
+    // The service codec checks the message's rules, violations come back as a BadRequest detail
+    // gRPC length-prefixed message: uncompressed flag + big endian length
+    let payload = input.encode_to_vec();
+    let mut frame = Vec::with_capacity(payload.len() + 5);
+    frame.push(0);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+
+    let mut grpc_req = http::Request::builder()
+        .method(http::Method::POST)
+        .uri(rule.grpc_path.as_str())
+        .header(http::header::CONTENT_TYPE, "application/grpc")
+        .header(http::header::TE, "trailers");
+    // gRPC metadata has no cookies, a token from the auth_token cookie is passed as a header
+    if let Some(token) = extract_token(&req)
+        && let Ok(value) = http::HeaderValue::from_str(&format!("Bearer {token}"))
+    {
+        grpc_req = grpc_req.header(http::header::AUTHORIZATION, value);
+    }
+    // Certificate claims go along as extensions, as ClientCertLayer adds them on the listener
+    if let Some(claims) = req.extensions().get::<Claims>().cloned() {
+        grpc_req = grpc_req.extension(claims);
+    }
+    let grpc_req = match grpc_req.body(Body::new(Full::new(bytes::Bytes::from(frame)))) {
+        Ok(grpc_req) => grpc_req,
+        Err(e) => {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+        }
+    };
+
+    let res = match gateway.grpc.clone().oneshot(grpc_req).await {
+        Ok(res) => res,
+        Err(e) => {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+        }
+    };
+    let (parts, body) = res.into_parts();
+    let collected = match body.collect().await {
+        Ok(collected) => collected,
+        Err(status) => {
+            return Problem::from_grpc(http_status(status.code()), &status).to_http();
+        }
+    };
+
+    // Errors come back either as trailers-only headers or as trailers after the body
+    let trailers = collected.trailers().cloned().unwrap_or_default();
+    let status = tonic::Status::from_header_map(&trailers)
+        .or_else(|| tonic::Status::from_header_map(&parts.headers));
+    if let Some(status) = status
+        && status.code() != tonic::Code::Ok
+    {
+        return Problem::from_grpc(http_status(status.code()), &status).to_http();
+    }
+
+    let data = collected.to_bytes();
+    if data.len() < 5 {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, "empty gRPC response");
+    }
+    let output = match DynamicMessage::decode(rule.rpc.output(), &data[5..]) {
+        Ok(output) => output,
+        Err(e) => {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+        }
+    };
+
+    if format == Format::Protobuf {
+        let payload = match &rule.response_body {
+            None => output.encode_to_vec(),
+            Some(field) => match output.get_field_by_name(field).as_deref() {
+                Some(Value::Message(message)) => message.encode_to_vec(),
+                _ => return not_acceptable(),
+            },
+        };
+        return negotiated(format, payload);
+    }
+
+    let json = match output.serialize_with_options(serde_json::value::Serializer, &json_options()) {
+        Ok(json) => json,
+        Err(e) => {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+        }
+    };
+    let json = match &rule.response_body {
+        Some(field) => json.get(field).cloned().unwrap_or(Json::Null),
+        None => json,
+    };
+    let payload = match format {
+        Format::MsgPack => rmp_serde::to_vec_named(&json).map_err(|e| e.to_string()),
+        Format::Cbor => {
+            let mut payload = Vec::new();
+            ciborium::into_writer(&json, &mut payload)
+                .map(|()| payload)
+                .map_err(|e| e.to_string())
+        }
+        _ => serde_json::to_vec(&json).map_err(|e| e.to_string()),
+    };
+    match payload {
+        Ok(payload) => negotiated(format, payload),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+fn negotiated(format: Format, payload: Vec<u8>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(format.media_type())
+        .insert_header((VARY, "accept"))
+        .body(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::FILE_DESCRIPTOR_SET;
+
+    fn pool() -> DescriptorPool {
+        DescriptorPool::decode(FILE_DESCRIPTOR_SET).unwrap()
+    }
+
+    fn get_order() -> MethodDescriptor {
+        pool()
+            .get_service_by_name("order.OrderService")
+            .and_then(|service| service.methods().find(|rpc| rpc.name() == "GetOrder"))
+            .unwrap()
+    }
+
+    /// A google.api.HttpRule with the given string fields set
+    fn binding(fields: &[(&str, &str)]) -> DynamicMessage {
+        let descriptor = pool().get_message_by_name("google.api.HttpRule").unwrap();
+        let mut binding = DynamicMessage::new(descriptor);
+        for (name, value) in fields {
+            binding.set_field_by_name(name, Value::String(value.to_string()));
+        }
+        binding
+    }
+
+    fn parse(fields: &[(&str, &str)]) -> Result<Rule, String> {
+        Rule::parse(
+            &binding(fields),
+            &get_order(),
+            "/order.OrderService/GetOrder",
+            &Access::Public,
+        )
+    }
+
+    fn parse_error(fields: &[(&str, &str)]) -> String {
+        match parse(fields) {
+            Ok(rule) => panic!("{} was accepted", rule.path()),
+            Err(error) => error,
+        }
+    }
+
+    fn gateway() -> Gateway {
+        Gateway::new(FILE_DESCRIPTOR_SET, Routes::default()).unwrap()
+    }
+
+    #[test]
+    fn parses_verb_and_path_template() {
+        let rule = parse(&[("get", "/api/v1/orders/{user_id}/{order_id=*}")]).unwrap();
+        assert_eq!(rule.method, Method::GET);
+        assert_eq!(rule.path(), "/api/v1/orders/{user_id}/{order_id}");
+        assert!(matches!(rule.body, BodyMapping::None));
+        assert_eq!(rule.response_body, None);
+    }
+
+    #[test]
+    fn parses_custom_pattern() {
+        let descriptor = pool()
+            .get_message_by_name("google.api.CustomHttpPattern")
+            .unwrap();
+        let mut custom = DynamicMessage::new(descriptor);
+        custom.set_field_by_name("kind", Value::String("OPTIONS".to_string()));
+        custom.set_field_by_name("path", Value::String("/api/v1/orders".to_string()));
+        let mut http_rule = binding(&[]);
+        http_rule.set_field_by_name("custom", Value::Message(custom));
+
+        let rule = Rule::parse(&http_rule, &get_order(), "", &Access::Public).unwrap();
+        assert_eq!(rule.method, Method::OPTIONS);
+        assert_eq!(rule.path(), "/api/v1/orders");
+    }
+
+    #[test]
+    fn parses_body_mappings() {
+        let body = |body| {
+            parse(&[("post", "/api/v1/orders"), ("body", body)])
+                .unwrap()
+                .body
+        };
+        assert!(matches!(body(""), BodyMapping::None));
+        assert!(matches!(body("*"), BodyMapping::All));
+        assert!(matches!(body("order"), BodyMapping::Field(f) if f == "order"));
+
+        let rule = parse(&[("get", "/api/v1/orders"), ("response_body", "orders")]).unwrap();
+        assert_eq!(rule.response_body.as_deref(), Some("orders"));
+    }
+
+    #[test]
+    fn rejects_unsupported_templates() {
+        assert!(parse_error(&[]).contains("has no pattern"));
+        for template in ["/api/v1/{name=orders/*}", "/api/v1/orders/a{b}"] {
+            let error = parse_error(&[("get", template)]);
+            assert!(error.contains("is not supported"), "{template}: {error}");
+        }
+    }
+
+    #[test]
+    fn finds_rule_and_decodes_captures() {
+        let gateway = gateway();
+        let (rule, captures) = gateway
+            .find(&Method::GET, "/api/v1/orders/a%20b/7")
+            .unwrap();
+        assert_eq!(rule.grpc_path, "/order.OrderService/GetOrder");
+        assert_eq!(
+            captures,
+            vec![
+                ("user_id", "a b".to_string()),
+                ("order_id", "7".to_string())
+            ]
+        );
+
+        let (rule, captures) = gateway.find(&Method::GET, "/api/v1/orders/u1").unwrap();
+        assert_eq!(rule.grpc_path, "/order.OrderService/GetOrders");
+        assert_eq!(captures, vec![("user_id", "u1".to_string())]);
+    }
+
+    #[test]
+    fn misses_on_method_literal_or_segment_count() {
+        let gateway = gateway();
+        assert!(gateway.find(&Method::POST, "/api/v1/orders/u1").is_none());
+        assert!(gateway.find(&Method::GET, "/api/v1/order/u1").is_none());
+        assert!(
+            gateway
+                .find(&Method::GET, "/api/v1/orders/u1/7/8")
+                .is_none()
+        );
+        assert!(gateway.find(&Method::GET, "/api/v1/users").is_some());
+    }
+}
@@ -0,0 +1,151 @@
+use super::request_id::RequestId;
+use crate::auth::Claims;
+use crate::logging::{AccessLog, TrustedProxies};
+use crate::telemetry;
+use actix_web::HttpMessage;
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::http::header::USER_AGENT;
+use actix_web::web::Bytes;
+use actix_web::{
+    Error,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+use futures_util::future::{LocalBoxFuture, Ready, ok};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Response body that counts what was sent and writes the access log when dropped,
+/// so streamed responses are logged with their full size and duration
+pub struct LoggedBody<B> {
+    body: Pin<Box<B>>,
+    entry: AccessLog,
+    status: u16,
+    user: Option<String>,
+    bytes: u64,
+}
+
+impl<B: MessageBody> MessageBody for LoggedBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let poll = self.body.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            self.bytes += chunk.len() as u64;
+        }
+        poll
+    }
+}
+
+impl<B> Drop for LoggedBody<B> {
+    fn drop(&mut self) {
+        self.entry
+            .finish(self.status, None, self.bytes, self.user.as_deref());
+    }
+}
+
+/// Structured access log of every request, see `crate::logging`.
+/// Must run inside AssignRequestId, which provides the id.
+pub struct AccessLogger {
+    proxies: Arc<TrustedProxies>,
+}
+
+impl AccessLogger {
+    pub fn new(proxies: Arc<TrustedProxies>) -> Self {
+        Self { proxies }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AccessLogger
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<LoggedBody<B>>;
+    type Error = Error;
+    type Transform = AccessLoggerMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AccessLoggerMiddleware {
+            service,
+            proxies: self.proxies.clone(),
+        })
+    }
+}
+
+pub struct AccessLoggerMiddleware<S> {
+    service: S,
+    proxies: Arc<TrustedProxies>,
+}
+
+impl<S, B> Service<ServiceRequest> for AccessLoggerMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<LoggedBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+        let entry = AccessLog {
+            protocol: "http",
+            request_id: req
+                .extensions()
+                .get::<RequestId>()
+                .map(|id| id.0.clone())
+                .unwrap_or_default(),
+            method: req.method().to_string(),
+            path: req.path().to_string(),
+            client_ip: self.proxies.client_ip(
+                req.peer_addr().map(|addr| addr.ip()),
+                header("x-forwarded-for"),
+            ),
+            user_agent: header(USER_AGENT.as_str()).map(str::to_string),
+            trace_id: telemetry::current_trace_id(),
+            started: Instant::now(),
+        };
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = match fut.await {
+                Ok(res) => res,
+                Err(e) => {
+                    let status = e.as_response_error().status_code().as_u16();
+                    entry.finish(status, None, 0, None);
+                    return Err(e);
+                }
+            };
+            // JwtAuth stores the claims on the same request further in
+            let user = res
+                .request()
+                .extensions()
+                .get::<Claims>()
+                .map(|claims| claims.sub.clone());
+            let status = res.status().as_u16();
+            Ok(res.map_body(|_, body| LoggedBody {
+                body: Box::pin(body),
+                entry,
+                status,
+                user,
+                bytes: 0,
+            }))
+        })
+    }
+}
@@ -0,0 +1,491 @@
+use crate::controllers::negotiation::vendor_media_type;
+use crate::metrics::Metrics;
+use crate::services::{AppError, FieldViolation};
+use actix_web::http::Uri;
+use actix_web::http::header::{
+    ACCEPT, CONTENT_TYPE, HeaderName, HeaderValue, HttpDate, LINK, VARY,
+};
+use actix_web::http::uri::PathAndQuery;
+use actix_web::{
+    Error, ResponseError,
+    body::EitherBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    web,
+};
+use futures_util::future::{LocalBoxFuture, Ready, ok};
+use serde::Serialize;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+pub const API_VERSION_HEADER: HeaderName = HeaderName::from_static("api-version");
+pub const DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+pub const SUNSET_HEADER: HeaderName = HeaderName::from_static("sunset");
+
+/// Versions mounted under `/api/v{n}` by `routes::config`
+pub const SUPPORTED_VERSIONS: [u32; 2] = [1, 2];
+
+/// Paths under `/api` that belong to no version
+const UNVERSIONED_PATHS: [&str; 2] = ["/api/openapi.json", "/api/versions"];
+
+/// Where the version of a request came from
+#[derive(Clone, Copy)]
+pub enum VersionSource {
+    Path,
+    Header,
+    MediaType,
+    Default,
+}
+
+impl VersionSource {
+    pub const ALL: [Self; 4] = [Self::Path, Self::Header, Self::MediaType, Self::Default];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Path => "path",
+            Self::Header => "header",
+            Self::MediaType => "media_type",
+            Self::Default => "default",
+        }
+    }
+}
+
+/// A version announced as deprecated, optionally with the date it stops being served
+pub struct Deprecation {
+    pub since: SystemTime,
+    pub sunset: Option<SystemTime>,
+}
+
+fn parse_date(date: &str) -> Result<SystemTime, String> {
+    OffsetDateTime::parse(&format!("{date}T00:00:00Z"), &Rfc3339)
+        .map(SystemTime::from)
+        .map_err(|_| format!("{date:?} is not a YYYY-MM-DD date"))
+}
+
+fn parse_version(value: &str) -> Option<u32> {
+    let value = value.trim();
+    value.strip_prefix(['v', 'V']).unwrap_or(value).parse().ok()
+}
+
+fn unix_seconds(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+struct VersionUsage {
+    requests: [AtomicU64; VersionSource::ALL.len()],
+    last_request_at: AtomicI64,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct VersionUsageBody {
+    /// `path`, `header`, `media_type` or `default`
+    pub source: &'static str,
+    pub requests: u64,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct ApiVersionBody {
+    pub version: u32,
+    pub default: bool,
+    pub deprecated: bool,
+    /// RFC 3339 dates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated_since: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sunset: Option<String>,
+    /// Requests served since startup
+    pub requests: u64,
+    pub usage: Vec<VersionUsageBody>,
+    /// Unix timestamp in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_request_at: Option<i64>,
+}
+
+// ============================================================================
+// SINGLETON: Version policy and per-version usage counters shared by every worker
+// ============================================================================
+pub struct ApiVersions {
+    default: u32,
+    deprecations: Vec<(u32, Deprecation)>,
+    link: String,
+    usage: Vec<(u32, VersionUsage)>,
+}
+
+impl ApiVersions {
+    /// deprecations are `v1:2026-09-01` or `v1:2026-09-01:2027-03-01`,
+    /// the version, when it was deprecated and when it will be removed
+    pub fn new(default: u32, deprecations: &[String], link: String) -> Result<Self, String> {
+        let supported = |version: u32| -> Result<u32, String> {
+            if SUPPORTED_VERSIONS.contains(&version) {
+                Ok(version)
+            } else {
+                Err(format!("API version {version} does not exist"))
+            }
+        };
+        supported(default)?;
+
+        let mut parsed = Vec::new();
+        for entry in deprecations {
+            let mut parts = entry.split(':');
+            let version = parts
+                .next()
+                .and_then(parse_version)
+                .ok_or_else(|| format!("{entry:?} does not start with a version"))?;
+            let since = parse_date(parts.next().unwrap_or_default())?;
+            let sunset = parts.next().map(parse_date).transpose()?;
+            parsed.push((supported(version)?, Deprecation { since, sunset }));
+        }
+
+        Ok(Self {
+            default,
+            deprecations: parsed,
+            link,
+            usage: SUPPORTED_VERSIONS
+                .iter()
+                .map(|v| {
+                    let usage = VersionUsage {
+                        requests: Default::default(),
+                        last_request_at: AtomicI64::new(0),
+                    };
+                    (*v, usage)
+                })
+                .collect(),
+        })
+    }
+
+    pub fn deprecation(&self, version: u32) -> Option<&Deprecation> {
+        self.deprecations
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, d)| d)
+    }
+
+    fn record(&self, version: u32, source: VersionSource) {
+        if let Some((_, usage)) = self.usage.iter().find(|(v, _)| *v == version) {
+            usage.requests[source as usize].fetch_add(1, Ordering::Relaxed);
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or_default();
+            usage.last_request_at.store(now, Ordering::Relaxed);
+            Metrics::shared().api_version_request(version, source.as_str());
+        }
+    }
+
+    pub fn report(&self) -> Vec<ApiVersionBody> {
+        let rfc3339 = |time: SystemTime| OffsetDateTime::from(time).format(&Rfc3339).ok();
+        self.usage
+            .iter()
+            .map(|(version, usage)| {
+                let deprecation = self.deprecation(*version);
+                let last_request_at = usage.last_request_at.load(Ordering::Relaxed);
+                let usage: Vec<VersionUsageBody> = VersionSource::ALL
+                    .into_iter()
+                    .map(|source| VersionUsageBody {
+                        source: source.as_str(),
+                        requests: usage.requests[source as usize].load(Ordering::Relaxed),
+                    })
+                    .collect();
+                ApiVersionBody {
+                    version: *version,
+                    default: *version == self.default,
+                    deprecated: deprecation.is_some(),
+                    deprecated_since: deprecation.and_then(|d| rfc3339(d.since)),
+                    sunset: deprecation.and_then(|d| d.sunset).and_then(rfc3339),
+                    requests: usage.iter().map(|u| u.requests).sum(),
+                    usage,
+                    last_request_at: (last_request_at > 0).then_some(last_request_at),
+                }
+            })
+            .collect()
+    }
+
+    /// Version named by the path, header or Accept/Content-Type media type, in that order
+    fn resolve(&self, req: &ServiceRequest) -> Result<(u32, VersionSource), AppError> {
+        let header = match req.headers().get(API_VERSION_HEADER) {
+            None => None,
+            Some(value) => {
+                let version = value.to_str().ok().and_then(parse_version);
+                match version.filter(|v| SUPPORTED_VERSIONS.contains(v)) {
+                    Some(v) => Some(v),
+                    None => return Err(unsupported("Api-Version")),
+                }
+            }
+        };
+        let media_type = [(ACCEPT, "Accept"), (CONTENT_TYPE, "Content-Type")]
+            .into_iter()
+            .find_map(|(name, field)| {
+                let value = req.headers().get(name)?.to_str().ok()?;
+                value
+                    .split(',')
+                    .find_map(|range| vendor_media_type(range).map(|(v, _)| (field, v)))
+            });
+        if let Some((name, version)) = media_type
+            && !SUPPORTED_VERSIONS.contains(&version)
+        {
+            return Err(unsupported(name));
+        }
+
+        let path = path_version(req.path());
+        let mut named = [
+            path.map(|v| (v, VersionSource::Path)),
+            header.map(|v| (v, VersionSource::Header)),
+            media_type.map(|(_, v)| (v, VersionSource::MediaType)),
+        ]
+        .into_iter()
+        .flatten();
+
+        let Some((version, source)) = named.next() else {
+            return Ok((self.default, VersionSource::Default));
+        };
+        if named.any(|(v, _)| v != version) {
+            return Err(AppError::Validation(vec![FieldViolation::new(
+                "Api-Version",
+                "path, Api-Version header and media type name different versions",
+            )]));
+        }
+        Ok((version, source))
+    }
+}
+
+fn unsupported(field: &str) -> AppError {
+    let versions: Vec<String> = SUPPORTED_VERSIONS.iter().map(|v| v.to_string()).collect();
+    AppError::Validation(vec![FieldViolation::new(
+        field,
+        &format!("API version must be one of {}", versions.join(", ")),
+    )])
+}
+
+/// `/api/v2/...` names version 2
+fn path_version(path: &str) -> Option<u32> {
+    let segment = path.strip_prefix("/api/v")?.split('/').next()?;
+    segment.parse().ok()
+}
+
+/// Same path and query under `/api/v{version}`
+fn versioned_uri(uri: &Uri, version: u32) -> Option<Uri> {
+    let rest = uri.path().strip_prefix("/api")?;
+    let path_and_query = match uri.query() {
+        Some(query) => format!("/api/v{version}{rest}?{query}"),
+        None => format!("/api/v{version}{rest}"),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+/// Routes `/api/...` requests without a version in the path by their Api-Version header
+/// or `application/vnd.app.v{n}+json` media type, and announces deprecated versions
+pub struct ApiVersioning {
+    versions: web::Data<ApiVersions>,
+}
+
+impl ApiVersioning {
+    pub fn new(versions: web::Data<ApiVersions>) -> Self {
+        Self { versions }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ApiVersioning
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ApiVersioningMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ApiVersioningMiddleware {
+            service,
+            versions: self.versions.clone(),
+        })
+    }
+}
+
+pub struct ApiVersioningMiddleware<S> {
+    service: S,
+    versions: web::Data<ApiVersions>,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiVersioningMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let path = req.path();
+        let in_api = path == "/api" || path.starts_with("/api/");
+        if !in_api || UNVERSIONED_PATHS.contains(&path) {
+            let fut = self.service.call(req);
+            return Box::pin(async move { Ok(fut.await?.map_into_left_body()) });
+        }
+
+        let (version, source) = match self.versions.resolve(&req) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let res = req.into_response(e.error_response().map_into_right_body());
+                return Box::pin(async move { Ok(res) });
+            }
+        };
+
+        if !matches!(source, VersionSource::Path)
+            && let Some(uri) = versioned_uri(req.uri(), version)
+        {
+            req.match_info_mut().get_mut().update(&uri);
+            req.head_mut().uri = uri;
+        }
+        self.versions.record(version, source);
+
+        let versions = self.versions.clone();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let mut res = fut.await?;
+            let headers = res.headers_mut();
+            headers.insert(API_VERSION_HEADER, HeaderValue::from(version));
+            if !matches!(source, VersionSource::Path) {
+                headers.append(VARY, HeaderValue::from_static("api-version, accept"));
+            }
+            if let Some(deprecation) = versions.deprecation(version) {
+                // RFC 9745: a Unix timestamp prefixed with @
+                let since = format!("@{}", unix_seconds(deprecation.since));
+                if let Ok(value) = HeaderValue::from_str(&since) {
+                    headers.insert(DEPRECATION_HEADER, value);
+                }
+                // RFC 8594: an HTTP date
+                if let Some(sunset) = deprecation.sunset
+                    && let Ok(value) = HeaderValue::from_str(&HttpDate::from(sunset).to_string())
+                {
+                    headers.insert(SUNSET_HEADER, value);
+                }
+                let link = format!("<{}>; rel=\"deprecation\"", versions.link);
+                if let Ok(value) = HeaderValue::from_str(&link) {
+                    headers.append(LINK, value);
+                }
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn versions() -> ApiVersions {
+        ApiVersions::new(1, &["v1:2026-09-01:2027-03-01".to_string()], String::new()).unwrap()
+    }
+
+    fn resolve(req: TestRequest) -> Result<(u32, &'static str), AppError> {
+        versions()
+            .resolve(&req.to_srv_request())
+            .map(|(version, source)| (version, source.as_str()))
+    }
+
+    #[test]
+    fn new_parses_deprecations_and_rejects_unknown_versions() {
+        let versions = versions();
+        let deprecation = versions.deprecation(1).unwrap();
+        assert_eq!(unix_seconds(deprecation.since), 1_788_220_800);
+        assert_eq!(deprecation.sunset.map(unix_seconds), Some(1_803_859_200));
+        assert!(versions.deprecation(2).is_none());
+
+        assert!(ApiVersions::new(3, &[], String::new()).is_err());
+        for entry in ["v3:2026-09-01", "v1", "v1:09/01/2026", "x:2026-09-01"] {
+            assert!(
+                ApiVersions::new(1, &[entry.to_string()], String::new()).is_err(),
+                "{entry}"
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_falls_back_to_the_default_version() {
+        let req = TestRequest::get().uri("/api/users");
+        assert_eq!(resolve(req).ok(), Some((1, "default")));
+    }
+
+    #[test]
+    fn resolve_reads_path_header_and_media_type() {
+        let req = TestRequest::get().uri("/api/v2/users");
+        assert_eq!(resolve(req).ok(), Some((2, "path")));
+
+        for value in ["2", "v2", " V2 "] {
+            let req = TestRequest::get()
+                .uri("/api/users")
+                .insert_header((API_VERSION_HEADER, value));
+            assert_eq!(resolve(req).ok(), Some((2, "header")), "{value}");
+        }
+
+        let req = TestRequest::get()
+            .uri("/api/users")
+            .insert_header((ACCEPT, "text/html, application/vnd.app.v2+msgpack"));
+        assert_eq!(resolve(req).ok(), Some((2, "media_type")));
+
+        let req = TestRequest::post()
+            .uri("/api/orders")
+            .insert_header((CONTENT_TYPE, "application/vnd.app.v2+json; charset=utf-8"));
+        assert_eq!(resolve(req).ok(), Some((2, "media_type")));
+    }
+
+    #[test]
+    fn resolve_prefers_the_path_when_every_source_agrees() {
+        let req = TestRequest::get()
+            .uri("/api/v2/users")
+            .insert_header((API_VERSION_HEADER, "2"))
+            .insert_header((ACCEPT, "application/vnd.app.v2+json"));
+        assert_eq!(resolve(req).ok(), Some((2, "path")));
+    }
+
+    #[test]
+    fn resolve_rejects_unsupported_and_conflicting_versions() {
+        let requests = [
+            TestRequest::get()
+                .uri("/api/users")
+                .insert_header((API_VERSION_HEADER, "3")),
+            TestRequest::get()
+                .uri("/api/users")
+                .insert_header((API_VERSION_HEADER, "latest")),
+            TestRequest::get()
+                .uri("/api/users")
+                .insert_header((ACCEPT, "application/vnd.app.v9+json")),
+            TestRequest::get()
+                .uri("/api/v1/users")
+                .insert_header((API_VERSION_HEADER, "2")),
+            TestRequest::get()
+                .uri("/api/users")
+                .insert_header((API_VERSION_HEADER, "1"))
+                .insert_header((ACCEPT, "application/vnd.app.v2+json")),
+        ];
+        for req in requests {
+            assert!(matches!(resolve(req), Err(AppError::Validation(_))));
+        }
+    }
+
+    #[test]
+    fn versioned_uri_keeps_the_query() {
+        let uri: Uri = "/api/orders/1?page=2".parse().unwrap();
+        let uri = versioned_uri(&uri, 2).unwrap();
+        assert_eq!(uri.path(), "/api/v2/orders/1");
+        assert_eq!(uri.query(), Some("page=2"));
+        assert_eq!(path_version(uri.path()), Some(2));
+        assert_eq!(path_version("/api/versions"), None);
+        assert!(versioned_uri(&"/metrics".parse().unwrap(), 2).is_none());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::config::Config;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{
+    CACHE_CONTROL, CONTENT_LENGTH, ETAG, HeaderValue, IF_NONE_MATCH, VARY,
+};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{
+    Error, HttpResponse,
+    body::{BodySize, BoxBody, MessageBody, to_bytes},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+use futures_util::future::{LocalBoxFuture, Ready, ok};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+/// Responses differ per caller, who is named by the token header or cookie
+const VARY_CALLER: &str = "authorization, cookie";
+
+/// SHA-256 of the body, so every instance and every build tags the same body alike
+fn etag(body: &[u8]) -> String {
+    let mut tag = String::with_capacity(66);
+    tag.push('"');
+    for byte in Sha256::digest(body) {
+        let _ = write!(tag, "{byte:02x}");
+    }
+    tag.push('"');
+    tag
+}
+
+/// Weak comparison as in RFC 9110 13.1.2, `*` matches any representation
+fn none_match(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// Marks successful reads as privately cacheable for `HTTP_CACHE_MAX_AGE` seconds,
+/// tags them with an ETag and answers a matching If-None-Match with 304 Not Modified.
+/// The handler still runs, so authorization and service caches apply as usual.
+pub struct HttpCache {
+    cache_control: HeaderValue,
+}
+
+impl HttpCache {
+    pub fn new() -> Self {
+        let cache_control = match Config::from_env().http_cache_max_age {
+            0 => HeaderValue::from_static("private, no-cache"),
+            max_age => HeaderValue::from_str(&format!("private, max-age={max_age}"))
+                .unwrap_or(HeaderValue::from_static("private, no-cache")),
+        };
+        Self { cache_control }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for HttpCache
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = HttpCacheMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(HttpCacheMiddleware {
+            service: Rc::new(service),
+            cache_control: self.cache_control.clone(),
+        })
+    }
+}
+
+pub struct HttpCacheMiddleware<S> {
+    service: Rc<S>,
+    cache_control: HeaderValue,
+}
+
+impl<S, B> Service<ServiceRequest> for HttpCacheMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = Rc::clone(&self.service);
+        let cache_control = self.cache_control.clone();
+
+        Box::pin(async move {
+            let readable = matches!(*req.method(), Method::GET | Method::HEAD);
+            let if_none_match = req
+                .headers()
+                .get(IF_NONE_MATCH)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from);
+
+            let res = srv.call(req).await?;
+            // Streams are never buffered, errors are never cached
+            if !readable
+                || res.status() != StatusCode::OK
+                || !matches!(res.response().body().size(), BodySize::Sized(_))
+            {
+                return Ok(res.map_into_boxed_body());
+            }
+
+            let (req, res) = res.into_parts();
+            let (head, body) = res.into_parts();
+            let bytes = to_bytes(body)
+                .await
+                .map_err(|e| ErrorInternalServerError(e.into()))?;
+            let tag = etag(&bytes);
+
+            let mut res = if if_none_match.is_some_and(|v| none_match(&v, &tag)) {
+                let mut not_modified = HttpResponse::NotModified().finish();
+                // Headers a 200 would have carried, minus the body's own
+                for (name, value) in head.headers() {
+                    if name != CONTENT_LENGTH {
+                        not_modified
+                            .headers_mut()
+                            .append(name.clone(), value.clone());
+                    }
+                }
+                not_modified
+            } else {
+                head.set_body(BoxBody::new(bytes))
+            };
+
+            let headers = res.headers_mut();
+            if let Ok(value) = HeaderValue::from_str(&tag) {
+                headers.insert(ETAG, value);
+            }
+            headers.insert(CACHE_CONTROL, cache_control);
+            // One merged value, CORS keeps only the first Vary header when adding its own
+            let vary = match headers.get(VARY).and_then(|v| v.to_str().ok()) {
+                Some(vary) => format!("{vary}, {VARY_CALLER}"),
+                None => VARY_CALLER.to_string(),
+            };
+            if let Ok(value) = HeaderValue::from_str(&vary) {
+                headers.insert(VARY, value);
+            }
+            Ok(ServiceResponse::new(req, res))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn etag_is_the_quoted_sha256_of_the_body() {
+        assert_eq!(
+            etag(b""),
+            "\"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\""
+        );
+        assert_ne!(etag(b"{\"users\":[]}"), etag(b"{\"users\":[{}]}"));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let tag = etag(b"body");
+        assert!(none_match(&tag, &tag));
+        assert!(none_match(&format!("\"other\", W/{tag}"), &tag));
+        assert!(none_match("*", &tag));
+        assert!(!none_match("\"other\"", &tag));
+    }
+}
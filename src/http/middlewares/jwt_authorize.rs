@@ -1,8 +1,10 @@
+use crate::auth::Claims;
 use crate::config::Config;
-use super::Claims;
-use actix_web::HttpMessage;
+use crate::metrics::{AuthRejection, Metrics};
+use crate::services::AppError;
+use actix_web::{HttpMessage, HttpRequest};
 use actix_web::{
-    Error, HttpResponse,
+    Error, ResponseError,
     body::EitherBody,
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
 };
@@ -13,13 +15,12 @@ use std::{
     task::{Context, Poll},
 };
 
-fn extract_token(req: &ServiceRequest) -> Option<String> {
-    if let Some(auth) = req.headers().get("Authorization") {
-        if let Ok(auth_str) = auth.to_str() {
-            if auth_str.starts_with("Bearer ") {
-                return Some(auth_str.trim_start_matches("Bearer ").to_string());
-            }
-        }
+pub fn extract_token(req: &HttpRequest) -> Option<String> {
+    if let Some(auth) = req.headers().get("Authorization")
+        && let Ok(auth_str) = auth.to_str()
+        && auth_str.starts_with("Bearer ")
+    {
+        return Some(auth_str.trim_start_matches("Bearer ").to_string());
     }
 
     if let Some(cookie) = req.cookie("auth_token") {
@@ -29,11 +30,64 @@ fn extract_token(req: &ServiceRequest) -> Option<String> {
     None
 }
 
+/// Decodes the request's token and checks it against the rules:
+/// any of `roles` and all of `policies`, when given.
+/// Without a token, the claims of a verified client certificate stand in for it, see ProxiedPeer.
+pub fn authorize(
+    req: &HttpRequest,
+    roles: Option<&[String]>,
+    policies: Option<&[String]>,
+) -> Result<Claims, AppError> {
+    let secret = Config::from_env().jwt_secret;
+
+    let claims = match extract_token(req) {
+        Some(token) => match decode::<Claims>(
+            &token,
+            &DecodingKey::from_secret(secret.as_bytes()),
+            &Validation::new(Algorithm::HS256),
+        ) {
+            Ok(d) => d.claims,
+            Err(_) => {
+                Metrics::shared().auth_rejected(AuthRejection::Invalid);
+                return Err(AppError::Unauthorized("invalid or expired token".to_string()));
+            }
+        },
+        None => match req.extensions().get::<Claims>() {
+            Some(certificate) => certificate.clone(),
+            None => {
+                Metrics::shared().auth_rejected(AuthRejection::Missing);
+                return Err(AppError::Unauthorized("missing bearer token".to_string()));
+            }
+        },
+    };
+
+    // Role rule: ANY match
+    if let Some(required_roles) = roles
+        && !claims.roles.iter().any(|r| required_roles.contains(r))
+    {
+        Metrics::shared().auth_rejected(AuthRejection::ForbiddenRole);
+        return Err(AppError::Forbidden("missing a required role".to_string()));
+    }
+
+    // Policy rule: ALL match
+    if let Some(required_policies) = policies
+        && !required_policies
+            .iter()
+            .all(|p| claims.policies.contains(p))
+    {
+        Metrics::shared().auth_rejected(AuthRejection::ForbiddenPolicy);
+        return Err(AppError::Forbidden("missing a required policy".to_string()));
+    }
+
+    Ok(claims)
+}
+
 pub struct JwtAuth {
     roles: Option<Vec<String>>,
     policies: Option<Vec<String>>,
 }
 
+#[allow(dead_code)] // Not every rule flavour is used by the current routes
 impl JwtAuth {
     // Only authentication
     pub fn new() -> Self {
@@ -107,56 +161,19 @@ where
         self.service.poll_ready(ctx)
     }
 
-    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+    fn call(&self, req: ServiceRequest) -> Self::Future {
         let srv = Rc::clone(&self.service);
         let roles = self.roles.clone();
         let policies = self.policies.clone();
 
         Box::pin(async move {
-            let secret = Config::from_env().jwt_secret;
-
-            let token = match extract_token(&req) {
-                Some(t) => t,
-                None => {
-                    return Ok(req.into_response(
-                        HttpResponse::Unauthorized().finish().map_into_right_body()
-                    ));
+            let claims = match authorize(req.request(), roles.as_deref(), policies.as_deref()) {
+                Ok(claims) => claims,
+                Err(e) => {
+                    return Ok(req.into_response(e.error_response().map_into_right_body()));
                 }
             };
 
-            let decoded = decode::<Claims>(
-                &token,
-                &DecodingKey::from_secret(secret.as_bytes()),
-                &Validation::new(Algorithm::HS256),
-            );
-
-            let claims = match decoded {
-                Ok(d) => d.claims,
-                Err(_) => {
-                    return Ok(req.into_response(
-                        HttpResponse::Unauthorized().finish().map_into_right_body()
-                    ));
-                }
-            };
-
-            // Role rule: ANY match
-            if let Some(required_roles) = roles {
-                if !claims.roles.iter().any(|r| required_roles.contains(r)) {
-                    return Ok(req.into_response(
-                        HttpResponse::Forbidden().finish().map_into_right_body()
-                    ));
-                }
-            }
-
-            // Policy rule: ALL match
-            if let Some(required_policies) = policies {
-                if !required_policies.iter().all(|p| claims.policies.contains(p)) {
-                    return Ok(req.into_response(
-                        HttpResponse::Forbidden().finish().map_into_right_body()
-                    ));
-                }
-            }
-
             req.extensions_mut().insert(claims);
 
             let res = srv.call(req).await?.map_into_left_body();
@@ -0,0 +1,133 @@
+use crate::metrics::{InFlight, Metrics, UNMATCHED_ROUTE};
+use actix_web::HttpMessage;
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::Bytes;
+use actix_web::{
+    Error,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+use futures_util::future::{LocalBoxFuture, Ready, ok};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Route template of a request served by the default service (the transcoding gateway),
+/// which actix knows no pattern for
+pub struct RouteTemplate(pub String);
+
+/// Records the request once its body has been sent, and keeps it in flight until then
+pub struct MeteredBody<B> {
+    body: Pin<Box<B>>,
+    method: String,
+    route: String,
+    status: u16,
+    started: Instant,
+    _in_flight: InFlight,
+}
+
+impl<B: MessageBody> MessageBody for MeteredBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        self.body.as_mut().poll_next(cx)
+    }
+}
+
+impl<B> Drop for MeteredBody<B> {
+    fn drop(&mut self) {
+        Metrics::shared().http_request(
+            &self.method,
+            &self.route,
+            self.status,
+            self.started.elapsed(),
+        );
+    }
+}
+
+/// Request count, latency and in-flight metrics per route template, see `crate::metrics`
+pub struct RecordMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RecordMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<MeteredBody<B>>;
+    type Error = Error;
+    type Transform = RecordMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RecordMetricsMiddleware { service })
+    }
+}
+
+pub struct RecordMetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RecordMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<MeteredBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let in_flight = Metrics::shared().in_flight("http");
+        let method = req.method().to_string();
+        let started = Instant::now();
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = match fut.await {
+                Ok(res) => res,
+                Err(e) => {
+                    let status = e.as_response_error().status_code().as_u16();
+                    Metrics::shared().http_request(
+                        &method,
+                        UNMATCHED_ROUTE,
+                        status,
+                        started.elapsed(),
+                    );
+                    return Err(e);
+                }
+            };
+            // Known once routing has happened, e.g. /api/v1/orders/{user_id}
+            let route = res
+                .request()
+                .match_pattern()
+                .or_else(|| {
+                    res.request()
+                        .extensions()
+                        .get::<RouteTemplate>()
+                        .map(|route| route.0.clone())
+                })
+                .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+            let status = res.status().as_u16();
+            Ok(res.map_body(|_, body| MeteredBody {
+                body: Box::pin(body),
+                method,
+                route,
+                status,
+                started,
+                _in_flight: in_flight,
+            }))
+        })
+    }
+}
@@ -1,15 +1,10 @@
-// pub mod request_logger;
+pub mod access_log;
+pub mod api_version;
+pub mod cache;
 pub mod jwt_authorize;
-
-
-use serde::{Serialize, Deserialize};
-
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Claims {
-    pub sub: String,
-    pub exp: usize,
-
-    pub roles: Vec<String>,
-    pub policies: Vec<String>,
-}
\ No newline at end of file
+pub mod metrics;
+pub mod problem_details;
+pub mod proxied_peer;
+pub mod rate_limit;
+pub mod request_id;
+pub mod trace;
@@ -0,0 +1,147 @@
+use super::request_id::RequestId;
+use crate::controllers::error::{PROBLEM_JSON, Problem};
+use crate::controllers::health::HEALTH_JSON;
+use crate::services::{AppError, FieldViolation};
+use actix_web::HttpMessage;
+use actix_web::error::InternalError;
+use actix_web::http::Method;
+use actix_web::http::header::{CONTENT_LENGTH, CONTENT_TYPE, HeaderValue};
+use actix_web::{
+    Error, HttpResponse,
+    body::{BoxBody, EitherBody, MessageBody, to_bytes},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    web,
+};
+use futures_util::future::{LocalBoxFuture, Ready, ok};
+use std::task::{Context, Poll};
+
+fn has_content_type<B>(res: &HttpResponse<B>, prefix: &str) -> bool {
+    res.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(prefix))
+}
+
+/// Rewrites an error response as problem+json. Problems built by handlers keep their
+/// fields; plain text bodies from actix (404, extractor errors) become the `detail`.
+async fn to_problem<B: MessageBody>(
+    res: HttpResponse<B>,
+    instance: String,
+    request_id: Option<String>,
+) -> HttpResponse {
+    let status = res.status();
+    let (head, body) = res.into_parts();
+    let bytes = to_bytes(body).await.unwrap_or_default();
+
+    let parsed = if has_content_type(&head, PROBLEM_JSON) {
+        serde_json::from_slice::<Problem>(&bytes).ok()
+    } else {
+        None
+    };
+    let mut problem = parsed.unwrap_or_else(|| {
+        let problem = Problem::new(status);
+        // Server error text may carry internals, only client errors are echoed
+        if status.is_client_error() && has_content_type(&head, "text/plain") && !bytes.is_empty() {
+            problem.with_detail(String::from_utf8_lossy(&bytes))
+        } else {
+            problem
+        }
+    });
+    problem.instance.get_or_insert(instance);
+    problem.request_id = request_id;
+
+    let mut head = head.set_body(BoxBody::new(
+        serde_json::to_vec(&problem).unwrap_or_default(),
+    ));
+    head.headers_mut().remove(CONTENT_LENGTH);
+    head.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    head
+}
+
+/// Extractor failures become validation problems naming the rejected part of the request
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _| {
+        AppError::Validation(vec![FieldViolation::new("body", &err.to_string())]).into()
+    })
+}
+
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default().error_handler(|err, _| {
+        AppError::Validation(vec![FieldViolation::new("path", &err.to_string())]).into()
+    })
+}
+
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|err, _| {
+        AppError::Validation(vec![FieldViolation::new("query", &err.to_string())]).into()
+    })
+}
+
+/// Makes every error response `application/problem+json` (RFC 7807),
+/// whether it comes from a handler, a middleware rejection or actix itself
+pub struct ProblemDetails;
+
+impl<S, B> Transform<S, ServiceRequest> for ProblemDetails
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ProblemDetailsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ProblemDetailsMiddleware { service })
+    }
+}
+
+pub struct ProblemDetailsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for ProblemDetailsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Captured up front: the request is owned by the inner service while it runs
+        let instance = req.path().to_string();
+        let request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone());
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            match fut.await {
+                // Failing health probes keep their per-check report
+                Ok(res)
+                    if res.status().as_u16() < 400
+                        || res.request().method() == Method::HEAD
+                        || has_content_type(res.response(), HEALTH_JSON) =>
+                {
+                    Ok(res.map_into_left_body())
+                }
+                Ok(res) => {
+                    let (req, res) = res.into_parts();
+                    let problem = to_problem(res, instance, request_id).await;
+                    Ok(ServiceResponse::new(req, problem).map_into_right_body())
+                }
+                // Errors escaping the app are rendered by actix from the attached response
+                Err(err) => {
+                    let problem = to_problem(err.error_response(), instance, request_id).await;
+                    Err(InternalError::from_response(err, problem).into())
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,93 @@
+use crate::auth::Claims;
+use crate::logging::{
+    PROXIED_CLAIMS_HEADER, PROXIED_PEER_HEADER, PROXY_SECRET_HEADER, ProxySecret,
+};
+use actix_web::{
+    Error, HttpMessage,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+use futures_util::future::{LocalBoxFuture, Ready, ok};
+use std::net::SocketAddr;
+use std::task::{Context, Poll};
+
+/// Single port mode: takes the peer address of requests forwarded by the gRPC listener's
+/// proxy from PROXIED_PEER_HEADER, if they carry the proxy's secret. Everything downstream
+/// then sees the real client, and TRUSTED_PROXIES applies as on the gRPC side.
+/// The `Claims` of a verified client certificate in PROXIED_CLAIMS_HEADER are added to the
+/// request extensions, where JwtAuth accepts them in place of a token.
+pub struct ProxiedPeer {
+    secret: Option<ProxySecret>,
+}
+
+impl ProxiedPeer {
+    /// None outside single port mode, where the headers are only removed
+    pub fn new(secret: Option<ProxySecret>) -> Self {
+        Self { secret }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ProxiedPeer
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = ProxiedPeerMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ProxiedPeerMiddleware {
+            service,
+            secret: self.secret.clone(),
+        })
+    }
+}
+
+pub struct ProxiedPeerMiddleware<S> {
+    service: S,
+    secret: Option<ProxySecret>,
+}
+
+impl<S, B> Service<ServiceRequest> for ProxiedPeerMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let headers = &mut req.head_mut().headers;
+        let peer = headers.remove(PROXIED_PEER_HEADER).next();
+        let claims = headers.remove(PROXIED_CLAIMS_HEADER).next();
+        let secret = headers.remove(PROXY_SECRET_HEADER).next();
+        let trusted = match (&self.secret, secret) {
+            (Some(expected), Some(secret)) => expected.matches(secret.as_bytes()),
+            _ => false,
+        };
+        if trusted
+            && let Some(peer) = peer
+                .as_ref()
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<SocketAddr>().ok())
+        {
+            req.head_mut().peer_addr = Some(peer);
+        }
+        if trusted
+            && let Some(claims) = claims
+                .as_ref()
+                .and_then(|v| serde_json::from_slice::<Claims>(v.as_bytes()).ok())
+        {
+            req.extensions_mut().insert(claims);
+        }
+
+        Box::pin(self.service.call(req))
+    }
+}
@@ -0,0 +1,111 @@
+use super::jwt_authorize::extract_token;
+use crate::auth::Claims;
+use crate::logging::TrustedProxies;
+use crate::rate_limit::{self, Decision, RateLimiter};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::{
+    Error, HttpMessage, ResponseError,
+    body::EitherBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+use futures_util::future::{LocalBoxFuture, Ready, ok};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+fn insert_headers(headers: &mut HeaderMap, decision: &Decision) {
+    for (name, value) in decision.headers() {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+/// Spends one request of the caller's budget, see `crate::rate_limit`.
+/// Over budget requests get 429 with Retry-After, every limited response RateLimit-* headers.
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+    proxies: Arc<TrustedProxies>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<RateLimiter>, proxies: Arc<TrustedProxies>) -> Self {
+        Self { limiter, proxies }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limiter: self.limiter.clone(),
+            proxies: self.proxies.clone(),
+        })
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: Arc<RateLimiter>,
+    proxies: Arc<TrustedProxies>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = Rc::clone(&self.service);
+        if !self.limiter.enabled() {
+            return Box::pin(async move { Ok(srv.call(req).await?.map_into_left_body()) });
+        }
+
+        let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+        let ip = self.proxies.client_ip(
+            req.peer_addr().map(|addr| addr.ip()),
+            header("x-forwarded-for"),
+        );
+        // Single port mode: the client certificate's, added by ProxiedPeer
+        let certificate = req.extensions().get::<Claims>().cloned();
+        let caller = self.limiter.caller(
+            extract_token(req.request()).as_deref(),
+            certificate.as_ref(),
+            header(rate_limit::API_KEY),
+            ip,
+        );
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            let Some(decision) = limiter.check(req.path(), &caller).await else {
+                return Ok(srv.call(req).await?.map_into_left_body());
+            };
+
+            let mut res = if decision.allowed {
+                srv.call(req).await?.map_into_left_body()
+            } else {
+                req.into_response(decision.error().error_response().map_into_right_body())
+            };
+            insert_headers(res.headers_mut(), &decision);
+            Ok(res)
+        })
+    }
+}
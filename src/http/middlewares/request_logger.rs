@@ -1,55 +0,0 @@
-use actix_web::{
-    dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    Error,
-};
-use futures_util::future::{ok, Ready, LocalBoxFuture};
-use std::task::{Context, Poll};
-
-#[derive(Clone)]
-pub struct RequestLogger;
-
-impl<S, B> Transform<S, ServiceRequest> for RequestLogger
-where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
-    B: 'static,
-{
-    type Response = ServiceResponse<B>;
-    type Error = Error;
-    type Transform = RequestLoggerMiddleware<S>;
-    type InitError = ();
-    type Future = Ready<Result<Self::Transform, Self::InitError>>;
-
-    fn new_transform(&self, service: S) -> Self::Future {
-        ok(RequestLoggerMiddleware { service })
-    }
-}
-
-pub struct RequestLoggerMiddleware<S> {
-    service: S,
-}
-
-impl<S, B> Service<ServiceRequest> for RequestLoggerMiddleware<S>
-where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
-    B: 'static,
-{
-    type Response = ServiceResponse<B>;
-    type Error = Error;
-    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
-
-    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.service.poll_ready(ctx)
-    }
-
-    fn call(&self, req: ServiceRequest) -> Self::Future {
-        println!("{} {}", req.method(), req.path());
-
-        let fut = self.service.call(req);
-
-        Box::pin(async move {
-            let res = fut.await?;
-            println!("Response: {}", res.status());
-            Ok(res)
-        })
-    }
-}
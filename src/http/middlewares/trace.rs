@@ -0,0 +1,134 @@
+use super::request_id::RequestId;
+use crate::telemetry;
+use actix_web::HttpMessage;
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::http::header::HeaderMap;
+use actix_web::web::Bytes;
+use actix_web::{
+    Error,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+};
+use futures_util::future::{LocalBoxFuture, Ready, ok};
+use opentelemetry::propagation::Extractor;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+struct ActixHeaders<'a>(&'a HeaderMap);
+
+impl Extractor for ActixHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+/// Keeps the request span open until the body is sent, and current while it is produced,
+/// so work done by streamed bodies (exports) is part of the request's trace
+pub struct TracedBody<B> {
+    body: Pin<Box<B>>,
+    span: tracing::Span,
+}
+
+impl<B: MessageBody> MessageBody for TracedBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let span = self.span.clone();
+        let _entered = span.enter();
+        self.body.as_mut().poll_next(cx)
+    }
+}
+
+/// Opens a server span for every request, continuing the caller's W3C `traceparent`.
+/// Must run inside AssignRequestId, which provides the id.
+pub struct TraceRequests;
+
+impl<S, B> Transform<S, ServiceRequest> for TraceRequests
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<TracedBody<B>>;
+    type Error = Error;
+    type Transform = TraceRequestsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(TraceRequestsMiddleware { service })
+    }
+}
+
+pub struct TraceRequestsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for TraceRequestsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<TracedBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.0.clone())
+            .unwrap_or_default();
+        let span = tracing::info_span!(
+            "HTTP request",
+            otel.name = req.method().as_str(),
+            otel.kind = "server",
+            otel.status_code = tracing::field::Empty,
+            http.request.method = req.method().as_str(),
+            url.path = req.path(),
+            http.route = tracing::field::Empty,
+            http.response.status_code = tracing::field::Empty,
+            request_id = request_id.as_str(),
+        );
+        span.set_parent(telemetry::extract(&ActixHeaders(req.headers())));
+
+        // Inner middlewares (the access log) see the span as current
+        let fut = span.in_scope(|| self.service.call(req));
+
+        Box::pin(
+            async move {
+                let res = fut.await?;
+                let span = tracing::Span::current();
+                // Known only once routing has happened, e.g. /api/v1/orders/{user_id}
+                if let Some(route) = res.request().match_pattern() {
+                    span.record("otel.name", format!("{} {route}", res.request().method()));
+                    span.record("http.route", route);
+                }
+                span.record("http.response.status_code", res.status().as_u16());
+                if res.status().is_server_error() {
+                    span.record("otel.status_code", "ERROR");
+                }
+                Ok(res.map_body(|_, body| TracedBody {
+                    body: Box::pin(body),
+                    span,
+                }))
+            }
+            .instrument(span),
+        )
+    }
+}
@@ -1,35 +1,143 @@
 mod endpoints;
+mod extractors;
+mod gateway;
 mod middlewares;
+mod openapi;
 mod routes;
 
 use crate::config::Config;
-use crate::services::{OrderServiceFactory, OrderServiceTransient, UserService};
+use crate::controllers::import::ImportLimits;
+use crate::proto::FILE_DESCRIPTOR_SET;
+use crate::rate_limit::{self, RateLimiter};
+use crate::services::{
+    DiskSpace, Downstream, HealthChecks, ImportJobs, OrderServiceFactory, OrderServiceTransient,
+    ScopedOrderHealth, ShutdownHooks, UserService,
+};
+use crate::shutdown;
+use crate::tls::Tls;
 use actix_cors::Cors;
 // use actix_files::Files;
 use actix_web::{App, HttpServer, web};
-// use middlewares::request_logger::RequestLogger;
+use endpoints::metrics;
+use gateway::Gateway;
+use crate::logging::{ProxySecret, TrustedProxies};
+use middlewares::access_log::AccessLogger;
+use middlewares::api_version::{ApiVersioning, ApiVersions};
+use middlewares::metrics::RecordMetrics;
+use middlewares::problem_details::{self, ProblemDetails};
+use middlewares::proxied_peer::ProxiedPeer;
+use middlewares::rate_limit::RateLimit;
+use middlewares::request_id::AssignRequestId;
+use middlewares::trace::TraceRequests;
+use std::net::TcpListener;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 /// Start HTTP server
 /// - user_service: Singleton (shared Arc across all requests)
 /// - order_service_factory: Scoped (factory creates new instance per request)
 /// - order_service_transient: Transient (function creates new instance every call)
+/// - rate_limiter: Singleton, shared with the gRPC server
+/// - tls: serve HTTPS, ignored in single port mode where the gRPC listener terminates TLS
+/// - listener: single port mode, serve on this internal listener instead of HTTP_PORT,
+///   taking client addresses only from requests that carry the proxy's secret
+/// - shutdown: stops the server gracefully once cancelled
 pub async fn start<U, F>(
     user_service: Arc<U>,
     order_service_factory: Arc<F>,
     order_service_transient: OrderServiceTransient,
+    rate_limiter: Arc<RateLimiter>,
+    tls: Option<Arc<Tls>>,
+    listener: Option<(TcpListener, ProxySecret)>,
+    shutdown: CancellationToken,
 ) -> std::io::Result<()>
 where
     U: UserService + 'static,
     F: OrderServiceFactory + 'static,
 {
     let cfg = Config::from_env();
-    println!("Starting HTTP server on http://{}:{}", cfg.host, cfg.http_port);
+    match &listener {
+        Some((l, _)) => log::info!("Starting HTTP server behind the gRPC port on http://{}", l.local_addr()?),
+        None => {
+            let scheme = if tls.is_some() { "https" } else { "http" };
+            log::info!("Starting HTTP server on {scheme}://{}:{}", cfg.host, cfg.http_port)
+        }
+    }
+    let (listener, proxy_secret) = listener.unzip();
+    let (host, port) = (cfg.host.clone(), cfg.http_port);
+
+    // Transcoded RPCs call the same tonic services in process
+    let grpc_routes = crate::grpc::routes(user_service.clone(), order_service_factory.clone());
+    let gateway = web::Data::new(
+        Gateway::new(FILE_DESCRIPTOR_SET, grpc_routes).map_err(std::io::Error::other)?,
+    );
+
+    // Singleton: usage counters must add up across workers
+    let api_versions = web::Data::new(
+        ApiVersions::new(
+            cfg.api_default_version,
+            &cfg.api_deprecations,
+            cfg.api_deprecation_link.clone(),
+        )
+        .map_err(std::io::Error::other)?,
+    );
+
+    let openapi = web::Data::new(
+        openapi::OpenApiJson::new(&gateway, &api_versions).map_err(std::io::Error::other)?,
+    );
+    let docs_enabled = cfg.docs_enabled;
+
+    // Single port: ProxiedPeer restores the address the gRPC listener saw, so the same
+    // proxies are trusted as there and loopback is not
+    let trusted_proxies =
+        Arc::new(TrustedProxies::parse(&cfg.trusted_proxies).map_err(std::io::Error::other)?);
 
-    HttpServer::new(move || {
+    // Singleton: readiness must flip on every worker once shutdown starts
+    let mut health_checks = HealthChecks::new(
+        Duration::from_millis(cfg.health_check_timeout_ms),
+        shutdown.clone(),
+    )
+    .with("users", user_service.clone())
+    .with("orders", Arc::new(ScopedOrderHealth(order_service_factory.clone())));
+    if cfg.health_disk_min_free_mb > 0 {
+        health_checks = health_checks.with(
+            "disk",
+            Arc::new(DiskSpace {
+                path: cfg.health_disk_path.clone().into(),
+                min_free_bytes: cfg.health_disk_min_free_mb * 1_000_000,
+            }),
+        );
+    }
+    for (name, address) in &cfg.health_downstreams {
+        health_checks = health_checks.with(
+            name.clone(),
+            Arc::new(Downstream {
+                address: address.clone(),
+            }),
+        );
+    }
+    let health_checks = web::Data::new(health_checks);
+
+    // Singleton: import jobs must be visible to every worker's status endpoint
+    let import_jobs = web::Data::new(ImportJobs::new(cfg.import_max_batch)?);
+    // Background imports outlive their request, so they are drained separately
+    ShutdownHooks::shared().register("import jobs", import_jobs.clone().into_inner());
+
+    let shutdown_delay = Duration::from_secs(cfg.shutdown_delay);
+    let shutdown_timeout = cfg.shutdown_timeout;
+
+    let server = HttpServer::new(move || {
         let mut cors = Cors::default()
             .allow_any_header()
             .allow_any_method()
+            .expose_headers([
+                rate_limit::POLICY,
+                rate_limit::LIMIT,
+                rate_limit::REMAINING,
+                rate_limit::RESET,
+                "retry-after",
+            ])
             .supports_credentials();
         for origin in &cfg.cors_origins {
             cors = cors.allowed_origin(origin);
@@ -43,13 +151,88 @@ where
             ))
             // Transient: function pointer, controller calls it every time it needs an instance
             .app_data(web::Data::new(order_service_transient))
-            // .wrap(RequestLogger)
+            .app_data(import_jobs.clone())
+            .app_data(health_checks.clone())
+            .app_data(web::Data::new(ImportLimits {
+                max_bytes: cfg.import_max_bytes,
+                inline_rows: cfg.import_inline_rows,
+            }))
+            // Malformed bodies, paths and queries are reported as validation problems
+            .app_data(problem_details::json_config())
+            .app_data(problem_details::path_config())
+            .app_data(problem_details::query_config())
+            // Inside CORS so rejections carry its headers, and preflights are never limited
+            .wrap(RateLimit::new(rate_limiter.clone(), trusted_proxies.clone()))
             .wrap(cors)
+            // Unversioned /api paths are rewritten before routing, so it wraps everything
+            .wrap(ApiVersioning::new(api_versions.clone()))
+            .wrap(ProblemDetails)
+            // Outside ProblemDetails to count the final status
+            .wrap(RecordMetrics)
+            // Outside ProblemDetails to log the final status, inside AssignRequestId for the id
+            .wrap(AccessLogger::new(trusted_proxies.clone()))
+            // Outside the access log, so its records carry the trace id
+            .wrap(TraceRequests)
+            .wrap(AssignRequestId)
+            // Outermost, everything else sees the client's address
+            .wrap(ProxiedPeer::new(proxy_secret.clone()))
             // Serve static file
             // .service(Files::new("/", "./wwwroot").index_file("index.html"))
+            .app_data(openapi.clone())
+            .app_data(api_versions.clone())
             .configure(routes::config)
+            .configure(|c| {
+                if docs_enabled {
+                    openapi::docs(c);
+                }
+            })
+            // Anything without a hand-written handler falls through to the gateway,
+            // which applies each transcoded route's own auth rule
+            .app_data(gateway.clone())
+            .default_service(web::route().to(gateway::dispatch))
     })
-    .bind((cfg.host.as_str(), cfg.http_port))?
-    .run()
-    .await
+    // Signals are handled in main so both servers stop together
+    .disable_signals()
+    // In-flight requests get this long to finish once the listener is closed
+    .shutdown_timeout(shutdown_timeout);
+
+    let server = match (listener, tls) {
+        (Some(listener), _) => server.listen(listener)?,
+        (None, Some(tls)) => server.bind_rustls_0_23(
+            (host.as_str(), port),
+            tls.http_config().map_err(std::io::Error::other)?,
+        )?,
+        (None, None) => server.bind((host.as_str(), port))?,
+    }
+    .run();
+
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown::stop_accepting(shutdown, shutdown_delay).await;
+        handle.stop(true).await;
+    });
+
+    server.await
+}
+
+/// Serves `/metrics` on a listener of its own, so scrapes never share a port, an ingress or
+/// the auth of the API. Stops with the API listeners.
+pub async fn start_metrics(addr: &str, shutdown: CancellationToken) -> std::io::Result<()> {
+    let cfg = Config::from_env();
+    log::info!("Starting metrics server on http://{addr}");
+
+    let server =
+        HttpServer::new(|| App::new().route("/metrics", web::get().to(metrics::get_metrics)))
+            .workers(1)
+            .disable_signals()
+            .bind(addr)?
+            .run();
+
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown::stop_accepting(shutdown, Duration::from_secs(cfg.shutdown_delay)).await;
+        handle.stop(true).await;
+    });
+
+    server.await
 }
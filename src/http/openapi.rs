@@ -0,0 +1,332 @@
+use super::endpoints::{health, v1, v2, versions};
+use super::gateway::Gateway;
+use super::middlewares::api_version::{ApiVersionBody, ApiVersions, VersionUsageBody};
+use crate::controllers::error::{PROBLEM_JSON, Problem, ProblemField};
+use crate::controllers::health::{HealthBody, HealthCheckBody};
+use actix_web::http::Method;
+use actix_web::http::header::LOCATION;
+use actix_web::{HttpResponse, web};
+use prost_reflect::{FieldDescriptor, Kind, MessageDescriptor};
+use utoipa::openapi::path::{HttpMethod, OperationBuilder, ParameterBuilder, ParameterIn};
+use utoipa::openapi::request_body::RequestBodyBuilder;
+use utoipa::openapi::schema::{
+    AdditionalProperties, ArrayBuilder, Components, ObjectBuilder, Schema, Type,
+};
+use utoipa::openapi::security::{
+    ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme,
+};
+use utoipa::openapi::{
+    Content, Deprecated, OpenApi as Document, Ref, RefOr, Required, ResponseBuilder,
+};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+/// Bearer header or `auth_token` cookie, as accepted by JwtAuth
+struct JwtSecurity;
+
+impl Modify for JwtSecurity {
+    fn modify(&self, openapi: &mut Document) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("auth_token"))),
+        );
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Rust Actix API",
+        description = "Responses are negotiated with Accept: application/json (default), \
+            application/x-protobuf, application/msgpack or application/cbor. \
+            Errors are application/problem+json. \
+            /api paths without a version are routed by the Api-Version header \
+            or an application/vnd.app.v{n}+json media type."
+    ),
+    paths(
+        v1::user::get_users,
+        v1::order::get_orders,
+        v1::order::create_order,
+        v1::order::export_orders,
+        v1::import::import,
+        v1::import::get_job,
+        v2::user::get_users_v2,
+        v2::order::get_orders,
+        versions::get_versions,
+        health::live,
+        health::ready,
+        health::startup,
+    ),
+    components(schemas(
+        Problem,
+        ProblemField,
+        ApiVersionBody,
+        VersionUsageBody,
+        HealthBody,
+        HealthCheckBody
+    )),
+    modifiers(&JwtSecurity),
+    tags(
+        (name = "users"),
+        (name = "orders"),
+        (name = "imports", description = "Bulk imports, admin only"),
+        (name = "versions", description = "API version usage, admin only"),
+        (name = "health", description = "Kubernetes liveness, readiness and startup probes"),
+        (name = "transcoded", description = "RPCs served over HTTP from their google.api.http annotations"),
+    )
+)]
+struct ApiDoc;
+
+fn http_method(method: &Method) -> Option<HttpMethod> {
+    match *method {
+        Method::GET => Some(HttpMethod::Get),
+        Method::POST => Some(HttpMethod::Post),
+        Method::PUT => Some(HttpMethod::Put),
+        Method::DELETE => Some(HttpMethod::Delete),
+        Method::PATCH => Some(HttpMethod::Patch),
+        _ => None,
+    }
+}
+
+fn schema_type(kind: &Kind) -> Type {
+    match kind {
+        Kind::Bool => Type::Boolean,
+        Kind::Int32
+        | Kind::Int64
+        | Kind::Uint32
+        | Kind::Uint64
+        | Kind::Sint32
+        | Kind::Sint64
+        | Kind::Fixed32
+        | Kind::Fixed64
+        | Kind::Sfixed32
+        | Kind::Sfixed64 => Type::Integer,
+        Kind::Float | Kind::Double => Type::Number,
+        _ => Type::String,
+    }
+}
+
+/// Schema of one field in the proto3 JSON mapping, registering the messages it refers to
+fn field_schema(components: &mut Components, field: &FieldDescriptor) -> RefOr<Schema> {
+    if field.is_map()
+        && let Kind::Message(entry) = field.kind()
+    {
+        let value = field_schema(components, &entry.map_entry_value_field());
+        return ObjectBuilder::new()
+            .schema_type(Type::Object)
+            .additional_properties(Some(AdditionalProperties::RefOr(value)))
+            .into();
+    }
+    let schema: RefOr<Schema> = match field.kind() {
+        Kind::Message(message) => {
+            add_message_schema(components, &message);
+            Ref::from_schema_name(message.name()).into()
+        }
+        Kind::Enum(values) => ObjectBuilder::new()
+            .schema_type(Type::String)
+            .enum_values(Some(values.values().map(|v| v.name().to_string())))
+            .into(),
+        kind => ObjectBuilder::new().schema_type(schema_type(&kind)).into(),
+    };
+    if field.is_list() {
+        ArrayBuilder::new().items(schema).into()
+    } else {
+        schema
+    }
+}
+
+/// Registers a message and the messages its fields refer to, under the name operations
+/// reference. Schemas derived from the generated types win, they carry more detail.
+fn add_message_schema(components: &mut Components, message: &MessageDescriptor) {
+    let name = message.name();
+    if components.schemas.contains_key(name) {
+        return;
+    }
+    // Placeholder first, so recursive messages stop here
+    components
+        .schemas
+        .insert(name.to_string(), ObjectBuilder::new().into());
+    let mut schema = ObjectBuilder::new().schema_type(Type::Object);
+    for field in message.fields() {
+        schema = schema.property(field.name(), field_schema(components, &field));
+    }
+    components.schemas.insert(name.to_string(), schema.into());
+}
+
+/// Documents transcoded routes that have no hand-written handler,
+/// with a schema for every message they reference
+fn add_transcoded(openapi: &mut Document, gateway: &Gateway) {
+    for route in gateway.routes() {
+        let Some(method) = http_method(route.method) else {
+            continue;
+        };
+        if openapi
+            .paths
+            .get_path_operation(&route.path, method.clone())
+            .is_some()
+        {
+            continue;
+        }
+
+        let input = route.rpc.input();
+        let output = route.rpc.output();
+        let components = openapi.components.get_or_insert_with(Default::default);
+        add_message_schema(components, &input);
+        let response_schema = match route.response_body {
+            None => {
+                add_message_schema(components, &output);
+                Some(Ref::from_schema_name(output.name()).into())
+            }
+            Some(field) => output
+                .get_field_by_name(field)
+                .map(|field| field_schema(components, &field)),
+        };
+        let mut operation = OperationBuilder::new()
+            .operation_id(Some(route.rpc.name()))
+            .summary(Some(format!("{} (transcoded)", route.rpc.full_name())))
+            .tag("transcoded");
+        if !route.public {
+            operation = operation
+                .security(SecurityRequirement::new("bearer", Vec::<String>::new()))
+                .security(SecurityRequirement::new("cookie", Vec::<String>::new()));
+        }
+
+        for field in input.fields() {
+            let location = if route.path_fields.contains(&field.name()) {
+                ParameterIn::Path
+            } else if !route.has_body && !field.is_list() && field.kind().as_message().is_none() {
+                ParameterIn::Query
+            } else {
+                continue;
+            };
+            let required = if location == ParameterIn::Path {
+                Required::True
+            } else {
+                Required::False
+            };
+            operation = operation.parameter(
+                ParameterBuilder::new()
+                    .name(field.name())
+                    .parameter_in(location)
+                    .required(required)
+                    .schema(Some(
+                        ObjectBuilder::new().schema_type(schema_type(&field.kind())),
+                    )),
+            );
+        }
+
+        if route.has_body {
+            operation = operation.request_body(Some(
+                RequestBodyBuilder::new()
+                    .content(
+                        "application/json",
+                        Content::new(Some(Ref::from_schema_name(input.name()))),
+                    )
+                    .required(Some(Required::True))
+                    .build(),
+            ));
+        }
+
+        let mut ok = ResponseBuilder::new().description("Success");
+        if let Some(schema) = response_schema {
+            ok = ok.content("application/json", Content::new(Some(schema)));
+        }
+        let problem = || Content::new(Some(Ref::from_schema_name("Problem")));
+        operation = operation
+            .response("200", ok)
+            .response(
+                "400",
+                ResponseBuilder::new()
+                    .description("Invalid request")
+                    .content(PROBLEM_JSON, problem()),
+            )
+            .response(
+                "401",
+                ResponseBuilder::new()
+                    .description("Missing or invalid token")
+                    .content(PROBLEM_JSON, problem()),
+            )
+            .response(
+                "404",
+                ResponseBuilder::new()
+                    .description("Not found")
+                    .content(PROBLEM_JSON, problem()),
+            );
+
+        openapi
+            .paths
+            .add_path_operation(&route.path, vec![method], operation);
+    }
+}
+
+/// Flags every operation of a deprecated version
+fn mark_deprecated(openapi: &mut Document, versions: &ApiVersions) {
+    for (path, item) in openapi.paths.paths.iter_mut() {
+        let version = path
+            .strip_prefix("/api/v")
+            .and_then(|rest| rest.split('/').next())
+            .and_then(|v| v.parse().ok());
+        if version.and_then(|v| versions.deprecation(v)).is_none() {
+            continue;
+        }
+        for operation in [
+            &mut item.get,
+            &mut item.put,
+            &mut item.post,
+            &mut item.delete,
+            &mut item.patch,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            operation.deprecated = Some(Deprecated::True);
+        }
+    }
+}
+
+/// The OpenAPI 3.1 document, rendered once at startup
+pub struct OpenApiJson(String);
+
+impl OpenApiJson {
+    pub fn new(gateway: &Gateway, versions: &ApiVersions) -> Result<Self, String> {
+        let mut openapi = ApiDoc::openapi();
+        // Filled from Cargo.toml, which declares no license
+        openapi.info.license = None;
+        add_transcoded(&mut openapi, gateway);
+        mark_deprecated(&mut openapi, versions);
+        openapi
+            .to_pretty_json()
+            .map(Self)
+            .map_err(|e| e.to_string())
+    }
+}
+
+pub async fn openapi_json(document: web::Data<OpenApiJson>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(document.0.clone())
+}
+
+/// Swagger UI at `/docs/`, its assets are built into the binary and it reads `/api/openapi.json`
+pub fn docs(cfg: &mut web::ServiceConfig) {
+    // The page loads its assets relative to itself, which needs the trailing slash
+    cfg.route(
+        "/docs",
+        web::get().to(|| async {
+            HttpResponse::MovedPermanently()
+                .insert_header((LOCATION, "/docs/"))
+                .finish()
+        }),
+    );
+    cfg.service(SwaggerUi::new("/docs/{_:.*}").config(Config::from("/api/openapi.json")));
+}
@@ -1,9 +1,23 @@
 use actix_web::web;
-use super::endpoints::{v1, v2};
+use super::endpoints::{health, v1, v2, versions};
+use super::middlewares::jwt_authorize::JwtAuth;
+use super::openapi;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/health")      // Kubernetes probes
+            .route("/live", web::get().to(health::live))
+            .route("/ready", web::get().to(health::ready))
+            .route("/startup", web::get().to(health::startup))
+    );
     cfg.service(
         web::scope("/api")        // Context Path
+            .route("/openapi.json", web::get().to(openapi::openapi_json))
+            .service(
+                web::resource("/versions")
+                    .wrap(JwtAuth::with_roles(vec!["admin"]))
+                    .route(web::get().to(versions::get_versions))
+            )
             .service(
                 web::scope("/v1") // Version 1
                     .configure(v1::routes)
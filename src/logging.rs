@@ -0,0 +1,365 @@
+use log::kv::{self, Key, Value, VisitSource, VisitValue};
+use std::io::Write;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+/// Target of access log records, filter with `RUST_LOG=access=off` to silence them
+pub const ACCESS_TARGET: &str = "access";
+
+#[derive(Clone, Copy)]
+enum LogFormat {
+    Json,
+    Logfmt,
+}
+
+/// Converts a log value to JSON, keeping numbers and booleans typed
+struct JsonValue<'a>(&'a mut serde_json::Value);
+
+impl<'v> VisitValue<'v> for JsonValue<'_> {
+    fn visit_any(&mut self, value: Value) -> Result<(), kv::Error> {
+        *self.0 = value.to_string().into();
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        *self.0 = serde_json::Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        *self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        *self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), kv::Error> {
+        *self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        *self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), kv::Error> {
+        *self.0 = value.into();
+        Ok(())
+    }
+}
+
+struct Fields(Vec<(String, serde_json::Value)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let mut json = serde_json::Value::Null;
+        value.visit(JsonValue(&mut json))?;
+        self.0.push((key.to_string(), json));
+        Ok(())
+    }
+}
+
+fn logfmt_value(value: &serde_json::Value) -> String {
+    let text = match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => return other.to_string(),
+    };
+    let plain = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_graphic() && c != '"' && c != '=' && c != '\\');
+    if plain {
+        text
+    } else {
+        serde_json::Value::String(text).to_string()
+    }
+}
+
+fn render(format: LogFormat, record: &log::Record) -> String {
+    let ts = OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default();
+    let mut fields = Fields(vec![
+        ("ts".to_string(), ts.into()),
+        (
+            "level".to_string(),
+            record.level().as_str().to_ascii_lowercase().into(),
+        ),
+        ("target".to_string(), record.target().into()),
+        ("msg".to_string(), record.args().to_string().into()),
+    ]);
+    let _ = record.key_values().visit(&mut fields);
+    fields.0.retain(|(_, value)| !value.is_null());
+
+    match format {
+        LogFormat::Json => {
+            let pairs: Vec<String> = fields
+                .0
+                .iter()
+                .map(|(key, value)| format!("{}:{value}", serde_json::Value::from(key.as_str())))
+                .collect();
+            format!("{{{}}}", pairs.join(","))
+        }
+        LogFormat::Logfmt => {
+            let pairs: Vec<String> = fields
+                .0
+                .iter()
+                .map(|(key, value)| format!("{key}={}", logfmt_value(value)))
+                .collect();
+            pairs.join(" ")
+        }
+    }
+}
+
+/// One record per line, as JSON objects or logfmt (LOG_FORMAT).
+/// RUST_LOG filters as usual and defaults to `info`.
+pub fn init(format: &str) {
+    let format = match format {
+        "logfmt" => LogFormat::Logfmt,
+        _ => LogFormat::Json,
+    };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format(move |buf, record| writeln!(buf, "{}", render(format, record)))
+        .init();
+}
+
+/// Accept the caller's request id only if it is short printable ASCII, so it is safe to log
+/// and echo; otherwise generate one
+pub fn request_id(header: Option<&str>) -> String {
+    header
+        .filter(|v| (1..=128).contains(&v.len()) && v.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Header in which the single port proxy passes the client's socket address to actix
+pub const PROXIED_PEER_HEADER: &str = "x-proxied-peer";
+/// JSON `Claims` of the client certificate the single port listener verified
+pub const PROXIED_CLAIMS_HEADER: &str = "x-proxied-claims";
+/// Carries the ProxySecret next to PROXIED_PEER_HEADER and PROXIED_CLAIMS_HEADER
+pub const PROXY_SECRET_HEADER: &str = "x-proxy-secret";
+
+/// Random value shared by the single port proxy and actix in the same process. Only requests
+/// that carry it may set the peer address and claims, other local processes can connect to
+/// actix too.
+#[derive(Clone)]
+pub struct ProxySecret(Arc<str>);
+
+impl ProxySecret {
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().simple().to_string().into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Compares in constant time, so the secret can't be guessed byte by byte
+    pub fn matches(&self, value: &[u8]) -> bool {
+        let secret = self.0.as_bytes();
+        secret.len() == value.len()
+            && secret
+                .iter()
+                .zip(value)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+/// Proxies whose X-Forwarded-For is believed, as addresses or CIDR ranges (TRUSTED_PROXIES)
+pub struct TrustedProxies(Vec<(IpAddr, u8)>);
+
+impl TrustedProxies {
+    pub fn parse(entries: &[String]) -> Result<Self, String> {
+        let mut ranges = Vec::new();
+        for entry in entries {
+            let invalid = || format!("{entry:?} is not an IP address or CIDR range");
+            let (ip, prefix) = entry.split_once('/').unwrap_or((entry, ""));
+            let ip: IpAddr = ip.parse().map_err(|_| invalid())?;
+            let max = if ip.is_ipv4() { 32 } else { 128 };
+            let prefix = if prefix.is_empty() {
+                max
+            } else {
+                prefix
+                    .parse()
+                    .ok()
+                    .filter(|p| *p <= max)
+                    .ok_or_else(invalid)?
+            };
+            ranges.push((ip, prefix));
+        }
+        Ok(Self(ranges))
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|(range, prefix)| match (range, ip) {
+            (IpAddr::V4(range), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+                u32::from(*range) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+                u128::from(*range) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        })
+    }
+
+    /// Walks X-Forwarded-For from the right while the hop it came from is trusted,
+    /// so clients cannot spoof their address by sending the header themselves
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let mut ip = peer?.to_canonical();
+        for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+            if !self.contains(ip) {
+                break;
+            }
+            match hop.trim().parse::<IpAddr>() {
+                Ok(hop) => ip = hop.to_canonical(),
+                Err(_) => break,
+            }
+        }
+        Some(ip)
+    }
+}
+
+/// A request in flight, logged to the `access` target once its response has been sent
+pub struct AccessLog {
+    /// `http` or `grpc`
+    pub protocol: &'static str,
+    pub request_id: String,
+    pub method: String,
+    pub path: String,
+    pub client_ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    /// Trace the request belongs to, when tracing is enabled
+    pub trace_id: Option<String>,
+    pub started: Instant,
+}
+
+impl AccessLog {
+    /// user is the `sub` of the caller's token, grpc_status is only set for gRPC calls
+    pub fn finish(&self, status: u16, grpc_status: Option<i32>, bytes: u64, user: Option<&str>) {
+        let latency_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+        let client_ip = self.client_ip.map(|ip| ip.to_string());
+        log::info!(
+            target: ACCESS_TARGET,
+            protocol = self.protocol,
+            request_id = self.request_id.as_str(),
+            method = self.method.as_str(),
+            path = self.path.as_str(),
+            status = status,
+            grpc_status = grpc_status,
+            bytes = bytes,
+            latency_ms = (latency_ms * 1000.0).round() / 1000.0,
+            client_ip = client_ip.as_deref(),
+            user = user,
+            user_agent = self.user_agent.as_deref(),
+            trace_id = self.trace_id.as_deref();
+            "{} {} {}", self.method, self.path, status
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxies(entries: &[&str]) -> TrustedProxies {
+        let entries: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+        TrustedProxies::parse(&entries).unwrap()
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_rejects_bad_addresses_and_prefixes() {
+        for entry in [
+            "10.0.0",
+            "10.0.0.0/33",
+            "::1/129",
+            "10.0.0.0/x",
+            "localhost",
+        ] {
+            assert!(
+                TrustedProxies::parse(&[entry.to_string()]).is_err(),
+                "{entry}"
+            );
+        }
+    }
+
+    #[test]
+    fn contains_matches_cidr_ranges() {
+        let proxies = proxies(&["10.1.0.0/16", "192.168.0.7", "fd00::/8"]);
+        assert!(proxies.contains(ip("10.1.255.255")));
+        assert!(!proxies.contains(ip("10.2.0.0")));
+        // A bare address is a /32
+        assert!(proxies.contains(ip("192.168.0.7")));
+        assert!(!proxies.contains(ip("192.168.0.8")));
+        assert!(proxies.contains(ip("fd12::1")));
+        assert!(!proxies.contains(ip("fe80::1")));
+    }
+
+    #[test]
+    fn contains_with_prefix_zero_matches_the_whole_family() {
+        let v4 = proxies(&["0.0.0.0/0"]);
+        assert!(v4.contains(ip("1.2.3.4")));
+        assert!(v4.contains(ip("255.255.255.255")));
+        assert!(!v4.contains(ip("::1")));
+
+        let v6 = proxies(&["::/0"]);
+        assert!(v6.contains(ip("2001:db8::1")));
+        assert!(!v6.contains(ip("1.2.3.4")));
+    }
+
+    #[test]
+    fn contains_treats_ipv4_mapped_ipv6_as_ipv4() {
+        let proxies = proxies(&["10.0.0.0/8"]);
+        assert!(proxies.contains(ip("::ffff:10.0.0.1")));
+        assert!(!proxies.contains(ip("::ffff:11.0.0.1")));
+    }
+
+    #[test]
+    fn client_ip_walks_forwarded_for_through_trusted_hops_only() {
+        let proxies = proxies(&["10.0.0.0/8"]);
+        let peer = Some(ip("10.0.0.1"));
+
+        assert_eq!(proxies.client_ip(peer, None), peer);
+        assert_eq!(
+            proxies.client_ip(peer, Some("6.6.6.6, 1.2.3.4, 10.0.0.2")),
+            Some(ip("1.2.3.4"))
+        );
+        // An untrusted peer's header is ignored
+        assert_eq!(
+            proxies.client_ip(Some(ip("1.2.3.4")), Some("6.6.6.6")),
+            Some(ip("1.2.3.4"))
+        );
+        // A malformed hop stops the walk at the last trusted address
+        assert_eq!(
+            proxies.client_ip(peer, Some("1.2.3.4, garbage")),
+            Some(ip("10.0.0.1"))
+        );
+        assert_eq!(
+            proxies.client_ip(Some(ip("::ffff:10.0.0.1")), Some("::ffff:1.2.3.4")),
+            Some(ip("1.2.3.4"))
+        );
+        assert_eq!(proxies.client_ip(None, Some("1.2.3.4")), None);
+    }
+
+    #[test]
+    fn proxy_secret_matches_only_itself() {
+        let secret = ProxySecret::generate();
+        assert!(secret.matches(secret.as_str().as_bytes()));
+        assert!(!secret.matches(b""));
+        assert!(!secret.matches(ProxySecret::generate().as_str().as_bytes()));
+    }
+}
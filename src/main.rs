@@ -1,19 +1,65 @@
+mod auth;
 mod config;
 mod controllers;
-// mod grpc;
+mod grpc;
 mod http;
+mod logging;
+mod metrics;
 mod proto;
+mod rate_limit;
 mod services;
+mod shutdown;
+mod telemetry;
+mod tls;
+mod validation;
 
-use services::{create_order_service, OrderServiceFactoryImpl, UserServiceImpl};
+use config::Config;
+use services::{
+    cache_orders, create_order_service, CachedUserService, OrderServiceFactoryImpl, ShutdownHooks,
+    TtlCache, UserServiceImpl,
+};
+use std::net::{Ipv4Addr, TcpListener};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env_logger::init();
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cfg = Config::from_env();
+    logging::init(&cfg.log_format);
+    let tracer_provider = telemetry::init(
+        &cfg.otel_traces_exporter,
+        &cfg.otel_exporter_otlp_endpoint,
+        &cfg.otel_service_name,
+    )?;
 
-    // Singleton: one instance shared across all requests
-    let user_service = Arc::new(UserServiceImpl);
+    if !cfg.http_enabled && !cfg.grpc_enabled {
+        return Err("both HTTP_ENABLED and GRPC_ENABLED are false, nothing to serve".into());
+    }
+    if cfg.single_port && !(cfg.http_enabled && cfg.grpc_enabled) {
+        return Err("SINGLE_PORT requires both HTTP_ENABLED and GRPC_ENABLED".into());
+    }
+    // Fail at startup rather than on the first request if a (validate.rules) option is invalid
+    validation::Validator::shared();
+
+    // Single port: gRPC owns HTTP_PORT and forwards REST traffic to actix on loopback
+    let (http_listener, http_upstream) = if cfg.single_port {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let upstream = listener.local_addr()?;
+        let secret = logging::ProxySecret::generate();
+        (Some((listener, secret.clone())), Some((upstream, secret)))
+    } else {
+        (None, None)
+    };
+
+    // Singleton: one instance shared across all requests, and so is its cache
+    let cache_ttl = Duration::from_secs(cfg.cache_ttl);
+    let user_service = Arc::new(CachedUserService::new(
+        UserServiceImpl,
+        TtlCache::new("users", cfg.cache_capacity, cache_ttl),
+    ));
+    // Scoped and Transient instances come and go, their cache is process-wide
+    cache_orders(TtlCache::new("orders", cfg.cache_capacity, cache_ttl));
 
     // Scoped: factory creates new instance per request
     let order_service_factory = Arc::new(OrderServiceFactoryImpl);
@@ -21,5 +67,88 @@ async fn main() -> std::io::Result<()> {
     // Transient: function creates new instance every call
     let order_service_transient = create_order_service;
 
-    http::start(user_service, order_service_factory, order_service_transient).await
+    // Singleton: both servers spend from the same budgets
+    let rate_limiter = Arc::new(rate_limit::RateLimiter::from_config(&cfg).await?);
+
+    // Cancelled on signal, or as soon as either server exits, so they stop together
+    let shutdown = CancellationToken::new();
+    shutdown::cancel_on_signal(shutdown.clone());
+    ShutdownHooks::shared().register("order watchers", order_service_factory.clone());
+
+    // Singleton: both listeners serve the same certificates, swapped in place when they change
+    let tls = tls::Tls::from_config(&cfg)?.map(Arc::new);
+    if let Some(tls) = &tls
+        && cfg.tls_reload_interval > 0
+    {
+        let interval = Duration::from_secs(cfg.tls_reload_interval);
+        tokio::spawn(tls.clone().reload_on_change(interval, shutdown.clone()));
+    }
+
+    // Singletons wind down while the servers drain, and get the same deadline
+    let hooks = async {
+        shutdown.cancelled().await;
+        let delay = Duration::from_secs(cfg.shutdown_delay);
+        let deadline = Instant::now() + delay + Duration::from_secs(cfg.shutdown_timeout);
+        tokio::time::sleep(delay).await;
+        ShutdownHooks::shared().run(deadline).await;
+    };
+
+    let http_server = async {
+        if !cfg.http_enabled {
+            return Ok(());
+        }
+        let result = http::start(
+            user_service.clone(),
+            order_service_factory.clone(),
+            order_service_transient,
+            rate_limiter.clone(),
+            tls.clone(),
+            http_listener,
+            shutdown.clone(),
+        )
+        .await;
+        shutdown.cancel();
+        result.map_err(Box::<dyn std::error::Error>::from)
+    };
+
+    let grpc_server = async {
+        if !cfg.grpc_enabled {
+            return Ok(());
+        }
+        let result = grpc::start(
+            user_service.clone(),
+            order_service_factory.clone(),
+            rate_limiter.clone(),
+            tls.clone(),
+            http_upstream,
+            shutdown.clone(),
+        )
+        .await;
+        shutdown.cancel();
+        result
+    };
+
+    // On its own listener, whichever API servers run
+    let metrics_server = async {
+        if cfg.metrics_addr.is_empty() {
+            return Ok(());
+        }
+        let result = http::start_metrics(&cfg.metrics_addr, shutdown.clone()).await;
+        shutdown.cancel();
+        result.map_err(Box::<dyn std::error::Error>::from)
+    };
+
+    let (http_result, grpc_result, metrics_result, ()) =
+        tokio::join!(http_server, grpc_server, metrics_server, hooks);
+    // Export the spans still buffered
+    if let Some(provider) = tracer_provider
+        && let Err(e) = provider.shutdown()
+    {
+        log::error!("flushing traces failed: {e}");
+    }
+    log::logger().flush();
+    http_result?;
+    grpc_result?;
+    metrics_result?;
+    Ok(())
 }
@@ -0,0 +1,266 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Content type of the text exposition format served on /metrics
+pub const CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+/// Route label of requests no route matched, so scanners can't create new series
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Why JwtAuth turned a request away
+#[derive(Clone, Copy)]
+pub enum AuthRejection {
+    /// No bearer token or auth cookie
+    Missing,
+    /// Bad signature, malformed or expired
+    Invalid,
+    /// None of the required roles
+    ForbiddenRole,
+    /// Not every required policy
+    ForbiddenPolicy,
+}
+
+impl AuthRejection {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuthRejection::Missing => "missing",
+            AuthRejection::Invalid => "invalid",
+            AuthRejection::ForbiddenRole => "forbidden_role",
+            AuthRejection::ForbiddenPolicy => "forbidden_policy",
+        }
+    }
+}
+
+/// Decrements the in-flight gauge when the request is over
+pub struct InFlight(&'static str);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        Metrics::shared()
+            .in_flight
+            .with_label_values(&[self.0])
+            .dec();
+    }
+}
+
+/// Process-wide Prometheus registry shared by both servers and the services
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    grpc_calls: IntCounterVec,
+    grpc_duration: HistogramVec,
+    in_flight: IntGaugeVec,
+    auth_rejections: IntCounterVec,
+    api_version_requests: IntCounterVec,
+    orders_created: IntCounter,
+    users_created: IntCounter,
+    import_jobs: IntCounterVec,
+    rate_limited: IntCounterVec,
+    cache_lookups: IntCounterVec,
+}
+
+fn register<C: prometheus::core::Collector + Clone + 'static>(
+    registry: &Registry,
+    collector: C,
+) -> C {
+    registry
+        .register(Box::new(collector.clone()))
+        .expect("metric names are unique");
+    collector
+}
+
+fn counter_vec(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    let counter = IntCounterVec::new(Opts::new(name, help), labels).expect("metric is valid");
+    register(registry, counter)
+}
+
+fn histogram_vec(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> HistogramVec {
+    let histogram =
+        HistogramVec::new(HistogramOpts::new(name, help), labels).expect("metric is valid");
+    register(registry, histogram)
+}
+
+/// Request methods outside the standard set share one label value
+fn method_label(method: &str) -> &str {
+    match method {
+        "GET" | "HEAD" | "POST" | "PUT" | "DELETE" | "PATCH" | "OPTIONS" | "CONNECT" | "TRACE" => {
+            method
+        }
+        _ => "OTHER",
+    }
+}
+
+/// Names as used by other gRPC servers' metrics, e.g. `OK`, `NotFound`
+fn grpc_code_label(code: i32) -> String {
+    match tonic::Code::from_i32(code) {
+        tonic::Code::Ok => "OK".to_string(),
+        code => format!("{code:?}"),
+    }
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        Self {
+            http_requests: counter_vec(
+                &registry,
+                "http_requests_total",
+                "HTTP requests by method, route template and status",
+                &["method", "route", "status"],
+            ),
+            http_duration: histogram_vec(
+                &registry,
+                "http_request_duration_seconds",
+                "Time until the HTTP response body was sent",
+                &["method", "route", "status"],
+            ),
+            grpc_calls: counter_vec(
+                &registry,
+                "grpc_server_handled_total",
+                "gRPC calls by service, method and status code",
+                &["grpc_service", "grpc_method", "grpc_code"],
+            ),
+            grpc_duration: histogram_vec(
+                &registry,
+                "grpc_server_handling_seconds",
+                "Time until the gRPC response stream ended",
+                &["grpc_service", "grpc_method", "grpc_code"],
+            ),
+            in_flight: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new("requests_in_flight", "Requests being handled, by protocol"),
+                    &["protocol"],
+                )
+                .expect("metric is valid"),
+            ),
+            auth_rejections: counter_vec(
+                &registry,
+                "auth_rejections_total",
+                "Requests rejected by JWT authentication, by reason",
+                &["reason"],
+            ),
+            api_version_requests: counter_vec(
+                &registry,
+                "api_version_requests_total",
+                "API requests by version and how the version was chosen",
+                &["version", "source"],
+            ),
+            orders_created: register(
+                &registry,
+                IntCounter::new("orders_created_total", "Orders written to the store")
+                    .expect("metric is valid"),
+            ),
+            users_created: register(
+                &registry,
+                IntCounter::new("users_created_total", "Users written to the store")
+                    .expect("metric is valid"),
+            ),
+            import_jobs: counter_vec(
+                &registry,
+                "import_jobs_total",
+                "Finished bulk import jobs by resource and outcome",
+                &["kind", "status"],
+            ),
+            rate_limited: counter_vec(
+                &registry,
+                "rate_limited_total",
+                "Requests rejected by rate limiting, by the quota they exceeded",
+                &["quota"],
+            ),
+            cache_lookups: counter_vec(
+                &registry,
+                "cache_lookups_total",
+                "Service cache lookups by cache and result",
+                &["cache", "result"],
+            ),
+            registry,
+        }
+    }
+
+    pub fn shared() -> &'static Metrics {
+        static METRICS: OnceLock<Metrics> = OnceLock::new();
+        METRICS.get_or_init(Metrics::new)
+    }
+
+    /// Every metric in the Prometheus text format
+    pub fn render(&self) -> Result<String, String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| e.to_string())?;
+        String::from_utf8(buffer).map_err(|e| e.to_string())
+    }
+
+    /// Counts a request as in flight until the returned guard is dropped
+    pub fn in_flight(&self, protocol: &'static str) -> InFlight {
+        self.in_flight.with_label_values(&[protocol]).inc();
+        InFlight(protocol)
+    }
+
+    /// route is the template the request matched, e.g. `/api/v1/orders/{user_id}`
+    pub fn http_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let status = status.to_string();
+        let labels = [method_label(method), route, status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// path is `/package.Service/Method`. Unimplemented calls are counted under `unknown`,
+    /// since their path may be anything the client sent.
+    pub fn grpc_call(&self, path: &str, code: i32, elapsed: Duration) {
+        let (service, method) = match path.trim_start_matches('/').split_once('/') {
+            Some(parts) if code != tonic::Code::Unimplemented as i32 => parts,
+            _ => ("unknown", "unknown"),
+        };
+        let code = grpc_code_label(code);
+        let labels = [service, method, code.as_str()];
+        self.grpc_calls.with_label_values(&labels).inc();
+        self.grpc_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn auth_rejected(&self, reason: AuthRejection) {
+        self.auth_rejections
+            .with_label_values(&[reason.as_str()])
+            .inc();
+    }
+
+    pub fn api_version_request(&self, version: u32, source: &str) {
+        self.api_version_requests
+            .with_label_values(&[&format!("v{version}"), source])
+            .inc();
+    }
+
+    pub fn orders_created(&self, count: usize) {
+        self.orders_created.inc_by(count as u64);
+    }
+
+    pub fn users_created(&self, count: usize) {
+        self.users_created.inc_by(count as u64);
+    }
+
+    /// status is `succeeded`, `failed` or `interrupted`
+    pub fn import_job_finished(&self, kind: &str, status: &str) {
+        self.import_jobs.with_label_values(&[kind, status]).inc();
+    }
+
+    /// Counted as `hit` or `miss`
+    pub fn cache_lookup(&self, cache: &str, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.cache_lookups.with_label_values(&[cache, result]).inc();
+    }
+
+    /// quota is `default`, `role:<role>` or `route:<path prefix>`
+    pub fn rate_limited(&self, quota: &str) {
+        self.rate_limited.with_label_values(&[quota]).inc();
+    }
+}
@@ -1,2 +1,4 @@
 tonic::include_proto!("user");
 tonic::include_proto!("order");
+
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("api_descriptor");
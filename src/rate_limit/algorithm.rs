@@ -0,0 +1,335 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How a quota's budget is spent and refilled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Refills continuously at `limit / window`, so bursts of up to `limit` are allowed
+    TokenBucket,
+    /// Counts the current fixed window plus the previous one weighted by how much of
+    /// it still overlaps the last `window`
+    SlidingWindow,
+}
+
+impl Algorithm {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "token_bucket" => Ok(Algorithm::TokenBucket),
+            "sliding_window" => Ok(Algorithm::SlidingWindow),
+            other => Err(format!(
+                "unknown rate limit algorithm {other:?}, expected token_bucket or sliding_window"
+            )),
+        }
+    }
+}
+
+/// `limit` requests per `window`
+#[derive(Debug)]
+pub struct Quota {
+    /// Which quota a decision came from, e.g. `default`, `role:admin` or `route:/api/v1/imports`
+    pub name: String,
+    pub limit: u64,
+    pub window: Duration,
+}
+
+impl Quota {
+    /// `100/1m`: a limit, then a window in `s`, `m` or `h`. A bare window is in seconds.
+    pub fn parse(name: impl Into<String>, spec: &str) -> Result<Self, String> {
+        let name = name.into();
+        let invalid =
+            || format!("invalid rate limit quota {spec:?} for {name}, expected e.g. 100/1m");
+        let (limit, window) = spec.trim().split_once('/').ok_or_else(invalid)?;
+        let limit: u64 = limit.trim().parse().map_err(|_| invalid())?;
+        let window = window.trim();
+        let (amount, unit) = match window.char_indices().last() {
+            Some((i, unit @ ('s' | 'm' | 'h'))) => (&window[..i], unit),
+            _ => (window, 's'),
+        };
+        let amount: u64 = match amount {
+            "" => 1,
+            amount => amount.parse().map_err(|_| invalid())?,
+        };
+        let seconds = match unit {
+            'h' => amount * 3600,
+            'm' => amount * 60,
+            _ => amount,
+        };
+        if limit == 0 || seconds == 0 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            name,
+            limit,
+            window: Duration::from_secs(seconds),
+        })
+    }
+
+    pub fn window_ms(&self) -> u64 {
+        self.window.as_millis() as u64
+    }
+
+    /// Requests per millisecond, to compare quotas with different windows
+    pub fn rate(&self) -> f64 {
+        self.limit as f64 / self.window_ms() as f64
+    }
+}
+
+/// Outcome of spending one request from a caller's budget
+pub struct Decision {
+    pub quota: Arc<Quota>,
+    pub allowed: bool,
+    pub remaining: u64,
+    /// Until the whole budget is available again
+    pub reset: Duration,
+    /// Until the next request would be allowed, zero when this one was
+    pub retry_after: Duration,
+}
+
+fn millis(ms: f64) -> Duration {
+    Duration::from_millis(ms.max(0.0).ceil() as u64)
+}
+
+/// Decision from a token bucket holding `tokens` after the request
+pub fn token_bucket(quota: Arc<Quota>, tokens: f64, allowed: bool) -> Decision {
+    let rate = quota.rate();
+    Decision {
+        allowed,
+        remaining: tokens.max(0.0).floor() as u64,
+        reset: millis((quota.limit as f64 - tokens) / rate),
+        retry_after: if allowed {
+            Duration::ZERO
+        } else {
+            millis((1.0 - tokens) / rate)
+        },
+        quota,
+    }
+}
+
+/// Decision from sliding window counters after a request at `now_ms`
+pub fn sliding_window(
+    quota: Arc<Quota>,
+    now_ms: u64,
+    previous: u64,
+    current: u64,
+    allowed: bool,
+) -> Decision {
+    let window = quota.window_ms() as f64;
+    let limit = quota.limit as f64;
+    let elapsed = (now_ms % quota.window_ms()) as f64;
+    let left = window - elapsed;
+    let estimate = previous as f64 * left / window + current as f64;
+
+    // Requests of the current window count fully until it ends, then fade out over the next
+    let reset = match (previous, current) {
+        (0, 0) => 0.0,
+        (_, 0) => left,
+        _ => left + window,
+    };
+    let retry_after = if allowed {
+        0.0
+    } else if current as f64 + 1.0 <= limit {
+        // Wait until enough of the previous window has slid out
+        window - (limit - 1.0 - current as f64) * window / previous as f64 - elapsed
+    } else {
+        // Wait for the next window, where this one's count fades out
+        left + window - (limit - 1.0) * window / current as f64
+    };
+
+    Decision {
+        allowed,
+        remaining: (limit - estimate).max(0.0).floor() as u64,
+        reset: millis(reset),
+        retry_after: millis(retry_after),
+        quota,
+    }
+}
+
+/// Per-key state of in-process stores. Shared stores run the same steps in a script.
+pub enum State {
+    TokenBucket {
+        tokens: f64,
+        updated_ms: u64,
+    },
+    SlidingWindow {
+        /// `now_ms / window_ms` of the current window
+        index: u64,
+        previous: u64,
+        current: u64,
+    },
+}
+
+impl State {
+    pub fn new(algorithm: Algorithm, quota: &Quota, now_ms: u64) -> Self {
+        match algorithm {
+            Algorithm::TokenBucket => State::TokenBucket {
+                tokens: quota.limit as f64,
+                updated_ms: now_ms,
+            },
+            Algorithm::SlidingWindow => State::SlidingWindow {
+                index: now_ms / quota.window_ms(),
+                previous: 0,
+                current: 0,
+            },
+        }
+    }
+
+    /// Spends one request if the budget allows it
+    pub fn acquire(&mut self, quota: Arc<Quota>, now_ms: u64) -> Decision {
+        match self {
+            State::TokenBucket { tokens, updated_ms } => {
+                let refilled = now_ms.saturating_sub(*updated_ms) as f64 * quota.rate();
+                *tokens = (*tokens + refilled).min(quota.limit as f64);
+                *updated_ms = now_ms;
+                let allowed = *tokens >= 1.0;
+                if allowed {
+                    *tokens -= 1.0;
+                }
+                token_bucket(quota, *tokens, allowed)
+            }
+            State::SlidingWindow {
+                index,
+                previous,
+                current,
+            } => {
+                let window = quota.window_ms();
+                let now_index = now_ms / window;
+                if now_index == *index + 1 {
+                    *previous = *current;
+                    *current = 0;
+                } else if now_index > *index + 1 {
+                    *previous = 0;
+                    *current = 0;
+                }
+                *index = now_index;
+                let left = (window - now_ms % window) as f64;
+                let estimate = *previous as f64 * left / window as f64 + *current as f64;
+                let allowed = estimate + 1.0 <= quota.limit as f64;
+                if allowed {
+                    *current += 1;
+                }
+                sliding_window(quota, now_ms, *previous, *current, allowed)
+            }
+        }
+    }
+
+    /// The whole budget is back, so the key can be forgotten
+    pub fn idle(&self, quota: &Quota, now_ms: u64) -> bool {
+        match self {
+            State::TokenBucket { tokens, updated_ms } => {
+                let refilled = now_ms.saturating_sub(*updated_ms) as f64 * quota.rate();
+                tokens + refilled >= quota.limit as f64
+            }
+            State::SlidingWindow { index, .. } => now_ms / quota.window_ms() > index + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota(limit: u64, window_ms: u64) -> Arc<Quota> {
+        Arc::new(Quota {
+            name: "default".to_string(),
+            limit,
+            window: Duration::from_millis(window_ms),
+        })
+    }
+
+    /// Spends `count` requests at `now_ms`, all of which must be allowed
+    fn fill(state: &mut State, quota: &Arc<Quota>, now_ms: u64, count: u64) {
+        for _ in 0..count {
+            assert!(state.acquire(quota.clone(), now_ms).allowed);
+        }
+    }
+
+    #[test]
+    fn quota_parse_reads_limit_and_window() {
+        let quota = Quota::parse("default", "100/1m").unwrap();
+        assert_eq!((quota.limit, quota.window), (100, Duration::from_secs(60)));
+        let quota = Quota::parse("default", " 5 / h ").unwrap();
+        assert_eq!((quota.limit, quota.window), (5, Duration::from_secs(3600)));
+        let quota = Quota::parse("default", "10/30").unwrap();
+        assert_eq!(quota.window, Duration::from_secs(30));
+
+        for spec in ["", "100", "0/1m", "10/0s", "x/1m", "10/1d"] {
+            assert!(Quota::parse("default", spec).is_err(), "{spec:?}");
+        }
+    }
+
+    #[test]
+    fn algorithm_parse_rejects_unknown_names() {
+        assert_eq!(Algorithm::parse("token_bucket"), Ok(Algorithm::TokenBucket));
+        assert_eq!(
+            Algorithm::parse("sliding_window"),
+            Ok(Algorithm::SlidingWindow)
+        );
+        assert!(Algorithm::parse("leaky_bucket").is_err());
+    }
+
+    #[test]
+    fn token_bucket_allows_a_burst_then_refills() {
+        let quota = quota(2, 1000);
+        let mut state = State::new(Algorithm::TokenBucket, &quota, 0);
+        fill(&mut state, &quota, 0, 2);
+
+        let denied = state.acquire(quota.clone(), 0);
+        assert!(!denied.allowed);
+        assert_eq!(denied.remaining, 0);
+        assert_eq!(denied.retry_after, Duration::from_millis(500));
+        assert_eq!(denied.reset, Duration::from_millis(1000));
+
+        assert!(!state.acquire(quota.clone(), 499).allowed);
+        assert!(state.acquire(quota.clone(), 500).allowed);
+        assert!(!state.idle(&quota, 1499));
+        assert!(state.idle(&quota, 1500));
+    }
+
+    #[test]
+    fn sliding_window_retry_after_waits_for_the_previous_window_to_slide_out() {
+        let quota = quota(10, 1000);
+        let mut state = State::new(Algorithm::SlidingWindow, &quota, 0);
+        fill(&mut state, &quota, 0, 10);
+
+        // The previous window still counts fully at the start of the next one
+        let denied = state.acquire(quota.clone(), 1000);
+        assert!(!denied.allowed);
+        assert_eq!(denied.retry_after, Duration::from_millis(100));
+        assert_eq!(denied.reset, Duration::from_millis(1000));
+
+        assert!(!state.acquire(quota.clone(), 1099).allowed);
+        let allowed = state.acquire(quota.clone(), 1100);
+        assert!(allowed.allowed);
+        assert_eq!(allowed.retry_after, Duration::ZERO);
+        assert_eq!(allowed.remaining, 0);
+    }
+
+    #[test]
+    fn sliding_window_retry_after_waits_for_the_current_window_to_fade() {
+        let quota = quota(10, 1000);
+        let mut state = State::new(Algorithm::SlidingWindow, &quota, 1500);
+        fill(&mut state, &quota, 1500, 10);
+
+        // Full within its own window, so the next window has to discount it first
+        let denied = state.acquire(quota.clone(), 1600);
+        assert!(!denied.allowed);
+        assert_eq!(denied.retry_after, Duration::from_millis(500));
+        assert_eq!(denied.reset, Duration::from_millis(1400));
+
+        assert!(!state.acquire(quota.clone(), 2099).allowed);
+        assert!(state.acquire(quota.clone(), 2100).allowed);
+    }
+
+    #[test]
+    fn sliding_window_forgets_keys_after_two_windows() {
+        let quota = quota(10, 1000);
+        let mut state = State::new(Algorithm::SlidingWindow, &quota, 0);
+        fill(&mut state, &quota, 0, 3);
+        assert!(!state.idle(&quota, 1999));
+        assert!(state.idle(&quota, 2000));
+
+        let decision = state.acquire(quota.clone(), 2000);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 9);
+    }
+}
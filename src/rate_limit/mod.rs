@@ -0,0 +1,418 @@
+mod algorithm;
+mod store;
+pub use algorithm::{Algorithm, Decision, Quota};
+pub use store::{MemoryStore, RateLimitStore, RedisStore};
+
+use crate::auth::Claims;
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::services::AppError;
+use jsonwebtoken::{Algorithm as JwtAlgorithm, DecodingKey, Validation, decode};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Response headers describing the caller's budget, lowercase for `HeaderName::from_static`
+pub const POLICY: &str = "ratelimit-policy";
+pub const LIMIT: &str = "ratelimit-limit";
+pub const REMAINING: &str = "ratelimit-remaining";
+pub const RESET: &str = "ratelimit-reset";
+
+/// Request header naming the caller's API key
+pub const API_KEY: &str = "x-api-key";
+
+/// Role whose quota applies to callers identified by an API key
+const API_KEY_ROLE: &str = "api_key";
+
+/// Never limited, so probes keep working while clients are throttled
+const EXEMPT: &[&str] = &["/health", "/grpc.health.v1.Health/"];
+
+/// Whether `path` is `prefix` or below it: `/api/v1/users` covers `/api/v1/users/7`
+/// but not `/api/v1/users-export`
+fn under(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/'))
+}
+
+impl Decision {
+    /// RateLimit-* headers as in draft-ietf-httpapi-ratelimit-headers
+    pub fn headers(&self) -> [(&'static str, String); 4] {
+        [
+            (
+                POLICY,
+                format!("{};w={}", self.quota.limit, self.quota.window.as_secs()),
+            ),
+            (LIMIT, self.quota.limit.to_string()),
+            (REMAINING, self.remaining.to_string()),
+            (RESET, self.reset.as_secs_f64().ceil().to_string()),
+        ]
+    }
+
+    pub fn error(&self) -> AppError {
+        // Whole seconds, rounded up so a client retrying on time is let through
+        AppError::RateLimited {
+            retry_after: Duration::from_secs(self.retry_after.as_secs_f64().ceil() as u64),
+        }
+    }
+}
+
+/// The claims needed to identify a caller. JwtAuth still decides whether the token is accepted.
+#[derive(Deserialize)]
+struct TokenClaims {
+    sub: String,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+/// Who a request is counted against
+pub struct Caller {
+    /// `user:<sub>`, `cert:<sub>`, `key:<name>` or `ip:<address>`
+    key: String,
+    roles: Vec<String>,
+}
+
+/// Quotas from config, checked by the actix middleware and the tonic layer against one store,
+/// so a caller has a single budget whichever protocol they use
+pub struct RateLimiter {
+    enabled: bool,
+    algorithm: Algorithm,
+    default: Arc<Quota>,
+    roles: HashMap<String, Arc<Quota>>,
+    /// Longest path prefix first, so the most specific route wins
+    routes: Vec<(String, Arc<Quota>)>,
+    /// API key to its name
+    api_keys: HashMap<String, String>,
+    decoding_key: DecodingKey,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    pub async fn from_config(cfg: &Config) -> Result<Self, String> {
+        let roles = cfg
+            .rate_limit_roles
+            .iter()
+            .map(|(role, spec)| {
+                Ok((
+                    role.clone(),
+                    Arc::new(Quota::parse(format!("role:{role}"), spec)?),
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        let mut routes = cfg
+            .rate_limit_routes
+            .iter()
+            .map(|(route, spec)| {
+                Ok((
+                    route.clone(),
+                    Arc::new(Quota::parse(format!("route:{route}"), spec)?),
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        routes.sort_by_key(|(route, _)| std::cmp::Reverse(route.len()));
+
+        let store: Arc<dyn RateLimitStore> = match cfg.rate_limit_store.as_str() {
+            // Nothing is counted while disabled, so Redis need not be reachable
+            _ if !cfg.rate_limit_enabled => Arc::new(MemoryStore::default()),
+            "memory" => Arc::new(MemoryStore::default()),
+            url if url.starts_with("redis://") => Arc::new(RedisStore::connect(url).await?),
+            other => {
+                return Err(format!(
+                    "unknown RATE_LIMIT_STORE {other:?}, expected memory or a redis:// URL"
+                ));
+            }
+        };
+
+        Ok(Self {
+            enabled: cfg.rate_limit_enabled,
+            algorithm: Algorithm::parse(&cfg.rate_limit_algorithm)?,
+            default: Arc::new(Quota::parse("default", &cfg.rate_limit_default)?),
+            roles,
+            routes,
+            api_keys: cfg
+                .rate_limit_api_keys
+                .iter()
+                .map(|(name, key)| (key.clone(), name.clone()))
+                .collect(),
+            decoding_key: DecodingKey::from_secret(cfg.jwt_secret.as_bytes()),
+            store,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The subject of a valid token first, then of a verified client certificate, then a known
+    /// API key, then the client IP. Invalid tokens and unknown keys are not rejected here, they
+    /// fall through to the next.
+    pub fn caller(
+        &self,
+        token: Option<&str>,
+        certificate: Option<&Claims>,
+        api_key: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Caller {
+        let claims = token.and_then(|token| {
+            decode::<TokenClaims>(
+                token,
+                &self.decoding_key,
+                &Validation::new(JwtAlgorithm::HS256),
+            )
+            .ok()
+        });
+        if let Some(claims) = claims {
+            return Caller {
+                key: format!("user:{}", claims.claims.sub),
+                roles: claims.claims.roles,
+            };
+        }
+        if let Some(certificate) = certificate {
+            return Caller {
+                key: format!("cert:{}", certificate.sub),
+                roles: certificate.roles.clone(),
+            };
+        }
+        if let Some(name) = api_key.and_then(|key| self.api_keys.get(key)) {
+            return Caller {
+                key: format!("key:{name}"),
+                roles: vec![API_KEY_ROLE.to_string()],
+            };
+        }
+        Caller {
+            key: format!("ip:{}", ip.map(|ip| ip.to_string()).unwrap_or_default()),
+            roles: Vec::new(),
+        }
+    }
+
+    /// The most generous quota among the caller's roles, or the default
+    fn quota(&self, caller: &Caller) -> Arc<Quota> {
+        caller
+            .roles
+            .iter()
+            .filter_map(|role| self.roles.get(role))
+            .max_by(|a, b| a.rate().total_cmp(&b.rate()))
+            .unwrap_or(&self.default)
+            .clone()
+    }
+
+    /// A failing store lets the request through rather than failing every request
+    async fn acquire(&self, key: &str, quota: Arc<Quota>) -> Option<Decision> {
+        match self.store.acquire(key, quota, self.algorithm).await {
+            Ok(decision) => Some(decision),
+            Err(e) => {
+                log::warn!("rate limit store failed, request let through: {e}");
+                None
+            }
+        }
+    }
+
+    /// Spends one request of the caller's budget, and of their budget for the route when
+    /// it has its own quota. Returns the decision closest to its limit, or None when the
+    /// path is exempt or limiting is off.
+    pub async fn check(&self, path: &str, caller: &Caller) -> Option<Decision> {
+        if !self.enabled || EXEMPT.iter().any(|exempt| under(path, exempt)) {
+            return None;
+        }
+        let mut decision = self.acquire(&caller.key, self.quota(caller)).await;
+        if decision.as_ref().is_none_or(|d| d.allowed)
+            && let Some((route, quota)) = self.routes.iter().find(|(route, _)| under(path, route))
+            && let Some(route) = self
+                .acquire(&format!("{}|{route}", caller.key), quota.clone())
+                .await
+            && decision
+                .as_ref()
+                .is_none_or(|d| !route.allowed || route.remaining < d.remaining)
+        {
+            decision = Some(route);
+        }
+        if let Some(d) = &decision
+            && !d.allowed
+        {
+            Metrics::shared().rate_limited(&d.quota.name);
+        }
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use jsonwebtoken::{EncodingKey, Header, encode};
+
+    const SECRET: &str = "secret";
+
+    fn quota(name: &str, spec: &str) -> Arc<Quota> {
+        Arc::new(Quota::parse(name, spec).unwrap())
+    }
+
+    fn limiter(store: Arc<dyn RateLimitStore>) -> RateLimiter {
+        RateLimiter {
+            enabled: true,
+            algorithm: Algorithm::TokenBucket,
+            default: quota("default", "100/1m"),
+            roles: [
+                ("reader", "10/1m"),
+                ("admin", "1000/1m"),
+                // More requests than admin, but spread over a longer window
+                ("batch", "5000/1h"),
+            ]
+            .into_iter()
+            .map(|(role, spec)| (role.to_string(), quota(&format!("role:{role}"), spec)))
+            .collect(),
+            routes: vec![(
+                "/api/v1/imports".to_string(),
+                quota("route:/api/v1/imports", "1/1m"),
+            )],
+            api_keys: HashMap::from([("k-123".to_string(), "billing".to_string())]),
+            decoding_key: DecodingKey::from_secret(SECRET.as_bytes()),
+            store,
+        }
+    }
+
+    fn claims(sub: &str, roles: &[&str]) -> Claims {
+        Claims {
+            sub: sub.to_string(),
+            exp: 4_000_000_000,
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            policies: Vec::new(),
+        }
+    }
+
+    fn token(sub: &str, roles: &[&str]) -> String {
+        encode(
+            &Header::default(),
+            &claims(sub, roles),
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    struct FailingStore;
+
+    #[async_trait]
+    impl RateLimitStore for FailingStore {
+        async fn acquire(&self, _: &str, _: Arc<Quota>, _: Algorithm) -> Result<Decision, String> {
+            Err("connection refused".to_string())
+        }
+    }
+
+    #[test]
+    fn caller_prefers_token_then_certificate_then_api_key_then_ip() {
+        let limiter = limiter(Arc::new(MemoryStore::default()));
+        let token = token("u1", &["admin"]);
+        let certificate = claims("billing", &["reader"]);
+        let ip = Some(IpAddr::from([10, 0, 0, 1]));
+
+        let caller = limiter.caller(Some(&token), Some(&certificate), Some("k-123"), ip);
+        assert_eq!(
+            (caller.key.as_str(), caller.roles),
+            ("user:u1", vec!["admin".to_string()])
+        );
+
+        // Invalid tokens and unknown keys fall through
+        let caller = limiter.caller(Some("not-a-jwt"), Some(&certificate), Some("k-123"), ip);
+        assert_eq!(
+            (caller.key.as_str(), caller.roles),
+            ("cert:billing", vec!["reader".to_string()])
+        );
+
+        let caller = limiter.caller(Some("not-a-jwt"), None, Some("k-123"), ip);
+        assert_eq!(
+            (caller.key.as_str(), caller.roles),
+            ("key:billing", vec![API_KEY_ROLE.to_string()])
+        );
+
+        let caller = limiter.caller(None, None, Some("unknown"), ip);
+        assert_eq!(caller.key, "ip:10.0.0.1");
+        assert!(caller.roles.is_empty());
+    }
+
+    #[test]
+    fn quota_is_the_most_generous_role() {
+        let limiter = limiter(Arc::new(MemoryStore::default()));
+        let caller = |roles: &[&str]| Caller {
+            key: "user:u1".to_string(),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        };
+
+        assert_eq!(
+            limiter.quota(&caller(&["reader", "admin", "batch"])).name,
+            "role:admin"
+        );
+        assert_eq!(
+            limiter.quota(&caller(&["reader", "unknown"])).name,
+            "role:reader"
+        );
+        assert_eq!(limiter.quota(&caller(&["unknown"])).name, "default");
+        assert_eq!(limiter.quota(&caller(&[])).name, "default");
+    }
+
+    #[tokio::test]
+    async fn check_applies_the_route_quota_on_top_of_the_caller_quota() {
+        let limiter = limiter(Arc::new(MemoryStore::default()));
+        let caller = limiter.caller(Some(&token("u1", &[])), None, None, None);
+
+        let decision = limiter.check("/api/v1/imports/7", &caller).await.unwrap();
+        assert!(decision.allowed);
+        assert_eq!(decision.quota.name, "route:/api/v1/imports");
+
+        let decision = limiter.check("/api/v1/imports", &caller).await.unwrap();
+        assert!(!decision.allowed);
+        assert_eq!(decision.quota.name, "route:/api/v1/imports");
+
+        // Other routes only spend the caller's budget
+        let decision = limiter
+            .check("/api/v1/imports-archive", &caller)
+            .await
+            .unwrap();
+        assert!(decision.allowed);
+        assert_eq!(
+            (decision.quota.name.as_str(), decision.remaining),
+            ("default", 97)
+        );
+
+        // Route budgets are per caller
+        let other = limiter.caller(Some(&token("u2", &[])), None, None, None);
+        assert!(
+            limiter
+                .check("/api/v1/imports", &other)
+                .await
+                .unwrap()
+                .allowed
+        );
+
+        assert!(limiter.check("/health", &caller).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn check_lets_requests_through_when_the_store_fails() {
+        let limiter = limiter(Arc::new(FailingStore));
+        let caller = limiter.caller(None, None, None, Some(IpAddr::from([10, 0, 0, 1])));
+
+        for _ in 0..3 {
+            assert!(limiter.check("/api/v1/imports", &caller).await.is_none());
+        }
+    }
+
+    #[test]
+    fn routes_match_whole_segments() {
+        assert!(under("/api/v1/users", "/api/v1/users"));
+        assert!(under("/api/v1/users/7", "/api/v1/users"));
+        assert!(!under("/api/v1/users-export", "/api/v1/users"));
+        assert!(!under("/api/v1/orders", "/api/v1/order"));
+        assert!(!under("/api/v1", "/api/v1/users"));
+        // A prefix ending in `/` is already a boundary
+        assert!(under(
+            "/order.OrderService/ImportOrders",
+            "/order.OrderService/"
+        ));
+        assert!(under(
+            "/grpc.health.v1.Health/Check",
+            "/grpc.health.v1.Health/"
+        ));
+        assert!(!under("/healthz", "/health"));
+    }
+}
@@ -0,0 +1,206 @@
+use super::algorithm::{self, Algorithm, Decision, Quota, State};
+use async_trait::async_trait;
+use redis::Script;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where budgets are kept. Every replica must use the same shared store
+/// for a quota to hold across the deployment rather than per pod.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Spends one request from `key`'s budget under `quota`
+    async fn acquire(
+        &self,
+        key: &str,
+        quota: Arc<Quota>,
+        algorithm: Algorithm,
+    ) -> Result<Decision, String>;
+}
+
+/// Idle keys are dropped once every this many requests
+const PRUNE_EVERY: u64 = 4096;
+
+/// Budgets of this process only, each replica enforces the quotas on its own
+pub struct MemoryStore {
+    started: Instant,
+    keys: Mutex<HashMap<String, (State, Arc<Quota>)>>,
+    requests: AtomicU64,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            keys: Mutex::new(HashMap::new()),
+            requests: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn acquire(
+        &self,
+        key: &str,
+        quota: Arc<Quota>,
+        algorithm: Algorithm,
+    ) -> Result<Decision, String> {
+        let now_ms = self.started.elapsed().as_millis() as u64;
+        let mut keys = self.keys.lock().map_err(|e| e.to_string())?;
+        if self.requests.fetch_add(1, Ordering::Relaxed) % PRUNE_EVERY == PRUNE_EVERY - 1 {
+            keys.retain(|_, (state, quota)| !state.idle(quota, now_ms));
+        }
+        let (state, _) = keys
+            .entry(key.to_string())
+            .or_insert_with(|| (State::new(algorithm, &quota, now_ms), quota.clone()));
+        Ok(state.acquire(quota, now_ms))
+    }
+}
+
+/// Redis is asked for the time, so replicas with skewed clocks still agree
+const TOKEN_BUCKET: &str = r"
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(state[1]) or limit
+local updated = tonumber(state[2]) or now
+tokens = math.min(limit, tokens + math.max(0, now - updated) * limit / window)
+local allowed = 0
+if tokens >= 1 then
+  allowed = 1
+  tokens = tokens - 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', now)
+redis.call('PEXPIRE', KEYS[1], window)
+return {allowed, tostring(tokens)}
+";
+
+const SLIDING_WINDOW: &str = r"
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local index = math.floor(now / window)
+local state = redis.call('HMGET', KEYS[1], 'index', 'previous', 'current')
+local last = tonumber(state[1]) or index
+local previous = tonumber(state[2]) or 0
+local current = tonumber(state[3]) or 0
+if index == last + 1 then
+  previous = current
+  current = 0
+elseif index > last + 1 then
+  previous = 0
+  current = 0
+end
+local left = window - now % window
+local allowed = 0
+if previous * left / window + current + 1 <= limit then
+  allowed = 1
+  current = current + 1
+end
+redis.call('HSET', KEYS[1], 'index', index, 'previous', previous, 'current', current)
+redis.call('PEXPIRE', KEYS[1], window * 2)
+return {allowed, now, previous, current}
+";
+
+/// A slow Redis must not hold up requests, they are let through instead
+const REDIS_TIMEOUT: Duration = Duration::from_millis(250);
+const REDIS_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Budgets shared by every replica using the same Redis
+pub struct RedisStore {
+    connection: ConnectionManager,
+    token_bucket: Script,
+    sliding_window: Script,
+}
+
+impl RedisStore {
+    /// `url` is e.g. `redis://cache:6379/0`
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let client = redis::Client::open(url).map_err(|e| format!("{url}: {e}"))?;
+        let config = ConnectionManagerConfig::new()
+            .set_number_of_retries(1)
+            .set_connection_timeout(REDIS_CONNECT_TIMEOUT)
+            .set_response_timeout(REDIS_TIMEOUT);
+        // Later reconnects happen in the background, the first connection is waited for
+        let connection = tokio::time::timeout(
+            REDIS_CONNECT_TIMEOUT,
+            ConnectionManager::new_with_config(client, config),
+        )
+        .await
+        .map_err(|_| {
+            format!(
+                "{url}: no connection within {} s",
+                REDIS_CONNECT_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(|e| format!("{url}: {e}"))?;
+        Ok(Self {
+            connection,
+            token_bucket: Script::new(TOKEN_BUCKET),
+            sliding_window: Script::new(SLIDING_WINDOW),
+        })
+    }
+
+    async fn run(
+        &self,
+        key: &str,
+        quota: Arc<Quota>,
+        algorithm: Algorithm,
+    ) -> redis::RedisResult<Decision> {
+        let mut connection = self.connection.clone();
+        let key = format!("ratelimit:{key}");
+        match algorithm {
+            Algorithm::TokenBucket => {
+                let (allowed, tokens): (i64, String) = self
+                    .token_bucket
+                    .key(key)
+                    .arg(quota.limit)
+                    .arg(quota.window_ms())
+                    .invoke_async(&mut connection)
+                    .await?;
+                let tokens = tokens.parse().unwrap_or_default();
+                Ok(algorithm::token_bucket(quota, tokens, allowed == 1))
+            }
+            Algorithm::SlidingWindow => {
+                let (allowed, now_ms, previous, current): (i64, u64, u64, u64) = self
+                    .sliding_window
+                    .key(key)
+                    .arg(quota.limit)
+                    .arg(quota.window_ms())
+                    .invoke_async(&mut connection)
+                    .await?;
+                Ok(algorithm::sliding_window(
+                    quota,
+                    now_ms,
+                    previous,
+                    current,
+                    allowed == 1,
+                ))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for RedisStore {
+    async fn acquire(
+        &self,
+        key: &str,
+        quota: Arc<Quota>,
+        algorithm: Algorithm,
+    ) -> Result<Decision, String> {
+        match tokio::time::timeout(REDIS_TIMEOUT, self.run(key, quota, algorithm)).await {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => Err(format!(
+                "no answer from Redis within {} ms",
+                REDIS_TIMEOUT.as_millis()
+            )),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use super::error::AppError;
+use crate::metrics::Metrics;
+use lru::LruCache;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// In-memory cache of service results: the least recently used entry is evicted once
+/// `capacity` is reached, and entries older than `ttl` are loaded again
+pub struct TtlCache<V> {
+    /// Label of the cache metrics, e.g. `users`
+    name: &'static str,
+    /// None when caching is off
+    entries: Option<Mutex<LruCache<String, (V, Instant)>>>,
+    ttl: Duration,
+    /// Bumped by every invalidation, so a load that raced with one is not stored
+    generation: AtomicU64,
+}
+
+impl<V: Clone> TtlCache<V> {
+    /// Caches nothing when capacity or ttl is zero
+    pub fn new(name: &'static str, capacity: usize, ttl: Duration) -> Self {
+        let entries = NonZeroUsize::new(capacity)
+            .filter(|_| !ttl.is_zero())
+            .map(|capacity| Mutex::new(LruCache::new(capacity)));
+        Self {
+            name,
+            entries,
+            ttl,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<V> {
+        let mut entries = self.entries.as_ref()?.lock().ok()?;
+        match entries.get(key) {
+            Some((value, stored)) if stored.elapsed() < self.ttl => Some(value.clone()),
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// The cached value, or the result of `load`, which is cached when it succeeds
+    pub async fn get_or_load<F, Fut>(&self, key: &str, load: F) -> Result<V, AppError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, AppError>>,
+    {
+        let Some(entries) = &self.entries else {
+            return load().await;
+        };
+        if let Some(value) = self.get(key) {
+            Metrics::shared().cache_lookup(self.name, true);
+            return Ok(value);
+        }
+        Metrics::shared().cache_lookup(self.name, false);
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let value = load().await?;
+        if let Ok(mut entries) = entries.lock()
+            && self.generation.load(Ordering::Acquire) == generation
+        {
+            entries.put(key.to_string(), (value.clone(), Instant::now()));
+        }
+        Ok(value)
+    }
+
+    pub fn invalidate(&self, key: &str) {
+        if let Some(entries) = &self.entries
+            && let Ok(mut entries) = entries.lock()
+        {
+            self.generation.fetch_add(1, Ordering::AcqRel);
+            entries.pop(key);
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Some(entries) = &self.entries
+            && let Ok(mut entries) = entries.lock()
+        {
+            self.generation.fetch_add(1, Ordering::AcqRel);
+            entries.clear();
+        }
+    }
+}
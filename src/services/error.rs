@@ -0,0 +1,74 @@
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Clone, Debug)]
+pub struct FieldViolation {
+    pub field: String,
+    pub description: String,
+}
+
+impl FieldViolation {
+    pub fn new(field: &str, description: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            description: description.to_string(),
+        }
+    }
+}
+
+/// Failure of a service call, mapped to an HTTP status by `ResponseError`
+/// and to a gRPC code by `From<AppError> for tonic::Status` (see controllers::error)
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("{resource} {id} not found")]
+    NotFound { resource: &'static str, id: String },
+
+    /// Every violation found, not just the first
+    #[error("request has {} invalid field(s)", .0.len())]
+    Validation(Vec<FieldViolation>),
+
+    #[allow(dead_code)] // No service raises it yet
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("rate limit exceeded, retry in {}s", .retry_after.as_secs())]
+    RateLimited { retry_after: Duration },
+
+    /// Temporarily unable to serve, e.g. while shutting down; worth retrying elsewhere
+    #[error("{0}")]
+    Unavailable(String),
+
+    /// The message is logged, never sent to the client
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl AppError {
+    /// Stable machine readable code, shared by both transports
+    pub fn reason(&self) -> &'static str {
+        match self {
+            AppError::NotFound { .. } => "NOT_FOUND",
+            AppError::Validation(_) => "VALIDATION_FAILED",
+            AppError::Conflict(_) => "CONFLICT",
+            AppError::Unauthorized(_) => "UNAUTHORIZED",
+            AppError::Forbidden(_) => "FORBIDDEN",
+            AppError::RateLimited { .. } => "RATE_LIMITED",
+            AppError::Unavailable(_) => "UNAVAILABLE",
+            AppError::Internal(_) => "INTERNAL",
+        }
+    }
+
+    /// Message safe to return to clients
+    pub fn public_message(&self) -> String {
+        match self {
+            AppError::Internal(_) => "internal server error".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::RwLock;
+use tokio::sync::broadcast;
+
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// In-process publish/subscribe channel for domain events.
+/// Subscribers that fall more than `capacity` events behind lose the oldest ones.
+pub struct EventBus<E: Clone> {
+    /// None once closed
+    sender: RwLock<Option<broadcast::Sender<E>>>,
+}
+
+impl<E: Clone> EventBus<E> {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender: RwLock::new(Some(sender)),
+        }
+    }
+
+    /// Publishing with no subscribers is not an error, the event is simply dropped
+    pub fn publish(&self, event: E) {
+        if let Ok(sender) = self.sender.read()
+            && let Some(sender) = sender.as_ref()
+        {
+            let _ = sender.send(event);
+        }
+    }
+
+    /// After `close`, the receiver is closed from the start
+    pub fn subscribe(&self) -> broadcast::Receiver<E> {
+        match self.sender.read().ok().as_ref().and_then(|s| s.as_ref()) {
+            Some(sender) => sender.subscribe(),
+            None => broadcast::channel(1).1,
+        }
+    }
+
+    /// Ends every subscription once it has received the events already published
+    pub fn close(&self) {
+        if let Ok(mut sender) = self.sender.write() {
+            sender.take();
+        }
+    }
+}
+
+impl<E: Clone> Default for EventBus<E> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// Implemented by services that can report whether they are able to serve requests
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    /// Err carries a human readable reason
+    async fn check(&self) -> Result<(), String>;
+}
+
+/// Fails when the file system holding `path` has less than `min_free_bytes` available
+pub struct DiskSpace {
+    pub path: PathBuf,
+    pub min_free_bytes: u64,
+}
+
+#[cfg(unix)]
+fn free_bytes(path: &Path) -> Result<u64, String> {
+    let stat = rustix::fs::statvfs(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(stat.f_bavail.saturating_mul(stat.f_frsize))
+}
+
+#[cfg(not(unix))]
+fn free_bytes(_path: &Path) -> Result<u64, String> {
+    Ok(u64::MAX)
+}
+
+#[async_trait]
+impl HealthCheck for DiskSpace {
+    async fn check(&self) -> Result<(), String> {
+        let free = free_bytes(&self.path)?;
+        if free < self.min_free_bytes {
+            return Err(format!(
+                "{} MB free on {}, need {} MB",
+                free / 1_000_000,
+                self.path.display(),
+                self.min_free_bytes / 1_000_000
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A dependency reached over TCP, healthy while it accepts connections
+pub struct Downstream {
+    /// `host:port`
+    pub address: String,
+}
+
+#[async_trait]
+impl HealthCheck for Downstream {
+    async fn check(&self) -> Result<(), String> {
+        tokio::net::TcpStream::connect(&self.address)
+            .await
+            .map(drop)
+            .map_err(|e| format!("{}: {e}", self.address))
+    }
+}
+
+pub struct CheckResult {
+    pub name: String,
+    pub latency: Duration,
+    /// None when the check passed
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct HealthReport {
+    pub checks: Vec<CheckResult>,
+    /// Shutdown has started, so no new requests should be sent
+    pub shutting_down: bool,
+}
+
+impl HealthReport {
+    pub fn healthy(&self) -> bool {
+        !self.shutting_down && self.checks.iter().all(|c| c.error.is_none())
+    }
+}
+
+/// Named checks behind the /health endpoints, run concurrently with a timeout each
+pub struct HealthChecks {
+    checks: Vec<(String, Arc<dyn HealthCheck>)>,
+    timeout: Duration,
+    started: AtomicBool,
+    shutdown: CancellationToken,
+}
+
+impl HealthChecks {
+    pub fn new(timeout: Duration, shutdown: CancellationToken) -> Self {
+        Self {
+            checks: Vec::new(),
+            timeout,
+            started: AtomicBool::new(false),
+            shutdown,
+        }
+    }
+
+    pub fn with(mut self, name: impl Into<String>, check: Arc<dyn HealthCheck>) -> Self {
+        self.checks.push((name.into(), check));
+        self
+    }
+
+    async fn run(&self) -> Vec<CheckResult> {
+        join_all(self.checks.iter().map(|(name, check)| async move {
+            let started = Instant::now();
+            let error = match tokio::time::timeout(self.timeout, check.check()).await {
+                Ok(result) => result.err(),
+                Err(_) => Some(format!("timed out after {} ms", self.timeout.as_millis())),
+            };
+            CheckResult {
+                name: name.clone(),
+                latency: started.elapsed(),
+                error,
+            }
+        }))
+        .await
+    }
+
+    /// Every check passes and shutdown has not started
+    pub async fn ready(&self) -> HealthReport {
+        if self.shutdown.is_cancelled() {
+            return HealthReport {
+                checks: Vec::new(),
+                shutting_down: true,
+            };
+        }
+        HealthReport {
+            checks: self.run().await,
+            shutting_down: false,
+        }
+    }
+
+    /// Every check has passed once; checks are not run again after that
+    pub async fn startup(&self) -> HealthReport {
+        if self.started.load(Ordering::Relaxed) {
+            return HealthReport::default();
+        }
+        let report = HealthReport {
+            checks: self.run().await,
+            shutting_down: false,
+        };
+        if report.healthy() {
+            self.started.store(true, Ordering::Relaxed);
+        }
+        report
+    }
+}
@@ -0,0 +1,634 @@
+use super::error::{AppError, FieldViolation};
+use super::order::{NewOrder, OrderImporter, OrderService};
+use super::shutdown::ShutdownHook;
+use super::user::{NewUser, UserService};
+use crate::metrics::Metrics;
+use async_trait::async_trait;
+use futures_util::FutureExt;
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// Jobs kept for status queries; the oldest finished ones are forgotten first
+const MAX_JOBS: usize = 100;
+/// Row errors kept per job, the counters still cover every row
+const MAX_ROW_ERRORS: usize = 1000;
+/// Threads of the runtime imports run on, apart from the servers' workers
+const IMPORT_WORKERS: usize = 2;
+/// How long interrupted jobs get to commit their last batch before they are aborted
+const INTERRUPT_GRACE: Duration = Duration::from_secs(5);
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Parsed upload. Each row carries its line in the uploaded file,
+/// rows that could not be parsed carry their violations instead.
+pub enum ImportRows {
+    Orders(Vec<(usize, Result<NewOrder, Vec<FieldViolation>>)>),
+    Users(Vec<(usize, Result<NewUser, Vec<FieldViolation>>)>),
+}
+
+impl ImportRows {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Orders(rows) => rows.len(),
+            Self::Users(rows) => rows.len(),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Orders(_) => "orders",
+            Self::Users(_) => "users",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ImportJobStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Clone)]
+pub struct RowError {
+    pub line: usize,
+    pub violations: Vec<FieldViolation>,
+}
+
+#[derive(Clone)]
+pub struct ImportJob {
+    pub id: String,
+    /// `orders` or `users`
+    pub kind: &'static str,
+    /// Rows are validated but nothing is written
+    pub dry_run: bool,
+    pub status: ImportJobStatus,
+    pub total: usize,
+    pub processed: usize,
+    /// Rows written, or rows that would be written in a dry run
+    pub imported: usize,
+    pub failed: usize,
+    pub errors: Vec<RowError>,
+    /// Why a failed job stopped; rows committed before that stay imported
+    pub error: Option<String>,
+    /// Unix timestamps in milliseconds
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+impl ImportJob {
+    fn record(&mut self, line: usize, result: Result<(), Vec<FieldViolation>>) {
+        self.processed += 1;
+        match result {
+            Ok(()) => self.imported += 1,
+            Err(violations) => {
+                self.failed += 1;
+                if self.errors.len() < MAX_ROW_ERRORS {
+                    self.errors.push(RowError { line, violations });
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// SINGLETON: Registry of bulk imports, run on a runtime of their own
+// ============================================================================
+pub struct ImportJobs {
+    jobs: RwLock<HashMap<String, ImportJob>>,
+    max_batch: usize,
+    /// Cancelled when shutdown can't wait any longer: jobs stop before their next row
+    interrupt: CancellationToken,
+    /// Jobs started and not yet joined, shutdown waits on them
+    tasks: Mutex<JoinSet<()>>,
+    /// Taken on drop, which may happen inside another runtime
+    runtime: Option<Runtime>,
+}
+
+impl ImportJobs {
+    pub fn new(max_batch: usize) -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(IMPORT_WORKERS)
+            .thread_name("import")
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            jobs: RwLock::new(HashMap::new()),
+            max_batch: max_batch.max(1),
+            interrupt: CancellationToken::new(),
+            tasks: Mutex::new(JoinSet::new()),
+            runtime: Some(runtime),
+        })
+    }
+
+    /// Every row before the interruption is committed, so the job can be resumed from there
+    fn interrupted(&self, id: &str) -> AppError {
+        let (processed, total) = self
+            .get(id)
+            .map(|job| (job.processed, job.total))
+            .unwrap_or_default();
+        log::warn!("import job {id} interrupted by shutdown after {processed} of {total} rows");
+        AppError::Unavailable(format!(
+            "interrupted by shutdown after {processed} of {total} rows, \
+             every row after that was not imported"
+        ))
+    }
+
+    pub fn get(&self, id: &str) -> Result<ImportJob, AppError> {
+        self.jobs
+            .read()
+            .map_err(|_| AppError::Internal("import job lock is poisoned".to_string()))?
+            .get(id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound {
+                resource: "import job",
+                id: id.to_string(),
+            })
+    }
+
+    /// Registers a running job for the rows, call `start` next
+    pub fn create(&self, rows: &ImportRows, dry_run: bool) -> Result<ImportJob, AppError> {
+        let job = ImportJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind: rows.kind(),
+            dry_run,
+            status: ImportJobStatus::Running,
+            total: rows.len(),
+            processed: 0,
+            imported: 0,
+            failed: 0,
+            errors: Vec::new(),
+            error: None,
+            started_at: now_millis(),
+            finished_at: None,
+        };
+        let mut jobs = self
+            .jobs
+            .write()
+            .map_err(|_| AppError::Internal("import job lock is poisoned".to_string()))?;
+        while jobs.len() >= MAX_JOBS {
+            let oldest = jobs
+                .values()
+                .filter(|j| j.status != ImportJobStatus::Running)
+                .min_by_key(|j| j.started_at)
+                .map(|j| j.id.clone());
+            match oldest {
+                Some(id) => jobs.remove(&id),
+                None => break,
+            };
+        }
+        jobs.insert(job.id.clone(), job.clone());
+        Ok(job)
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut ImportJob)) {
+        if let Ok(mut jobs) = self.jobs.write()
+            && let Some(job) = jobs.get_mut(id)
+        {
+            f(job);
+        }
+    }
+
+    /// Runs the job on the import runtime. The receiver gets the finished job,
+    /// dropping it leaves the job running in the background.
+    pub fn start(
+        self: Arc<Self>,
+        id: String,
+        rows: ImportRows,
+        dry_run: bool,
+        orders: Box<dyn OrderService>,
+        users: Arc<dyn UserService>,
+    ) -> oneshot::Receiver<Result<ImportJob, AppError>> {
+        let (done, receiver) = oneshot::channel();
+        let Some(runtime) = &self.runtime else {
+            return receiver;
+        };
+        let jobs = self.clone();
+        let task = async move {
+            let run = AssertUnwindSafe(jobs.run(&id, rows, dry_run, orders, users));
+            let result = match run.catch_unwind().await {
+                Ok(result) => result,
+                Err(_) => {
+                    log::error!("import job {id} panicked");
+                    jobs.fail(&id, "failed", "the import stopped unexpectedly");
+                    jobs.get(&id)
+                }
+            };
+            let _ = done.send(result);
+        };
+        let mut tasks = self.tasks.lock().unwrap_or_else(PoisonError::into_inner);
+        // Finished tasks are only reaped here and at shutdown
+        while tasks.try_join_next().is_some() {}
+        tasks.spawn_on(task, runtime.handle());
+        receiver
+    }
+
+    /// Marks a job that did not finish on its own, e.g. aborted by shutdown, as failed
+    fn fail(&self, id: &str, outcome: &str, error: &str) {
+        self.update(id, |job| {
+            if job.status != ImportJobStatus::Running {
+                return;
+            }
+            job.status = ImportJobStatus::Failed;
+            job.finished_at = Some(now_millis());
+            job.error = Some(error.to_string());
+            Metrics::shared().import_job_finished(job.kind, outcome);
+        });
+    }
+
+    /// Validates every row and commits valid ones in batches of max_batch,
+    /// updating the job's progress as batches complete
+    async fn run(
+        &self,
+        id: &str,
+        rows: ImportRows,
+        dry_run: bool,
+        orders: Box<dyn OrderService>,
+        users: Arc<dyn UserService>,
+    ) -> Result<ImportJob, AppError> {
+        let kind = rows.kind();
+        let result = match rows {
+            ImportRows::Orders(rows) => self.import_orders(id, rows, dry_run, orders).await,
+            ImportRows::Users(rows) => self.import_users(id, rows, dry_run, users).await,
+        };
+        // Interruptions are logged with their checkpoint already
+        if let Err(e) = &result
+            && !matches!(e, AppError::Unavailable(_))
+        {
+            log::error!("import job {id} failed: {e}");
+        }
+        self.update(id, |job| {
+            job.finished_at = Some(now_millis());
+            match result {
+                Ok(()) => {
+                    job.status = ImportJobStatus::Succeeded;
+                    Metrics::shared().import_job_finished(kind, "succeeded");
+                }
+                Err(e) => {
+                    job.status = ImportJobStatus::Failed;
+                    let outcome = match e {
+                        AppError::Unavailable(_) => "interrupted",
+                        _ => "failed",
+                    };
+                    Metrics::shared().import_job_finished(kind, outcome);
+                    job.error = Some(e.public_message());
+                }
+            }
+        });
+        self.get(id)
+    }
+
+    async fn import_orders(
+        &self,
+        id: &str,
+        rows: Vec<(usize, Result<NewOrder, Vec<FieldViolation>>)>,
+        dry_run: bool,
+        service: Box<dyn OrderService>,
+    ) -> Result<(), AppError> {
+        if dry_run {
+            for (i, (line, row)) in rows.into_iter().enumerate() {
+                self.between_batches(i).await;
+                if self.interrupt.is_cancelled() {
+                    return Err(self.interrupted(id));
+                }
+                let result = row.and_then(|order| order.validate());
+                self.update(id, |job| job.record(line, result));
+            }
+            return Ok(());
+        }
+
+        let lines: Vec<usize> = rows.iter().map(|(line, _)| *line).collect();
+        let mut importer = OrderImporter::new(service, self.max_batch);
+        for (i, (_, row)) in rows.into_iter().enumerate() {
+            self.between_batches(i).await;
+            if self.interrupt.is_cancelled() {
+                // Commit the rows already accepted, they count as processed
+                let outcomes = importer.finish().await?;
+                self.update(id, |job| {
+                    for outcome in outcomes {
+                        job.record(lines[outcome.index], outcome.result.map(|_| ()));
+                    }
+                });
+                return Err(self.interrupted(id));
+            }
+            let outcomes = match row {
+                Ok(order) => importer.push(order).await?,
+                Err(violations) => vec![importer.reject(violations)],
+            };
+            self.update(id, |job| {
+                for outcome in outcomes {
+                    job.record(lines[outcome.index], outcome.result.map(|_| ()));
+                }
+            });
+        }
+        let outcomes = importer.finish().await?;
+        self.update(id, |job| {
+            for outcome in outcomes {
+                job.record(lines[outcome.index], outcome.result.map(|_| ()));
+            }
+        });
+        Ok(())
+    }
+
+    async fn import_users(
+        &self,
+        id: &str,
+        rows: Vec<(usize, Result<NewUser, Vec<FieldViolation>>)>,
+        dry_run: bool,
+        service: Arc<dyn UserService>,
+    ) -> Result<(), AppError> {
+        let mut pending: Vec<(usize, NewUser)> = Vec::new();
+        for (i, (line, row)) in rows.into_iter().enumerate() {
+            self.between_batches(i).await;
+            if self.interrupt.is_cancelled() {
+                self.commit_users(id, pending, service.as_ref()).await?;
+                return Err(self.interrupted(id));
+            }
+            match row.and_then(|user| user.validate().map(|()| user)) {
+                Ok(user) if !dry_run => pending.push((line, user)),
+                result => self.update(id, |job| job.record(line, result.map(|_| ()))),
+            }
+            if pending.len() >= self.max_batch {
+                self.commit_users(id, std::mem::take(&mut pending), service.as_ref())
+                    .await?;
+            }
+        }
+        self.commit_users(id, pending, service.as_ref()).await
+    }
+
+    /// Lets other jobs and shutdown run once every max_batch rows
+    async fn between_batches(&self, row: usize) {
+        if row > 0 && row.is_multiple_of(self.max_batch) {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    async fn commit_users(
+        &self,
+        id: &str,
+        batch: Vec<(usize, NewUser)>,
+        service: &dyn UserService,
+    ) -> Result<(), AppError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let (lines, users): (Vec<usize>, Vec<NewUser>) = batch.into_iter().unzip();
+        service.import_users(users).await?;
+        self.update(id, |job| {
+            for line in lines {
+                job.record(line, Ok(()));
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Drop for ImportJobs {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which panics inside the servers' runtime
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+async fn join_all(tasks: &mut JoinSet<()>) {
+    while let Some(result) = tasks.join_next().await {
+        if let Err(e) = result
+            && e.is_panic()
+        {
+            log::error!("import task panicked: {e}");
+        }
+    }
+}
+
+#[async_trait]
+impl ShutdownHook for ImportJobs {
+    /// Lets running imports finish until the deadline, then stops them at their next row.
+    /// Jobs still running INTERRUPT_GRACE later are aborted and marked as interrupted.
+    async fn shutdown(&self, deadline: Instant) {
+        let mut tasks = {
+            let mut tasks = self.tasks.lock().unwrap_or_else(PoisonError::into_inner);
+            std::mem::take(&mut *tasks)
+        };
+        let deadline = tokio::time::Instant::from_std(deadline);
+        let finished = tokio::time::timeout_at(deadline, join_all(&mut tasks)).await;
+        if finished.is_err() {
+            self.interrupt.cancel();
+            // A job notices between rows, after at most one batch commit
+            let stopped = tokio::time::timeout(INTERRUPT_GRACE, join_all(&mut tasks)).await;
+            if stopped.is_err() {
+                tasks.shutdown().await;
+            }
+        }
+
+        let running: Vec<String> = self
+            .jobs
+            .read()
+            .map(|jobs| {
+                jobs.values()
+                    .filter(|j| j.status == ImportJobStatus::Running)
+                    .map(|j| j.id.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        for id in running {
+            log::warn!("import job {id} did not stop in time and was aborted");
+            self.fail(
+                &id,
+                "interrupted",
+                "aborted by shutdown, rows after the last committed batch were not imported",
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::health::HealthCheck;
+    use crate::services::order::{Order, OrderEvent, OrderFilter};
+    use crate::services::user::UserServiceImpl;
+    use futures_util::stream::BoxStream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::{Semaphore, broadcast};
+
+    /// Commits a batch for every permit the test adds, and waits for one otherwise
+    struct Gate {
+        permits: Semaphore,
+        /// Batches that reached the gate
+        arrived: AtomicUsize,
+    }
+
+    struct GatedOrders(Arc<Gate>);
+
+    #[async_trait]
+    impl OrderService for GatedOrders {
+        async fn get_orders(&self, _user_id: &str) -> Result<Vec<Order>, AppError> {
+            unimplemented!()
+        }
+
+        async fn get_order(&self, _user_id: &str, _order_id: &str) -> Result<Order, AppError> {
+            unimplemented!()
+        }
+
+        async fn create_order(&self, _order: NewOrder) -> Result<Order, AppError> {
+            unimplemented!()
+        }
+
+        async fn import_orders(&self, orders: Vec<NewOrder>) -> Result<Vec<Order>, AppError> {
+            self.0.arrived.fetch_add(1, Ordering::SeqCst);
+            self.0.permits.acquire().await.unwrap().forget();
+            Ok(orders
+                .into_iter()
+                .map(|order| Order {
+                    id: "1".to_string(),
+                    user_id: order.user_id,
+                    product: order.product,
+                    quantity: order.quantity,
+                    created_at: 0,
+                })
+                .collect())
+        }
+
+        fn watch_orders(&self) -> broadcast::Receiver<OrderEvent> {
+            unimplemented!()
+        }
+
+        fn export_orders(
+            &self,
+            _filter: OrderFilter,
+        ) -> Result<BoxStream<'static, Result<Vec<Order>, AppError>>, AppError> {
+            unimplemented!()
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for GatedOrders {
+        async fn check(&self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    const DEADLINE: Duration = Duration::from_secs(10);
+
+    /// A job importing `rows` orders one batch at a time, each batch committed once the gate
+    /// lets it through. The job runs on the import runtime, where the paused clock does not apply.
+    fn start(rows: usize) -> (Arc<ImportJobs>, String, Arc<Gate>) {
+        let jobs = Arc::new(ImportJobs::new(1).unwrap());
+        let rows = ImportRows::Orders(
+            (1..=rows)
+                .map(|line| {
+                    let order = NewOrder {
+                        user_id: "u1".to_string(),
+                        product: "Pen".to_string(),
+                        quantity: 1,
+                    };
+                    (line, Ok(order))
+                })
+                .collect(),
+        );
+        let job = jobs.create(&rows, false).unwrap();
+        let gate = Arc::new(Gate {
+            permits: Semaphore::new(0),
+            arrived: AtomicUsize::new(0),
+        });
+        drop(jobs.clone().start(
+            job.id.clone(),
+            rows,
+            false,
+            Box::new(GatedOrders(gate.clone())),
+            Arc::new(UserServiceImpl),
+        ));
+        (jobs, job.id, gate)
+    }
+
+    /// Polls until `done`. Busy, so the paused clock can't skip ahead while the import runtime
+    /// catches up.
+    async fn until(done: impl Fn() -> bool) {
+        while !done() {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    /// Waits until `batches` have reached the gate
+    async fn arrived(gate: &Gate, batches: usize) {
+        until(|| gate.arrived.load(Ordering::SeqCst) == batches).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_waits_for_jobs_until_the_deadline() {
+        let (jobs, id, gate) = start(3);
+        arrived(&gate, 1).await;
+        let started = tokio::time::Instant::now();
+        let shutdown = tokio::spawn({
+            let jobs = jobs.clone();
+            async move { jobs.shutdown(Instant::now() + DEADLINE).await }
+        });
+
+        tokio::time::sleep(DEADLINE - Duration::from_secs(1)).await;
+        assert!(!shutdown.is_finished());
+        assert!(!jobs.interrupt.is_cancelled());
+
+        gate.permits.add_permits(3);
+        until(|| shutdown.is_finished()).await;
+        shutdown.await.unwrap();
+        assert!(started.elapsed() < DEADLINE);
+        assert!(!jobs.interrupt.is_cancelled());
+        let job = jobs.get(&id).unwrap();
+        assert!(job.status == ImportJobStatus::Succeeded);
+        assert_eq!(job.imported, 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_interrupts_jobs_at_the_deadline() {
+        let (jobs, id, gate) = start(5);
+        gate.permits.add_permits(1);
+        arrived(&gate, 2).await;
+        let shutdown = tokio::spawn({
+            let jobs = jobs.clone();
+            async move { jobs.shutdown(Instant::now() + DEADLINE).await }
+        });
+
+        tokio::time::sleep(DEADLINE + Duration::from_secs(1)).await;
+        assert!(jobs.interrupt.is_cancelled());
+
+        // The batch in flight is still committed, the job stops before the next row
+        gate.permits.add_permits(1);
+        until(|| shutdown.is_finished()).await;
+        shutdown.await.unwrap();
+        let job = jobs.get(&id).unwrap();
+        assert!(job.status == ImportJobStatus::Failed);
+        assert_eq!((job.processed, job.imported), (2, 2));
+        assert!(
+            job.error
+                .unwrap()
+                .contains("interrupted by shutdown after 2 of 5 rows")
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_aborts_jobs_still_running_after_the_grace() {
+        let (jobs, id, gate) = start(2);
+        arrived(&gate, 1).await;
+        let started = tokio::time::Instant::now();
+
+        jobs.shutdown(Instant::now() + DEADLINE).await;
+        assert!(started.elapsed() >= DEADLINE + INTERRUPT_GRACE);
+        let job = jobs.get(&id).unwrap();
+        assert!(job.status == ImportJobStatus::Failed);
+        assert_eq!(job.processed, 0);
+        assert!(job.error.unwrap().starts_with("aborted by shutdown"));
+    }
+}
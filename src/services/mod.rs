@@ -1,11 +1,24 @@
+pub mod cache;
+pub mod error;
+pub mod events;
+pub mod health;
+pub mod import;
 pub mod order;
+pub mod shutdown;
 pub mod user;
 
+pub use cache::TtlCache;
+pub use error::{AppError, FieldViolation};
+pub use health::{CheckResult, DiskSpace, Downstream, HealthChecks, HealthReport};
 pub use order::{
-    Order, OrderService,
+    ImportOutcome, NewOrder, Order, OrderEvent, OrderEventKind, OrderFilter, OrderImporter,
     // Scoped
-    OrderServiceFactory, OrderServiceFactoryImpl,
+    OrderServiceFactory, OrderServiceFactoryImpl, ScopedOrderHealth,
     // Transient
     OrderServiceTransient, create_order_service,
+    // Cache
+    cache_orders,
 };
-pub use user::{UserService, UserServiceImpl};
+pub use import::{ImportJob, ImportJobStatus, ImportJobs, ImportRows};
+pub use shutdown::ShutdownHooks;
+pub use user::{CachedUserService, NewUser, User, UserService, UserServiceImpl};
@@ -1,36 +1,481 @@
+use super::cache::TtlCache;
+use super::error::{AppError, FieldViolation};
+use super::events::EventBus;
+use super::health::HealthCheck;
+use super::shutdown::ShutdownHook;
+use crate::metrics::Metrics;
+use crate::proto::CreateOrderRequest;
+use crate::telemetry;
+use crate::validation::Validator;
 use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
 
+#[derive(Clone)]
 pub struct Order {
     pub id: String,
     pub user_id: String,
     pub product: String,
     pub quantity: i32,
+    /// Unix timestamp in milliseconds
+    pub created_at: i64,
+}
+
+pub struct NewOrder {
+    pub user_id: String,
+    pub product: String,
+    pub quantity: i32,
+}
+
+impl NewOrder {
+    /// Checks the `(validate.rules)` of CreateOrderRequest, which every new order arrives as,
+    /// and returns every violation rather than stopping at the first
+    pub fn validate(&self) -> Result<(), Vec<FieldViolation>> {
+        let request = CreateOrderRequest {
+            user_id: self.user_id.clone(),
+            product: self.product.clone(),
+            quantity: self.quantity,
+        };
+        match Validator::shared().validate(&request) {
+            Ok(()) => Ok(()),
+            Err(AppError::Validation(violations)) => Err(violations),
+            Err(e) => Err(vec![FieldViolation::new("order", &e.public_message())]),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OrderEventKind {
+    Created,
+}
+
+#[derive(Clone)]
+pub struct OrderEvent {
+    pub kind: OrderEventKind,
+    pub order: Order,
+    /// Unix timestamp in milliseconds
+    pub occurred_at: i64,
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+impl OrderEvent {
+    fn now(kind: OrderEventKind, order: Order) -> Self {
+        Self { kind, order, occurred_at: now_millis() }
+    }
+}
+
+/// Criteria of an order export; unset fields match every order
+#[derive(Default)]
+pub struct OrderFilter {
+    pub user_id: Option<String>,
+    pub product: Option<String>,
+    pub min_quantity: Option<i32>,
+    pub max_quantity: Option<i32>,
+    /// Unix timestamp in milliseconds, inclusive
+    pub created_from: Option<i64>,
+    /// Unix timestamp in milliseconds, exclusive
+    pub created_to: Option<i64>,
+}
+
+impl OrderFilter {
+    pub fn matches(&self, order: &Order) -> bool {
+        self.user_id.as_ref().is_none_or(|u| *u == order.user_id)
+            && self.product.as_ref().is_none_or(|p| *p == order.product)
+            && self.min_quantity.is_none_or(|min| order.quantity >= min)
+            && self.max_quantity.is_none_or(|max| order.quantity <= max)
+            && self.created_from.is_none_or(|from| order.created_at >= from)
+            && self.created_to.is_none_or(|to| order.created_at < to)
+    }
+}
+
+// ============================================================================
+// STORAGE: In-memory orders shared by every OrderService instance (stands in for a database)
+// ============================================================================
+pub struct OrderStore {
+    orders: RwLock<Vec<Order>>,
+    next_id: AtomicU64,
+    events: EventBus<OrderEvent>,
+}
+
+impl OrderStore {
+    fn new() -> Self {
+        let store = Self {
+            orders: RwLock::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+            events: EventBus::default(),
+        };
+        // Alice (user 1) starts with the orders every user was once served
+        if let Ok(mut orders) = store.orders.write() {
+            let created_at = now_millis();
+            for (product, quantity) in [("Laptop", 1), ("Mouse", 2)] {
+                orders.push(Order {
+                    id: store.next_id.fetch_add(1, Ordering::Relaxed).to_string(),
+                    user_id: "1".to_string(),
+                    product: product.to_string(),
+                    quantity,
+                    created_at,
+                });
+            }
+        }
+        store
+    }
+
+    /// Process-wide store, so HTTP and gRPC see the same orders and events
+    pub fn shared() -> &'static OrderStore {
+        static STORE: OnceLock<OrderStore> = OnceLock::new();
+        STORE.get_or_init(OrderStore::new)
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, Vec<Order>>, AppError> {
+        self.orders
+            .read()
+            .map_err(|_| AppError::Internal("order store lock is poisoned".to_string()))
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_, Vec<Order>>, AppError> {
+        self.orders
+            .write()
+            .map_err(|_| AppError::Internal("order store lock is poisoned".to_string()))
+    }
+
+    pub fn find_by_user(&self, user_id: &str) -> Result<Vec<Order>, AppError> {
+        let _query = telemetry::db_span("orders", "select").entered();
+        Ok(self
+            .read()?
+            .iter()
+            .filter(|o| o.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    pub fn find(&self, user_id: &str, order_id: &str) -> Result<Option<Order>, AppError> {
+        let _query = telemetry::db_span("orders", "select").entered();
+        Ok(self
+            .read()?
+            .iter()
+            .find(|o| o.user_id == user_id && o.id == order_id)
+            .cloned())
+    }
+
+    /// Orders are append-only, so the orders below this length are a consistent snapshot
+    /// that later inserts never change
+    pub fn snapshot_len(&self) -> Result<usize, AppError> {
+        Ok(self.read()?.len())
+    }
+
+    /// Matching orders among the given positions, holding the lock for this range only
+    pub fn scan(&self, range: Range<usize>, filter: &OrderFilter) -> Result<Vec<Order>, AppError> {
+        let _query = telemetry::db_span("orders", "select").entered();
+        let orders = self.read()?;
+        let end = range.end.min(orders.len());
+        let start = range.start.min(end);
+        Ok(orders[start..end]
+            .iter()
+            .filter(|o| filter.matches(o))
+            .cloned()
+            .collect())
+    }
+
+    pub fn insert(&self, new_order: NewOrder) -> Result<Order, AppError> {
+        Ok(self.insert_many(vec![new_order])?.remove(0))
+    }
+
+    /// Inserts all orders in one transaction: readers see either none or all of them.
+    /// Events are published only after the batch is committed.
+    pub fn insert_many(&self, new_orders: Vec<NewOrder>) -> Result<Vec<Order>, AppError> {
+        let _query = telemetry::db_span("orders", "insert").entered();
+        let mut orders = self.write()?;
+        let created_at = now_millis();
+        let created: Vec<Order> = new_orders
+            .into_iter()
+            .map(|o| Order {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed).to_string(),
+                user_id: o.user_id,
+                product: o.product,
+                quantity: o.quantity,
+                created_at,
+            })
+            .collect();
+        orders.extend(created.iter().cloned());
+        drop(orders);
+        Metrics::shared().orders_created(created.len());
+        for order in &created {
+            self.events
+                .publish(OrderEvent::now(OrderEventKind::Created, order.clone()));
+        }
+        Ok(created)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<OrderEvent> {
+        self.events.subscribe()
+    }
+
+    /// Ends every subscription, no events are published afterwards
+    pub fn close_events(&self) {
+        self.events.close();
+    }
+
+    /// A writer that panicked mid-transaction leaves the store unusable
+    pub fn ping(&self) -> Result<(), String> {
+        if self.orders.is_poisoned() {
+            return Err("order store lock is poisoned".to_string());
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
-pub trait OrderService: Send + Sync {
-    async fn get_orders(&self, user_id: &str) -> Vec<Order>;
+pub trait OrderService: HealthCheck + Send + Sync {
+    async fn get_orders(&self, user_id: &str) -> Result<Vec<Order>, AppError>;
+    async fn get_order(&self, user_id: &str, order_id: &str) -> Result<Order, AppError>;
+    async fn create_order(&self, order: NewOrder) -> Result<Order, AppError>;
+    /// Create all orders in a single transaction; nothing is written if any order is invalid
+    async fn import_orders(&self, orders: Vec<NewOrder>) -> Result<Vec<Order>, AppError>;
+    /// Receive every order change published after this call
+    fn watch_orders(&self) -> broadcast::Receiver<OrderEvent>;
+    /// Orders matching the filter as of this call, in pages read from the store on demand
+    fn export_orders(
+        &self,
+        filter: OrderFilter,
+    ) -> Result<BoxStream<'static, Result<Vec<Order>, AppError>>, AppError>;
 }
 
 pub struct OrderServiceImpl;
 
+/// Orders read per store lock while exporting
+const EXPORT_PAGE_SIZE: usize = 500;
+
 #[async_trait]
 impl OrderService for OrderServiceImpl {
-    async fn get_orders(&self, user_id: &str) -> Vec<Order> {
-        vec![
-            Order {
-                id: "1".to_string(),
-                user_id: user_id.to_string(),
-                product: "Laptop".to_string(),
-                quantity: 1,
-            },
-            Order {
-                id: "2".to_string(),
-                user_id: user_id.to_string(),
-                product: "Mouse".to_string(),
-                quantity: 2,
-            },
-        ]
+    #[tracing::instrument(skip(self))]
+    async fn get_orders(&self, user_id: &str) -> Result<Vec<Order>, AppError> {
+        OrderStore::shared().find_by_user(user_id)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_order(&self, user_id: &str, order_id: &str) -> Result<Order, AppError> {
+        OrderStore::shared()
+            .find(user_id, order_id)?
+            .ok_or_else(|| AppError::NotFound {
+                resource: "order",
+                id: order_id.to_string(),
+            })
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = %order.user_id))]
+    async fn create_order(&self, order: NewOrder) -> Result<Order, AppError> {
+        order.validate().map_err(AppError::Validation)?;
+        OrderStore::shared().insert(order)
+    }
+
+    #[tracing::instrument(skip_all, fields(count = orders.len()))]
+    async fn import_orders(&self, orders: Vec<NewOrder>) -> Result<Vec<Order>, AppError> {
+        for order in &orders {
+            order.validate().map_err(AppError::Validation)?;
+        }
+        OrderStore::shared().insert_many(orders)
+    }
+
+    fn watch_orders(&self) -> broadcast::Receiver<OrderEvent> {
+        OrderStore::shared().subscribe()
+    }
+
+    fn export_orders(
+        &self,
+        filter: OrderFilter,
+    ) -> Result<BoxStream<'static, Result<Vec<Order>, AppError>>, AppError> {
+        let store = OrderStore::shared();
+        let end = store.snapshot_len()?;
+        Ok(stream::iter((0..end).step_by(EXPORT_PAGE_SIZE))
+            .map(move |start| store.scan(start..(start + EXPORT_PAGE_SIZE).min(end), &filter))
+            .filter(|page| std::future::ready(!matches!(page, Ok(orders) if orders.is_empty())))
+            .boxed())
+    }
+}
+
+#[async_trait]
+impl HealthCheck for OrderServiceImpl {
+    async fn check(&self) -> Result<(), String> {
+        OrderStore::shared().ping()
+    }
+}
+
+// ============================================================================
+// CACHE: Orders by user, shared by every Scoped and Transient instance
+// ============================================================================
+static ORDER_CACHE: OnceLock<TtlCache<Vec<Order>>> = OnceLock::new();
+
+/// Starts caching get_orders per user. Order events drop the owner's entry,
+/// so writes made through any instance are seen.
+pub fn cache_orders(cache: TtlCache<Vec<Order>>) {
+    if ORDER_CACHE.set(cache).is_err() {
+        return;
+    }
+    // Subscribed before returning, so no write after startup is missed
+    let mut events = OrderStore::shared().subscribe();
+    tokio::spawn(async move {
+        let Some(cache) = ORDER_CACHE.get() else {
+            return;
+        };
+        loop {
+            match events.recv().await {
+                Ok(event) => cache.invalidate(&event.order.user_id),
+                // Events were missed, so any entry may be stale
+                Err(RecvError::Lagged(_)) => cache.clear(),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Serves get_orders from the order cache, once `cache_orders` has been called
+pub struct CachedOrderService {
+    inner: Box<dyn OrderService>,
+}
+
+impl CachedOrderService {
+    pub fn new(inner: Box<dyn OrderService>) -> Self {
+        Self { inner }
+    }
+
+    /// Events invalidate shortly after a write, this makes it visible to the writer's next read
+    fn invalidate(&self, user_id: &str) {
+        if let Some(cache) = ORDER_CACHE.get() {
+            cache.invalidate(user_id);
+        }
+    }
+}
+
+#[async_trait]
+impl OrderService for CachedOrderService {
+    async fn get_orders(&self, user_id: &str) -> Result<Vec<Order>, AppError> {
+        match ORDER_CACHE.get() {
+            Some(cache) => {
+                cache
+                    .get_or_load(user_id, || self.inner.get_orders(user_id))
+                    .await
+            }
+            None => self.inner.get_orders(user_id).await,
+        }
+    }
+
+    async fn get_order(&self, user_id: &str, order_id: &str) -> Result<Order, AppError> {
+        self.inner.get_order(user_id, order_id).await
+    }
+
+    async fn create_order(&self, order: NewOrder) -> Result<Order, AppError> {
+        let created = self.inner.create_order(order).await?;
+        self.invalidate(&created.user_id);
+        Ok(created)
+    }
+
+    async fn import_orders(&self, orders: Vec<NewOrder>) -> Result<Vec<Order>, AppError> {
+        let created = self.inner.import_orders(orders).await?;
+        for order in &created {
+            self.invalidate(&order.user_id);
+        }
+        Ok(created)
+    }
+
+    fn watch_orders(&self) -> broadcast::Receiver<OrderEvent> {
+        self.inner.watch_orders()
+    }
+
+    fn export_orders(
+        &self,
+        filter: OrderFilter,
+    ) -> Result<BoxStream<'static, Result<Vec<Order>, AppError>>, AppError> {
+        self.inner.export_orders(filter)
+    }
+}
+
+#[async_trait]
+impl HealthCheck for CachedOrderService {
+    async fn check(&self) -> Result<(), String> {
+        self.inner.check().await
+    }
+}
+
+// ============================================================================
+// IMPORT: Validates items one by one and commits valid ones in batches
+// ============================================================================
+pub struct ImportOutcome {
+    /// 0-based position of the item in the import
+    pub index: usize,
+    pub result: Result<Order, Vec<FieldViolation>>,
+}
+
+pub struct OrderImporter {
+    service: Box<dyn OrderService>,
+    max_batch: usize,
+    pending: Vec<(usize, NewOrder)>,
+    received: usize,
+}
+
+impl OrderImporter {
+    pub fn new(service: Box<dyn OrderService>, max_batch: usize) -> Self {
+        Self {
+            service,
+            max_batch: max_batch.max(1),
+            pending: Vec::new(),
+            received: 0,
+        }
+    }
+
+    /// Returns outcomes that became final with this item: its own validation failure,
+    /// or the whole pending batch once it reaches max_batch and is committed.
+    /// A failed commit aborts the import.
+    pub async fn push(&mut self, order: NewOrder) -> Result<Vec<ImportOutcome>, AppError> {
+        let index = self.received;
+        self.received += 1;
+
+        if let Err(violations) = order.validate() {
+            return Ok(vec![ImportOutcome { index, result: Err(violations) }]);
+        }
+
+        self.pending.push((index, order));
+        if self.pending.len() >= self.max_batch {
+            self.flush().await
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Records an item rejected before it reached the importer, e.g. by request validation
+    pub fn reject(&mut self, violations: Vec<FieldViolation>) -> ImportOutcome {
+        let index = self.received;
+        self.received += 1;
+        ImportOutcome { index, result: Err(violations) }
+    }
+
+    /// Commits whatever is still pending
+    pub async fn finish(mut self) -> Result<Vec<ImportOutcome>, AppError> {
+        self.flush().await
+    }
+
+    async fn flush(&mut self) -> Result<Vec<ImportOutcome>, AppError> {
+        if self.pending.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (indexes, batch): (Vec<usize>, Vec<NewOrder>) =
+            std::mem::take(&mut self.pending).into_iter().unzip();
+        let created = self.service.import_orders(batch).await?;
+        Ok(indexes
+            .into_iter()
+            .zip(created)
+            .map(|(index, order)| ImportOutcome { index, result: Ok(order) })
+            .collect())
     }
 }
 
@@ -45,7 +490,25 @@ pub struct OrderServiceFactoryImpl;
 
 impl OrderServiceFactory for OrderServiceFactoryImpl {
     fn create(&self) -> Box<dyn OrderService> {
-        Box::new(OrderServiceImpl)
+        Box::new(CachedOrderService::new(Box::new(OrderServiceImpl)))
+    }
+}
+
+/// Order watchers never finish on their own, so their streams are ended for the servers to drain
+#[async_trait]
+impl ShutdownHook for OrderServiceFactoryImpl {
+    async fn shutdown(&self, _deadline: Instant) {
+        OrderStore::shared().close_events();
+    }
+}
+
+/// Health of the scoped service, checked on a fresh instance as a request would get it
+pub struct ScopedOrderHealth<F>(pub Arc<F>);
+
+#[async_trait]
+impl<F: OrderServiceFactory> HealthCheck for ScopedOrderHealth<F> {
+    async fn check(&self) -> Result<(), String> {
+        self.0.create().check().await
     }
 }
 
@@ -55,5 +518,192 @@ impl OrderServiceFactory for OrderServiceFactoryImpl {
 pub type OrderServiceTransient = fn() -> Box<dyn OrderService>;
 
 pub fn create_order_service() -> Box<dyn OrderService> {
-    Box::new(OrderServiceImpl)
+    Box::new(CachedOrderService::new(Box::new(OrderServiceImpl)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Counts loads and records the size of every committed batch instead of using the store,
+    /// so it publishes no order events
+    #[derive(Clone, Default)]
+    struct Recorder {
+        loads: Arc<AtomicU64>,
+        batches: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Recorder {
+        fn loads(&self) -> u64 {
+            self.loads.load(Ordering::SeqCst)
+        }
+
+        fn batches(&self) -> Vec<usize> {
+            self.batches.lock().unwrap().clone()
+        }
+    }
+
+    fn created(id: String, order: NewOrder) -> Order {
+        Order {
+            id,
+            user_id: order.user_id,
+            product: order.product,
+            quantity: order.quantity,
+            created_at: 0,
+        }
+    }
+
+    #[async_trait]
+    impl OrderService for Recorder {
+        async fn get_orders(&self, _user_id: &str) -> Result<Vec<Order>, AppError> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            Ok(Vec::new())
+        }
+
+        async fn get_order(&self, _user_id: &str, _order_id: &str) -> Result<Order, AppError> {
+            unimplemented!()
+        }
+
+        async fn create_order(&self, order: NewOrder) -> Result<Order, AppError> {
+            Ok(created("1".to_string(), order))
+        }
+
+        async fn import_orders(&self, orders: Vec<NewOrder>) -> Result<Vec<Order>, AppError> {
+            let mut batches = self.batches.lock().unwrap();
+            batches.push(orders.len());
+            Ok(orders
+                .into_iter()
+                .enumerate()
+                .map(|(i, order)| created(format!("{}-{i}", batches.len()), order))
+                .collect())
+        }
+
+        fn watch_orders(&self) -> broadcast::Receiver<OrderEvent> {
+            unimplemented!()
+        }
+
+        fn export_orders(
+            &self,
+            _filter: OrderFilter,
+        ) -> Result<BoxStream<'static, Result<Vec<Order>, AppError>>, AppError> {
+            unimplemented!()
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for Recorder {
+        async fn check(&self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn order(quantity: i32) -> NewOrder {
+        owned_by("u1", quantity)
+    }
+
+    fn owned_by(user_id: &str, quantity: i32) -> NewOrder {
+        NewOrder {
+            user_id: user_id.to_string(),
+            product: "Pen".to_string(),
+            quantity,
+        }
+    }
+
+    /// Indexes of the outcomes, negated for rejected items
+    fn indexes(outcomes: &[ImportOutcome]) -> Vec<isize> {
+        outcomes
+            .iter()
+            .map(|outcome| match outcome.result {
+                Ok(_) => outcome.index as isize,
+                Err(_) => -(outcome.index as isize),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn importer_commits_full_batches() {
+        let recorder = Recorder::default();
+        let mut importer = OrderImporter::new(Box::new(recorder.clone()), 2);
+
+        assert!(importer.push(order(1)).await.unwrap().is_empty());
+        assert_eq!(indexes(&importer.push(order(2)).await.unwrap()), [0, 1]);
+        assert!(importer.push(order(3)).await.unwrap().is_empty());
+        assert_eq!(indexes(&importer.push(order(4)).await.unwrap()), [2, 3]);
+        assert_eq!(recorder.batches(), [2, 2]);
+
+        assert!(importer.finish().await.unwrap().is_empty());
+        assert_eq!(recorder.batches(), [2, 2]);
+    }
+
+    #[tokio::test]
+    async fn importer_reports_rejected_items_before_their_batch_commits() {
+        let recorder = Recorder::default();
+        let mut importer = OrderImporter::new(Box::new(recorder.clone()), 2);
+
+        assert!(importer.push(order(1)).await.unwrap().is_empty());
+        let rejected = importer.push(order(0)).await.unwrap();
+        assert_eq!(indexes(&rejected), [-1]);
+        assert!(recorder.batches().is_empty());
+        let rejected = importer.reject(vec![FieldViolation::new("user_id", "required")]);
+        assert_eq!(indexes(&[rejected]), [-2]);
+
+        assert_eq!(indexes(&importer.push(order(3)).await.unwrap()), [0, 3]);
+        assert_eq!(recorder.batches(), [2]);
+    }
+
+    #[tokio::test]
+    async fn importer_finish_commits_a_partial_batch() {
+        let recorder = Recorder::default();
+        let mut importer = OrderImporter::new(Box::new(recorder.clone()), 3);
+
+        assert!(importer.push(order(1)).await.unwrap().is_empty());
+        assert!(importer.push(order(2)).await.unwrap().is_empty());
+        assert!(recorder.batches().is_empty());
+
+        assert_eq!(indexes(&importer.finish().await.unwrap()), [0, 1]);
+        assert_eq!(recorder.batches(), [2]);
+    }
+
+    // One test, as the order cache is process-wide and its event listener
+    // lives on the runtime of whichever test starts it
+    #[tokio::test]
+    async fn cached_orders_are_invalidated_by_writes_and_order_events() {
+        cache_orders(TtlCache::new("orders", 100, Duration::from_secs(60)));
+        let recorder = Recorder::default();
+        let service = CachedOrderService::new(Box::new(recorder.clone()));
+
+        service.get_orders("cache-writes").await.unwrap();
+        service.get_orders("cache-writes").await.unwrap();
+        assert_eq!(recorder.loads(), 1);
+        service
+            .create_order(owned_by("cache-writes", 1))
+            .await
+            .unwrap();
+        service.get_orders("cache-writes").await.unwrap();
+        assert_eq!(recorder.loads(), 2);
+        service
+            .import_orders(vec![owned_by("cache-writes", 1)])
+            .await
+            .unwrap();
+        service.get_orders("cache-writes").await.unwrap();
+        assert_eq!(recorder.loads(), 3);
+
+        // Written by another instance: only the event reaches this one's cache
+        service.get_orders("cache-events").await.unwrap();
+        assert_eq!(recorder.loads(), 4);
+        OrderStore::shared()
+            .insert(owned_by("cache-events", 1))
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while recorder.loads() == 4 {
+                tokio::task::yield_now().await;
+                service.get_orders("cache-events").await.unwrap();
+            }
+        })
+        .await
+        .expect("the order event invalidates the owner's entry");
+        assert_eq!(recorder.loads(), 5);
+    }
 }
@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Instant;
+
+/// Implemented by singletons holding work or resources that must be wound down before exit
+#[async_trait]
+pub trait ShutdownHook: Send + Sync {
+    /// Called once the servers stop accepting connections. Work still running at
+    /// `deadline` should be stopped and checkpointed, the process exits soon after.
+    async fn shutdown(&self, deadline: Instant);
+}
+
+/// Hooks registered by main and the servers, run concurrently on shutdown
+#[derive(Default)]
+pub struct ShutdownHooks {
+    hooks: RwLock<Vec<(&'static str, Arc<dyn ShutdownHook>)>>,
+}
+
+impl ShutdownHooks {
+    pub fn shared() -> &'static ShutdownHooks {
+        static HOOKS: OnceLock<ShutdownHooks> = OnceLock::new();
+        HOOKS.get_or_init(ShutdownHooks::default)
+    }
+
+    pub fn register(&self, name: &'static str, hook: Arc<dyn ShutdownHook>) {
+        if let Ok(mut hooks) = self.hooks.write() {
+            hooks.push((name, hook));
+        }
+    }
+
+    pub async fn run(&self, deadline: Instant) {
+        let hooks = self.hooks.read().map(|h| h.clone()).unwrap_or_default();
+        join_all(hooks.iter().map(|(name, hook)| async move {
+            let started = Instant::now();
+            hook.shutdown(deadline).await;
+            log::info!(
+                "shutdown hook {name} finished in {} ms",
+                started.elapsed().as_millis()
+            );
+        }))
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Takes `delay` to wind down and logs when it starts and ends
+    struct Hook {
+        name: &'static str,
+        delay: Duration,
+        log: Arc<Mutex<Vec<String>>>,
+        deadlines: Arc<Mutex<Vec<Instant>>>,
+    }
+
+    impl Hook {
+        fn note(&self, event: &str) {
+            let mut log = self.log.lock().unwrap();
+            log.push(format!("{} {event}", self.name));
+        }
+    }
+
+    #[async_trait]
+    impl ShutdownHook for Hook {
+        async fn shutdown(&self, deadline: Instant) {
+            self.deadlines.lock().unwrap().push(deadline);
+            self.note("started");
+            tokio::time::sleep(self.delay).await;
+            self.note("finished");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hooks_start_in_registration_order_and_run_concurrently() {
+        let hooks = ShutdownHooks::default();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let deadlines = Arc::new(Mutex::new(Vec::new()));
+        for (name, seconds) in [("imports", 3), ("order watchers", 1)] {
+            let hook = Hook {
+                name,
+                delay: Duration::from_secs(seconds),
+                log: log.clone(),
+                deadlines: deadlines.clone(),
+            };
+            hooks.register(name, Arc::new(hook));
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let started = tokio::time::Instant::now();
+        hooks.run(deadline).await;
+
+        // As long as the slowest hook, not the sum of them
+        assert_eq!(started.elapsed(), Duration::from_secs(3));
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "imports started",
+                "order watchers started",
+                "order watchers finished",
+                "imports finished",
+            ]
+        );
+        assert_eq!(*deadlines.lock().unwrap(), [deadline, deadline]);
+    }
+}
@@ -1,18 +1,252 @@
+use super::cache::TtlCache;
+use super::error::{AppError, FieldViolation};
+use super::health::HealthCheck;
+use crate::metrics::Metrics;
+use crate::telemetry;
 use async_trait::async_trait;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[derive(Clone)]
+pub struct User {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+}
+
+pub struct NewUser {
+    pub name: String,
+    pub email: String,
+}
+
+impl NewUser {
+    /// Returns every violation rather than stopping at the first
+    pub fn validate(&self) -> Result<(), Vec<FieldViolation>> {
+        let mut violations = Vec::new();
+        if self.name.trim().is_empty() {
+            violations.push(FieldViolation::new("name", "must not be empty"));
+        }
+        let valid_email = self.email.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty() && domain.contains('.') && !domain.starts_with('.')
+        });
+        if !valid_email {
+            violations.push(FieldViolation::new(
+                "email",
+                "must be a valid email address",
+            ));
+        }
+        if violations.is_empty() { Ok(()) } else { Err(violations) }
+    }
+}
+
+// ============================================================================
+// STORAGE: In-memory users shared by every UserService instance (stands in for a database)
+// ============================================================================
+pub struct UserStore {
+    users: RwLock<Vec<User>>,
+    next_id: AtomicU64,
+}
+
+impl UserStore {
+    fn new() -> Self {
+        let store = Self {
+            users: RwLock::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+        };
+        if let Ok(mut users) = store.users.write() {
+            for (name, email) in [("Alice", "alice@example.com"), ("Bob", "bob@example.com")] {
+                users.push(store.create(NewUser {
+                    name: name.to_string(),
+                    email: email.to_string(),
+                }));
+            }
+        }
+        store
+    }
+
+    pub fn shared() -> &'static UserStore {
+        static STORE: OnceLock<UserStore> = OnceLock::new();
+        STORE.get_or_init(UserStore::new)
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, Vec<User>>, AppError> {
+        self.users
+            .read()
+            .map_err(|_| AppError::Internal("user store lock is poisoned".to_string()))
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_, Vec<User>>, AppError> {
+        self.users
+            .write()
+            .map_err(|_| AppError::Internal("user store lock is poisoned".to_string()))
+    }
+
+    fn create(&self, new_user: NewUser) -> User {
+        User {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed).to_string(),
+            name: new_user.name,
+            email: new_user.email,
+        }
+    }
+
+    pub fn all(&self) -> Result<Vec<User>, AppError> {
+        let _query = telemetry::db_span("users", "select").entered();
+        Ok(self.read()?.clone())
+    }
+
+    /// Inserts all users in one transaction: readers see either none or all of them
+    pub fn insert_many(&self, new_users: Vec<NewUser>) -> Result<Vec<User>, AppError> {
+        let _query = telemetry::db_span("users", "insert").entered();
+        let mut users = self.write()?;
+        let created: Vec<User> = new_users.into_iter().map(|u| self.create(u)).collect();
+        users.extend(created.iter().cloned());
+        Metrics::shared().users_created(created.len());
+        Ok(created)
+    }
+
+    pub fn ping(&self) -> Result<(), String> {
+        if self.users.is_poisoned() {
+            return Err("user store lock is poisoned".to_string());
+        }
+        Ok(())
+    }
+}
 
 #[async_trait]
-pub trait UserService: Send + Sync {
-    async fn get_users(&self) -> Vec<String>;
+pub trait UserService: HealthCheck + Send + Sync {
+    async fn get_users(&self) -> Result<Vec<String>, AppError>;
+    /// Every user with all of their fields
+    async fn list_users(&self) -> Result<Vec<User>, AppError>;
+    /// Create all users in a single transaction; nothing is written if any user is invalid
+    async fn import_users(&self, users: Vec<NewUser>) -> Result<Vec<User>, AppError>;
 }
 
 pub struct UserServiceImpl;
 
 #[async_trait]
 impl UserService for UserServiceImpl {
-    async fn get_users(&self) -> Vec<String> {
-        vec![
-            "Alice".to_string(),
-            "Bob".to_string(),
-        ]
+    #[tracing::instrument(skip(self))]
+    async fn get_users(&self) -> Result<Vec<String>, AppError> {
+        Ok(UserStore::shared()
+            .all()?
+            .into_iter()
+            .map(|u| u.name)
+            .collect())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_users(&self) -> Result<Vec<User>, AppError> {
+        UserStore::shared().all()
+    }
+
+    #[tracing::instrument(skip_all, fields(count = users.len()))]
+    async fn import_users(&self, users: Vec<NewUser>) -> Result<Vec<User>, AppError> {
+        for user in &users {
+            user.validate().map_err(AppError::Validation)?;
+        }
+        UserStore::shared().insert_many(users)
+    }
+}
+
+#[async_trait]
+impl HealthCheck for UserServiceImpl {
+    async fn check(&self) -> Result<(), String> {
+        UserStore::shared().ping()
+    }
+}
+
+// ============================================================================
+// CACHE: Decorator keeping get_users in the singleton's LRU, shared by all requests
+// ============================================================================
+const USER_NAMES_KEY: &str = "names";
+
+pub struct CachedUserService<S> {
+    inner: S,
+    cache: TtlCache<Vec<String>>,
+}
+
+impl<S: UserService> CachedUserService<S> {
+    pub fn new(inner: S, cache: TtlCache<Vec<String>>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait]
+impl<S: UserService> UserService for CachedUserService<S> {
+    async fn get_users(&self) -> Result<Vec<String>, AppError> {
+        self.cache
+            .get_or_load(USER_NAMES_KEY, || self.inner.get_users())
+            .await
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, AppError> {
+        self.inner.list_users().await
+    }
+
+    async fn import_users(&self, users: Vec<NewUser>) -> Result<Vec<User>, AppError> {
+        let created = self.inner.import_users(users).await;
+        self.cache.invalidate(USER_NAMES_KEY);
+        created
+    }
+}
+
+#[async_trait]
+impl<S: UserService> HealthCheck for CachedUserService<S> {
+    async fn check(&self) -> Result<(), String> {
+        self.inner.check().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Counts loads of the user names instead of reading the store
+    #[derive(Clone, Default)]
+    struct CountingUsers {
+        loads: Arc<AtomicU64>,
+    }
+
+    #[async_trait]
+    impl UserService for CountingUsers {
+        async fn get_users(&self) -> Result<Vec<String>, AppError> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            Ok(vec!["alice".to_string()])
+        }
+
+        async fn list_users(&self) -> Result<Vec<User>, AppError> {
+            unimplemented!()
+        }
+
+        async fn import_users(&self, _users: Vec<NewUser>) -> Result<Vec<User>, AppError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[async_trait]
+    impl HealthCheck for CountingUsers {
+        async fn check(&self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn import_clears_the_cached_names() {
+        let inner = CountingUsers::default();
+        let service = CachedUserService::new(
+            inner.clone(),
+            TtlCache::new("users", 10, Duration::from_secs(60)),
+        );
+        let loads = || inner.loads.load(Ordering::SeqCst);
+
+        assert_eq!(service.get_users().await.unwrap(), ["alice"]);
+        service.get_users().await.unwrap();
+        assert_eq!(loads(), 1);
+
+        service.import_users(Vec::new()).await.unwrap();
+        service.get_users().await.unwrap();
+        assert_eq!(loads(), 2);
     }
 }
@@ -0,0 +1,56 @@
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Resolves on Ctrl+C, or SIGTERM on unix
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Cancel the token once a shutdown signal arrives. A second signal exits immediately.
+pub fn cancel_on_signal(token: CancellationToken) {
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = signal() => {
+                log::info!("Shutdown signal received, stopping servers");
+                token.cancel();
+            }
+            _ = token.cancelled() => {}
+        }
+        signal().await;
+        log::warn!("Second shutdown signal received, exiting without draining");
+        std::process::exit(1);
+    });
+}
+
+/// Resolves when the servers should stop accepting connections: `delay` after the token
+/// is cancelled, so load balancers see the failing readiness probe and stop sending first
+pub async fn stop_accepting(token: CancellationToken, delay: Duration) {
+    token.cancelled().await;
+    tokio::time::sleep(delay).await;
+}
+
+/// Resolves when connections still open `timeout` after the listeners closed must be cut
+pub async fn drain_expired(token: CancellationToken, delay: Duration, timeout: Duration) {
+    stop_accepting(token, delay).await;
+    tokio::time::sleep(timeout).await;
+}
@@ -0,0 +1,165 @@
+use futures_util::future::BoxFuture;
+use http::HeaderMap;
+use http::header::{HeaderName, HeaderValue};
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{Status, TraceContextExt, TracerProvider as _};
+use opentelemetry::{Context, KeyValue, global};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{Resource, runtime};
+use std::io::Write;
+use std::time::UNIX_EPOCH;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Writes finished spans to stdout, one JSON object per line
+#[derive(Debug)]
+struct StdoutExporter;
+
+fn unix_nanos(time: std::time::SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+}
+
+impl SpanExporter for StdoutExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let mut stdout = std::io::stdout().lock();
+        for span in batch {
+            let attributes: serde_json::Map<String, serde_json::Value> = span
+                .attributes
+                .iter()
+                .map(|kv| (kv.key.to_string(), kv.value.to_string().into()))
+                .collect();
+            let status = match &span.status {
+                Status::Unset => "unset".to_string(),
+                Status::Ok => "ok".to_string(),
+                Status::Error { description } => format!("error: {description}"),
+            };
+            let line = serde_json::json!({
+                "trace_id": span.span_context.trace_id().to_string(),
+                "span_id": span.span_context.span_id().to_string(),
+                "parent_span_id": span.parent_span_id.to_string(),
+                "name": span.name,
+                "kind": format!("{:?}", span.span_kind).to_ascii_lowercase(),
+                "start_unix_nano": unix_nanos(span.start_time),
+                "duration_ms": span
+                    .end_time
+                    .duration_since(span.start_time)
+                    .map(|d| d.as_secs_f64() * 1000.0)
+                    .unwrap_or_default(),
+                "status": status,
+                "attributes": attributes,
+            });
+            let _ = writeln!(stdout, "{line}");
+        }
+        Box::pin(std::future::ready(Ok(())))
+    }
+}
+
+/// Installs the W3C trace context propagator and, unless `exporter` is `none`, a tracing
+/// subscriber that turns `tracing` spans into OpenTelemetry spans sent to `exporter`:
+/// `otlp` (gRPC to `otlp_endpoint`) or `stdout`.
+/// The returned provider must be shut down on exit to flush the last spans.
+pub fn init(
+    exporter: &str,
+    otlp_endpoint: &str,
+    service_name: &str,
+) -> Result<Option<TracerProvider>, Box<dyn std::error::Error>> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let builder = TracerProvider::builder().with_resource(Resource::new([KeyValue::new(
+        "service.name",
+        service_name.to_string(),
+    )]));
+    let provider = match exporter {
+        "none" => return Ok(None),
+        "otlp" => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(otlp_endpoint)
+                .build()?;
+            builder
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .build()
+        }
+        "stdout" => builder
+            .with_batch_exporter(StdoutExporter, runtime::Tokio)
+            .build(),
+        other => {
+            return Err(format!(
+                "OTEL_TRACES_EXPORTER must be none, otlp or stdout, not {other:?}"
+            )
+            .into());
+        }
+    };
+
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber)?;
+    global::set_tracer_provider(provider.clone());
+    Ok(Some(provider))
+}
+
+/// Trace id of the current span, for correlating logs with traces
+pub fn current_trace_id() -> Option<String> {
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
+
+/// Trace context sent by the caller in `traceparent` / `tracestate`
+pub fn extract(headers: &dyn Extractor) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(headers))
+}
+
+/// Adds `traceparent` / `tracestate` of the span to outgoing request headers
+pub fn inject(span: &tracing::Span, headers: &mut HeaderMap) {
+    let context = span.context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
+/// Span of a query against one of the in-memory stores, which stand in for a database
+pub fn db_span(collection: &'static str, operation: &'static str) -> tracing::Span {
+    tracing::info_span!(
+        "db.query",
+        otel.name = format!("{operation} {collection}"),
+        otel.kind = "client",
+        db.system.name = "memory",
+        db.collection.name = collection,
+        db.operation.name = operation,
+    )
+}
+
+pub struct HeaderExtractor<'a>(pub &'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use crate::auth::Claims;
+use crate::config::Config;
+use rustls::crypto::{CryptoProvider, ring};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// Server certificate handed to every new connection, swapped in place on reload
+#[derive(Debug)]
+struct ReloadableCert(RwLock<Arc<CertifiedKey>>);
+
+impl ResolvesServerCert for ReloadableCert {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.0.read().ok().map(|key| key.clone())
+    }
+}
+
+fn load_cert(cert: &str, key: &str, provider: &CryptoProvider) -> Result<CertifiedKey, String> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("{cert}: {e}"))?;
+    if chain.is_empty() {
+        return Err(format!("{cert}: no certificate found"));
+    }
+    let private_key = PrivateKeyDer::from_pem_file(key).map_err(|e| format!("{key}: {e}"))?;
+    CertifiedKey::from_der(chain, private_key, provider).map_err(|e| format!("{key}: {e}"))
+}
+
+fn load_roots(ca: &str) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca).map_err(|e| format!("{ca}: {e}"))? {
+        let cert = cert.map_err(|e| format!("{ca}: {e}"))?;
+        roots.add(cert).map_err(|e| format!("{ca}: {e}"))?;
+    }
+    if roots.is_empty() {
+        return Err(format!("{ca}: no certificate found"));
+    }
+    Ok(roots)
+}
+
+/// Roles per subject from `subject=role` entries, see TLS_CLIENT_ROLES
+pub fn client_roles(entries: &[(String, String)]) -> HashMap<String, Vec<String>> {
+    let mut roles: HashMap<String, Vec<String>> = HashMap::new();
+    for (subject, role) in entries {
+        roles.entry(subject.clone()).or_default().push(role.clone());
+    }
+    roles
+}
+
+/// Claims of a verified client certificate: `sub` is the subject's common name (the whole
+/// subject without one), `exp` the end of its validity. Roles are only those `roles` grants
+/// to `sub`, nothing in the certificate itself confers one.
+pub fn client_claims(
+    cert: &CertificateDer<'_>,
+    roles: &HashMap<String, Vec<String>>,
+) -> Option<Claims> {
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    let subject = cert.subject();
+    let sub = subject
+        .iter_common_name()
+        .find_map(|cn| cn.as_str().ok())
+        .map(String::from)
+        .unwrap_or_else(|| subject.to_string());
+    Some(Claims {
+        roles: roles.get(&sub).cloned().unwrap_or_default(),
+        sub,
+        exp: cert.validity().not_after.timestamp().max(0) as usize,
+        policies: Vec::new(),
+    })
+}
+
+/// Config of the gRPC listener. With a client CA, client certificates are requested
+/// and verified against it, and required unless `client_auth_required` is false.
+fn grpc_config(
+    provider: &Arc<CryptoProvider>,
+    cert: &Arc<ReloadableCert>,
+    client_ca: Option<&str>,
+    client_auth_required: bool,
+) -> Result<ServerConfig, String> {
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match client_ca {
+        None => builder.with_no_client_auth(),
+        Some(ca) => {
+            let verifier = WebPkiClientVerifier::builder_with_provider(
+                Arc::new(load_roots(ca)?),
+                provider.clone(),
+            );
+            let verifier = if client_auth_required {
+                verifier
+            } else {
+                verifier.allow_unauthenticated()
+            };
+            builder.with_client_cert_verifier(verifier.build().map_err(|e| format!("{ca}: {e}"))?)
+        }
+    };
+    let mut config = builder.with_cert_resolver(cert.clone());
+    // gRPC needs HTTP/2, gRPC-Web and REST clients in single port mode HTTP/1.1
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+// ============================================================================
+// SINGLETON: Certificates shared by both listeners, reloaded when their files change
+// ============================================================================
+pub struct Tls {
+    provider: Arc<CryptoProvider>,
+    cert_path: String,
+    key_path: String,
+    client_ca_path: Option<String>,
+    client_auth_required: bool,
+    cert: Arc<ReloadableCert>,
+    /// Rebuilt on reload, as the client CA is part of its verifier
+    grpc: RwLock<Arc<ServerConfig>>,
+}
+
+impl Tls {
+    /// None when TLS_CERT is unset, the listeners then serve plaintext
+    pub fn from_config(cfg: &Config) -> Result<Option<Self>, String> {
+        if cfg.tls_cert.is_empty() {
+            return Ok(None);
+        }
+        if cfg.tls_key.is_empty() {
+            return Err("TLS_CERT is set but TLS_KEY is not".to_string());
+        }
+        let client_auth_required = match cfg.tls_client_auth.as_str() {
+            "required" => true,
+            "optional" => false,
+            other => {
+                return Err(format!(
+                    "unknown TLS_CLIENT_AUTH {other:?}, expected required or optional"
+                ));
+            }
+        };
+        let client_ca_path = Some(cfg.tls_client_ca.clone()).filter(|ca| !ca.is_empty());
+
+        let provider = Arc::new(ring::default_provider());
+        let cert = load_cert(&cfg.tls_cert, &cfg.tls_key, &provider)?;
+        let cert = Arc::new(ReloadableCert(RwLock::new(Arc::new(cert))));
+        let grpc = grpc_config(
+            &provider,
+            &cert,
+            client_ca_path.as_deref(),
+            client_auth_required,
+        )?;
+        Ok(Some(Self {
+            provider,
+            cert_path: cfg.tls_cert.clone(),
+            key_path: cfg.tls_key.clone(),
+            client_ca_path,
+            client_auth_required,
+            cert,
+            grpc: RwLock::new(Arc::new(grpc)),
+        }))
+    }
+
+    /// Config of the HTTP listener, which never asks for client certificates.
+    /// The certificate is resolved per connection, so reloads apply to it too.
+    pub fn http_config(&self) -> Result<ServerConfig, String> {
+        Ok(ServerConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_cert_resolver(self.cert.clone()))
+    }
+
+    /// Acceptor for a new gRPC connection, with the latest certificates and client CA
+    pub fn grpc_acceptor(&self) -> Option<TlsAcceptor> {
+        let config = self.grpc.read().ok()?.clone();
+        Some(TlsAcceptor::from(config))
+    }
+
+    /// Modification times of the certificate files, None for a file that can't be read
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        [
+            Some(&self.cert_path),
+            Some(&self.key_path),
+            self.client_ca_path.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+    }
+
+    /// Loads every file again and swaps them in only if all of them are valid
+    fn reload(&self) -> Result<(), String> {
+        let cert = Arc::new(load_cert(&self.cert_path, &self.key_path, &self.provider)?);
+        // Resolves through self.cert, which is only swapped once the CA loaded too
+        let grpc = grpc_config(
+            &self.provider,
+            &self.cert,
+            self.client_ca_path.as_deref(),
+            self.client_auth_required,
+        )?;
+        if let Ok(mut current) = self.cert.0.write() {
+            *current = cert;
+        }
+        if let Ok(mut current) = self.grpc.write() {
+            *current = Arc::new(grpc);
+        }
+        Ok(())
+    }
+
+    /// Checks the files every `interval` and reloads them once they change.
+    /// Open connections keep the certificate they were established with.
+    pub async fn reload_on_change(
+        self: Arc<Self>,
+        interval: Duration,
+        shutdown: CancellationToken,
+    ) {
+        let mut seen = self.modified();
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep(interval) => {}
+            }
+            let modified = self.modified();
+            if modified == seen {
+                continue;
+            }
+            // A pair still being written fails here, and is retried once it changes again
+            seen = modified;
+            match self.reload() {
+                Ok(()) => log::info!("TLS certificates reloaded from {}", self.cert_path),
+                Err(e) => {
+                    log::warn!("TLS certificates not reloaded, the previous ones are kept: {e}")
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Self-signed, subject `O=acme, OU=admin, CN=billing`
+    const CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBuDCCAV+gAwIBAgIUTVaIhQZlGsWLdDAjP2r1ECuwRhgwCgYIKoZIzj0EAwIw
+MTENMAsGA1UECgwEYWNtZTEOMAwGA1UECwwFYWRtaW4xEDAOBgNVBAMMB2JpbGxp
+bmcwIBcNMjYxMDE5MDIwNTIxWhgPMjEyNjA5MjUwMjA1MjFaMDExDTALBgNVBAoM
+BGFjbWUxDjAMBgNVBAsMBWFkbWluMRAwDgYDVQQDDAdiaWxsaW5nMFkwEwYHKoZI
+zj0CAQYIKoZIzj0DAQcDQgAE7JXCOkXge1ON+qp+4X/4V9l+EEz8FeOp+691rG7m
+Tu4aKc/UEOzcdIKKRZZhH2HRSoBWw4GaB4xN/nLIdw6Ba6NTMFEwHQYDVR0OBBYE
+FNIfwpzn/dLtjiWSPoMqYTKvtttwMB8GA1UdIwQYMBaAFNIfwpzn/dLtjiWSPoMq
+YTKvtttwMA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDRwAwRAIgRDZbMLEm
+Dbp+Np2u6JLs4rWKSWMZBEXRdQytOehTobYCIGSEtqb9imaRX3fB7IjCw6iGhWBW
+t5NcCp4mshe/Rnlf
+-----END CERTIFICATE-----
+";
+
+    fn claims(entries: &[(&str, &str)]) -> Claims {
+        let entries: Vec<(String, String)> = entries
+            .iter()
+            .map(|(subject, role)| (subject.to_string(), role.to_string()))
+            .collect();
+        let cert = CertificateDer::from_pem_slice(CERT.as_bytes()).unwrap();
+        client_claims(&cert, &client_roles(&entries)).unwrap()
+    }
+
+    #[test]
+    fn subject_comes_from_the_common_name() {
+        let claims = claims(&[]);
+        assert_eq!(claims.sub, "billing");
+        assert!(claims.policies.is_empty());
+        assert!(claims.exp > 4_000_000_000);
+    }
+
+    #[test]
+    fn organizational_units_grant_no_roles() {
+        assert!(claims(&[]).roles.is_empty());
+        assert!(claims(&[("admin", "admin")]).roles.is_empty());
+    }
+
+    #[test]
+    fn roles_come_from_the_configured_grants() {
+        let claims = claims(&[
+            ("billing", "admin"),
+            ("shipping", "ops"),
+            ("billing", "service"),
+        ]);
+        assert_eq!(claims.roles, ["admin", "service"]);
+    }
+}
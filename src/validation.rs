@@ -0,0 +1,506 @@
+use crate::proto::FILE_DESCRIPTOR_SET;
+use crate::services::{AppError, FieldViolation};
+use prost_reflect::{DescriptorPool, DynamicMessage, FieldDescriptor, Kind, ReflectMessage, Value};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+const RULES_EXTENSION: &str = "validate.rules";
+
+/// `validate.FieldRules` of one field, see proto/validate/validate.proto
+struct FieldRules {
+    min_len: Option<u64>,
+    max_len: Option<u64>,
+    pattern: Option<Regex>,
+    email: bool,
+    gt: Option<f64>,
+    gte: Option<f64>,
+    lt: Option<f64>,
+    lte: Option<f64>,
+    one_of: Vec<String>,
+    defined_only: bool,
+    required: bool,
+    min_items: Option<u64>,
+    max_items: Option<u64>,
+}
+
+fn optional(rules: &DynamicMessage, name: &str) -> Option<Value> {
+    rules
+        .has_field_by_name(name)
+        .then(|| rules.get_field_by_name(name).map(|v| v.into_owned()))
+        .flatten()
+}
+
+fn optional_u64(rules: &DynamicMessage, name: &str) -> Option<u64> {
+    optional(rules, name)
+        .and_then(|v| v.as_u32())
+        .map(u64::from)
+}
+
+fn optional_f64(rules: &DynamicMessage, name: &str) -> Option<f64> {
+    optional(rules, name).and_then(|v| v.as_f64())
+}
+
+fn flag(rules: &DynamicMessage, name: &str) -> bool {
+    optional(rules, name)
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+impl FieldRules {
+    fn parse(field: &FieldDescriptor, rules: &DynamicMessage) -> Result<Self, String> {
+        let pattern = optional(rules, "pattern")
+            .and_then(|v| v.as_str().map(str::to_string))
+            .map(|p| Regex::new(&p).map_err(|e| format!("{}: {e}", field.full_name())))
+            .transpose()?;
+        let one_of = optional(rules, "in")
+            .and_then(|v| {
+                v.as_list().map(|l| {
+                    l.iter()
+                        .filter_map(|v| v.as_str().map(str::to_string))
+                        .collect()
+                })
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            min_len: optional_u64(rules, "min_len"),
+            max_len: optional_u64(rules, "max_len"),
+            pattern,
+            email: flag(rules, "email"),
+            gt: optional_f64(rules, "gt"),
+            gte: optional_f64(rules, "gte"),
+            lt: optional_f64(rules, "lt"),
+            lte: optional_f64(rules, "lte"),
+            one_of,
+            defined_only: flag(rules, "defined_only"),
+            required: flag(rules, "required"),
+            min_items: optional_u64(rules, "min_items"),
+            max_items: optional_u64(rules, "max_items"),
+        })
+    }
+}
+
+fn email_regex() -> &'static Regex {
+    static EMAIL: OnceLock<Regex> = OnceLock::new();
+    EMAIL.get_or_init(|| {
+        Regex::new(r"^[A-Za-z0-9.!#$%&'*+/=?^_`{|}~-]+@[A-Za-z0-9-]+(\.[A-Za-z0-9-]+)+$")
+            .expect("email regex is valid")
+    })
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::I32(n) => Some(f64::from(*n)),
+        Value::I64(n) => Some(*n as f64),
+        Value::U32(n) => Some(f64::from(*n)),
+        Value::U64(n) => Some(*n as f64),
+        Value::F32(n) => Some(f64::from(*n)),
+        Value::F64(n) => Some(*n),
+        _ => None,
+    }
+}
+
+/// Checks requests against the `(validate.rules)` options declared in proto/*.proto.
+/// Rules are read once from the compiled descriptor set.
+pub struct Validator {
+    pool: DescriptorPool,
+    /// Keyed by field full name, e.g. `order.CreateOrderRequest.quantity`
+    rules: HashMap<String, FieldRules>,
+    /// Request messages of client streaming RPCs, whose handlers check items one by one
+    streamed: HashSet<String>,
+}
+
+impl Validator {
+    fn new() -> Result<Self, String> {
+        let pool = DescriptorPool::decode(FILE_DESCRIPTOR_SET).map_err(|e| e.to_string())?;
+        let mut rules = HashMap::new();
+        if let Some(extension) = pool.get_extension_by_name(RULES_EXTENSION) {
+            for message in pool.all_messages() {
+                for field in message.fields() {
+                    let options = field.options();
+                    if !options.has_extension(&extension) {
+                        continue;
+                    }
+                    if let Value::Message(field_rules) = options.get_extension(&extension).as_ref()
+                    {
+                        rules.insert(
+                            field.full_name().to_string(),
+                            FieldRules::parse(&field, field_rules)?,
+                        );
+                    }
+                }
+            }
+        }
+        let streamed = pool
+            .services()
+            .flat_map(|service| {
+                service
+                    .methods()
+                    .filter(|rpc| rpc.is_client_streaming())
+                    .map(|rpc| rpc.input().full_name().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        Ok(Self {
+            pool,
+            rules,
+            streamed,
+        })
+    }
+
+    /// Panics on an invalid rule (e.g. a bad regex), so call it at startup
+    pub fn shared() -> &'static Validator {
+        static VALIDATOR: OnceLock<Validator> = OnceLock::new();
+        VALIDATOR.get_or_init(|| {
+            Validator::new().unwrap_or_else(|e| panic!("invalid validation rules: {e}"))
+        })
+    }
+
+    /// Every violation in the message, including nested messages
+    pub fn violations(&self, message: &DynamicMessage) -> Vec<FieldViolation> {
+        let mut violations = Vec::new();
+        self.check_message(message, "", &mut violations);
+        violations
+    }
+
+    /// Validate a generated proto type, e.g. an extracted JSON body
+    pub fn validate<M: prost::Message + prost::Name>(&self, message: &M) -> Result<(), AppError> {
+        let Some(descriptor) = self.pool.get_message_by_name(&M::full_name()) else {
+            return Ok(());
+        };
+        let dynamic = DynamicMessage::decode(descriptor, message.encode_to_vec().as_slice())
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let violations = self.violations(&dynamic);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(violations))
+        }
+    }
+
+    /// Validate a request tonic decoded, see grpc::codec. Items of client streams are left to
+    /// their handler, which rejects a bad item without failing the rest of the stream.
+    pub fn validate_request<M: prost::Message + prost::Name>(
+        &self,
+        message: &M,
+    ) -> Result<(), AppError> {
+        if self.streamed.contains(&M::full_name()) {
+            return Ok(());
+        }
+        self.validate(message)
+    }
+
+    fn check_message(&self, message: &DynamicMessage, prefix: &str, out: &mut Vec<FieldViolation>) {
+        for field in message.descriptor().fields() {
+            let path = format!("{prefix}{}", field.name());
+            let rules = self.rules.get(field.full_name());
+            let value = message.get_field(&field);
+
+            if field.is_map() {
+                continue;
+            }
+            if let Value::List(items) = value.as_ref() {
+                if let Some(rules) = rules {
+                    let count = items.len() as u64;
+                    if let Some(min) = rules.min_items
+                        && count < min
+                    {
+                        out.push(FieldViolation::new(
+                            &path,
+                            &format!("must have at least {min} items"),
+                        ));
+                    }
+                    if let Some(max) = rules.max_items
+                        && count > max
+                    {
+                        out.push(FieldViolation::new(
+                            &path,
+                            &format!("must have at most {max} items"),
+                        ));
+                    }
+                }
+                for (i, item) in items.iter().enumerate() {
+                    self.check_value(&field, item, rules, &format!("{path}[{i}]"), out);
+                }
+                continue;
+            }
+
+            if let Kind::Message(_) = field.kind()
+                && !message.has_field(&field)
+            {
+                if rules.is_some_and(|r| r.required) {
+                    out.push(FieldViolation::new(&path, "is required"));
+                }
+                continue;
+            }
+            self.check_value(&field, &value, rules, &path, out);
+        }
+    }
+
+    fn check_value(
+        &self,
+        field: &FieldDescriptor,
+        value: &Value,
+        rules: Option<&FieldRules>,
+        path: &str,
+        out: &mut Vec<FieldViolation>,
+    ) {
+        if let Value::Message(nested) = value {
+            self.check_message(nested, &format!("{path}."), out);
+            return;
+        }
+        let Some(rules) = rules else {
+            return;
+        };
+        let mut violation = |description: String| out.push(FieldViolation::new(path, &description));
+
+        if let Value::String(s) = value {
+            let len = s.chars().count() as u64;
+            if let Some(min) = rules.min_len
+                && len < min
+            {
+                violation(if min == 1 {
+                    "must not be empty".to_string()
+                } else {
+                    format!("must be at least {min} characters")
+                });
+            }
+            if let Some(max) = rules.max_len
+                && len > max
+            {
+                violation(format!("must be at most {max} characters"));
+            }
+            if let Some(pattern) = &rules.pattern
+                && !pattern.is_match(s)
+            {
+                violation(format!("must match {}", pattern.as_str()));
+            }
+            if rules.email && !email_regex().is_match(s) {
+                violation("must be a valid email address".to_string());
+            }
+        }
+
+        if let Some(n) = number(value) {
+            if let Some(gt) = rules.gt
+                && n <= gt
+            {
+                violation(format!("must be greater than {gt}"));
+            }
+            if let Some(gte) = rules.gte
+                && n < gte
+            {
+                violation(format!("must be at least {gte}"));
+            }
+            if let Some(lt) = rules.lt
+                && n >= lt
+            {
+                violation(format!("must be less than {lt}"));
+            }
+            if let Some(lte) = rules.lte
+                && n > lte
+            {
+                violation(format!("must be at most {lte}"));
+            }
+        }
+
+        if let Value::EnumNumber(n) = value
+            && rules.defined_only
+            && field
+                .kind()
+                .as_enum()
+                .is_some_and(|e| e.get_value(*n).is_none())
+        {
+            violation("must be a defined value".to_string());
+        }
+
+        if !rules.one_of.is_empty() {
+            let text = match value {
+                Value::String(s) => Some(s.clone()),
+                Value::EnumNumber(n) => field
+                    .kind()
+                    .as_enum()
+                    .and_then(|e| e.get_value(*n))
+                    .map(|v| v.name().to_string()),
+                other => number(other).map(|n| n.to_string()),
+            };
+            if text.is_some_and(|t| !rules.one_of.contains(&t)) {
+                violation(format!("must be one of {}", rules.one_of.join(", ")));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{CreateOrderRequest, GetOrdersResponse, Order};
+
+    fn order(user_id: &str, product: &str, quantity: i32) -> CreateOrderRequest {
+        CreateOrderRequest {
+            user_id: user_id.to_string(),
+            product: product.to_string(),
+            quantity,
+        }
+    }
+
+    /// `(field, description)` of every violation, empty when the message is valid
+    fn violations<M: prost::Message + prost::Name>(
+        validator: &Validator,
+        message: &M,
+    ) -> Vec<(String, String)> {
+        match validator.validate(message) {
+            Ok(()) => Vec::new(),
+            Err(AppError::Validation(violations)) => violations
+                .into_iter()
+                .map(|v| (v.field, v.description))
+                .collect(),
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+
+    fn no_rules() -> FieldRules {
+        FieldRules {
+            min_len: None,
+            max_len: None,
+            pattern: None,
+            email: false,
+            gt: None,
+            gte: None,
+            lt: None,
+            lte: None,
+            one_of: Vec::new(),
+            defined_only: false,
+            required: false,
+            min_items: None,
+            max_items: None,
+        }
+    }
+
+    /// A validator with only the given rules instead of the ones from the protos
+    fn validator(rules: Vec<(&str, FieldRules)>) -> Validator {
+        Validator {
+            pool: DescriptorPool::decode(FILE_DESCRIPTOR_SET).unwrap(),
+            streamed: HashSet::new(),
+            rules: rules
+                .into_iter()
+                .map(|(field, rules)| (field.to_string(), rules))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reads_rules_from_protos() {
+        let validator = Validator::new().unwrap();
+        assert!(violations(&validator, &order("u1", "Book", 1)).is_empty());
+        assert_eq!(
+            violations(&validator, &order("", "Book", 10_000)),
+            vec![("user_id".to_string(), "must not be empty".to_string())]
+        );
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let validator = Validator::new().unwrap();
+        let fields: Vec<String> = violations(&validator, &order("u 1", "", 0))
+            .into_iter()
+            .map(|(field, _)| field)
+            .collect();
+        assert_eq!(fields, ["user_id", "product", "quantity"]);
+    }
+
+    #[test]
+    fn checks_string_length_in_characters() {
+        let validator = Validator::new().unwrap();
+        assert!(violations(&validator, &order("u1", &"é".repeat(200), 1)).is_empty());
+        assert_eq!(
+            violations(&validator, &order("u1", &"é".repeat(201), 1)),
+            vec![(
+                "product".to_string(),
+                "must be at most 200 characters".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn checks_numeric_bounds() {
+        let validator = Validator::new().unwrap();
+        let quantity = |quantity| violations(&validator, &order("u1", "Book", quantity));
+        assert_eq!(quantity(0)[0].1, "must be greater than 0");
+        assert_eq!(quantity(10_001)[0].1, "must be at most 10000");
+        assert!(quantity(10_000).is_empty());
+    }
+
+    #[test]
+    fn checks_email_and_allowed_values() {
+        let validator = validator(vec![
+            (
+                "order.CreateOrderRequest.user_id",
+                FieldRules {
+                    email: true,
+                    ..no_rules()
+                },
+            ),
+            (
+                "order.CreateOrderRequest.product",
+                FieldRules {
+                    one_of: vec!["Book".to_string(), "Pen".to_string()],
+                    ..no_rules()
+                },
+            ),
+        ]);
+        assert!(violations(&validator, &order("ann@example.com", "Pen", 1)).is_empty());
+        assert_eq!(
+            violations(&validator, &order("ann@example", "Ink", 1)),
+            vec![
+                (
+                    "user_id".to_string(),
+                    "must be a valid email address".to_string()
+                ),
+                (
+                    "product".to_string(),
+                    "must be one of Book, Pen".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_items_and_nested_messages() {
+        let validator = validator(vec![
+            (
+                "order.GetOrdersResponse.orders",
+                FieldRules {
+                    max_items: Some(2),
+                    ..no_rules()
+                },
+            ),
+            (
+                "order.Order.quantity",
+                FieldRules {
+                    gt: Some(0.0),
+                    ..no_rules()
+                },
+            ),
+        ]);
+        let item = |quantity| Order {
+            quantity,
+            ..Order::default()
+        };
+        let response = GetOrdersResponse {
+            orders: vec![item(1), item(0), item(3)],
+        };
+        assert_eq!(
+            violations(&validator, &response),
+            vec![
+                (
+                    "orders".to_string(),
+                    "must have at most 2 items".to_string()
+                ),
+                (
+                    "orders[1].quantity".to_string(),
+                    "must be greater than 0".to_string()
+                ),
+            ]
+        );
+    }
+}